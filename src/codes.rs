@@ -3,8 +3,11 @@ use core::option::Option::{None, Some};
 use core::result::Result::{Err, Ok};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
 
-use crate::tables::{as_register, InstrCode};
+use crate::diagnostic::Diagnostic;
+use crate::expr::{Expr, Part};
+use crate::tables::{as_register, register, InstrCode, RegClass};
 use crate::{rem_spaces, Encoding};
 
 pub enum Syntax {
@@ -16,6 +19,7 @@ pub enum Syntax {
     MoveFrom,
     MoveTo,
     ArithLogI,
+    LogicI,
     LoadI,
     Branch,
     BranchZ,
@@ -26,39 +30,144 @@ pub enum Syntax {
     S2ArithLog,
     RegImmBranch,
     CoProc1Move,
+    CoProc0Move,
     Break,
     AtomicLoadStore,
-    Pseudo(Box<fn(Args<Arg>) -> Encoding>),
+    ExcReturn,
+    // Coprocessor 1, in the format of the last suffix of the name
+    FpArith,
+    FpUnary,
+    FpCompare,
+    FpBranch,
+    FpMoveCond,
+    FpMoveZero,
+    MoveCond,
+    // MIPS32 Release 2
+    BitField,
+    ByteSwap,
+    Rotate,
+    RotateV,
+    CountBits,
+    MulAcc,
+    TrapReg,
+    TrapImm,
+    Sync,
+    // MIPS32 Release 6
+    MulDivR6,
+    CountBitsR6,
+    AtomicR6,
+    CompactJump,
+    CompactBranchZ,
+    FpCompareR6,
+    FpBranchR6,
+}
+
+impl Syntax {
+    /// Example operands for this format, written the same way as the examples in PseudoOps.txt.
+    pub fn operands(&self) -> &'static str {
+        match self {
+            Syntax::ArithLog | Syntax::ShiftV | Syntax::S2ArithLog => "$t1,$t2,$t3",
+            Syntax::DivMult => "$t1,$t2",
+            Syntax::CoProc1Move => "$t1,$f1",
+            Syntax::Shift => "$t1,$t2,10",
+            Syntax::JumpR | Syntax::MoveFrom | Syntax::MoveTo => "$t1",
+            Syntax::ArithLogI => "$t1,$t2,-100",
+            Syntax::LogicI => "$t1,$t2,100",
+            Syntax::LoadI => "$t1,100",
+            Syntax::Branch => "$t1,$t2,label",
+            Syntax::BranchZ | Syntax::RegImmBranch => "$t1,label",
+            Syntax::LoadStore | Syntax::AtomicLoadStore => "$t1,-100($t2)",
+            Syntax::Jump => "label",
+            Syntax::Trap => "100000",
+            Syntax::Syscall | Syntax::Break | Syntax::ExcReturn => "",
+            Syntax::CoProc0Move => "$t1,$8",
+            Syntax::FpArith => "$f0,$f2,$f4",
+            Syntax::FpUnary | Syntax::FpCompare => "$f0,$f2",
+            Syntax::FpBranch => "label",
+            Syntax::FpMoveCond => "$f0,$f2,1",
+            Syntax::FpMoveZero => "$f0,$f2,$t1",
+            Syntax::MoveCond => "$t1,$t2,1",
+            Syntax::BitField => "$t1,$t2,10,5",
+            Syntax::ByteSwap | Syntax::CountBits | Syntax::MulAcc | Syntax::TrapReg => "$t1,$t2",
+            Syntax::Rotate => "$t1,$t2,10",
+            Syntax::RotateV => "$t1,$t2,$t3",
+            Syntax::TrapImm => "$t1,-100",
+            Syntax::Sync => "",
+            Syntax::MulDivR6 => "$t1,$t2,$t3",
+            Syntax::CountBitsR6 => "$t1,$t2",
+            Syntax::AtomicR6 => "$t1,-100($t2)",
+            Syntax::CompactJump => "label",
+            Syntax::CompactBranchZ => "$t1,label",
+            Syntax::FpCompareR6 => "$f0,$f2,$f4",
+            Syntax::FpBranchR6 => "$f1,label",
+        }
+    }
+}
+
+/// Kinds of token in an example statement. Immediates are classed by the range of the example value.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OpKind {
+    Reg(RegClass),
+    Imm5,
+    Imm16,
+    Imm16U,
+    Imm32,
+    Label,
+    LParen,
+    RParen,
+    Plus,
 }
 
 #[derive(Clone)]
 pub enum Args<T> {
+    Four(T, T, T, T),
     Three(T, T, T),
     Two(T, T),
     One(T),
     None,
 }
 
+impl<T> Args<T> {
+    pub fn items(&self) -> Vec<&T> {
+        match self {
+            Args::Four(a, b, c, d) => vec![a, b, c, d],
+            Args::Three(a, b, c) => vec![a, b, c],
+            Args::Two(a, b) => vec![a, b],
+            Args::One(a) => vec![a],
+            Args::None => vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Args::Four(..) => 4,
+            Args::Three(..) => 3,
+            Args::Two(..) => 2,
+            Args::One(_) => 1,
+            Args::None => 0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Arg {
     Reg(i8),
     Imm(i32),
-    Label(String),
+    /// A label, or an expression using labels or `.`, worked out in pass2.
+    Expr(String),
 }
 
 trait Binary {
-    fn to_bin(&self, lbl_adr: &HashMap<String, u32>) -> Option<u32>;
+    fn to_bin(&self, lbl_adr: &HashMap<String, u32>, adr: u32) -> Result<u32, String>;
 }
 
 impl Binary for Arg {
-    fn to_bin(&self, lbl_adr: &HashMap<String, u32>) -> Option<u32> {
+    /// The value of the operand in an instruction at `adr`.
+    fn to_bin(&self, lbl_adr: &HashMap<String, u32>, adr: u32) -> Result<u32, String> {
         match self {
-            Arg::Reg(r) => Some(*r as u32),
-            Arg::Imm(n) => Some(*n as u32),
-            Arg::Label(l) => match lbl_adr.get(l) {
-                None => None,
-                Some(i) => Some(*i),
-            },
+            Arg::Reg(r) => Ok(*r as u32),
+            Arg::Imm(n) => Ok(*n as u32),
+            Arg::Expr(e) => Expr::parse(e)?.value(lbl_adr, adr),
         }
     }
 }
@@ -67,13 +176,13 @@ impl Display for Arg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Arg::Reg(r) => {
-                write!(f, "Reg{}", r)
+                write!(f, "${}", r)
             }
             Arg::Imm(i) => {
                 write!(f, "{}", i)
             }
-            Arg::Label(l) => {
-                write!(f, "{}", l)
+            Arg::Expr(e) => {
+                write!(f, "{}", e)
             }
         }
     }
@@ -82,6 +191,9 @@ impl Display for Arg {
 impl Display for Args<String> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            Args::Four(a, b, c, d) => {
+                write!(f, "{0}, {1}, {2}, {3}", a, b, c, d)
+            }
             Args::Three(a, b, c) => {
                 write!(f, "{0}, {1}, {2}", a, b, c)
             }
@@ -96,31 +208,188 @@ impl Display for Args<String> {
     }
 }
 
-fn parse_num<S: Into<String>>(arg_i: S) -> Result<i32, String> {
+/// Returns the byte offset of the first `c` in `s` that is not inside a string or char literal.
+pub fn find_unquoted(s: &str, c: char) -> Option<usize> {
+    find_unquoted_by(s, |ch| ch == c)
+}
+
+fn find_unquoted_by(s: &str, stop: impl Fn(char) -> bool) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, ch) in s.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' {
+                    escaped = true;
+                } else if ch == q {
+                    quote = None;
+                }
+            }
+            None if stop(ch) => return Some(i),
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Splits a list of values on commas and whitespace, keeping quoted literals and expressions
+/// such as `(a + 4) * 2` whole: spaces next to an operator or inside parentheses don't split.
+pub fn split_items(s: &str) -> Vec<&str> {
+    let binary = |c: Option<char>| c.is_some_and(|c| "+-*/%<>&|^:".contains(c));
+    let mut items = Vec::new();
+    let (mut depth, mut start, mut at) = (0, 0, 0);
+    while let Some(n) = find_unquoted_by(&s[at..], |c| {
+        matches!(c, '(' | ')' | ',') || c.is_whitespace()
+    }) {
+        let i = at + n;
+        let c = s[i..].chars().next().unwrap_or(' ');
+        at = i + c.len_utf8();
+        let item = s[start..i].trim();
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if depth > 0 => {}
+            ',' => {
+                items.push(item);
+                start = at;
+            }
+            _ if item.is_empty()
+                || binary(item.chars().last())
+                || item.ends_with('~')
+                || binary(s[at..].trim_start().chars().next()) => {}
+            _ => {
+                items.push(item);
+                start = at;
+            }
+        }
+    }
+    items.push(s[start..].trim());
+    items.retain(|i| !i.is_empty());
+    items
+}
+
+/// Removes a `#` comment from a line, leaving any `#` inside quotes alone.
+pub fn strip_comment(line: &str) -> &str {
+    match find_unquoted(line, '#') {
+        Some(n) => &line[..n],
+        None => line,
+    }
+}
+
+/// Splits the label off a line without its comment: a label is a single word before the first
+/// `:` outside quotes, so `.word 5:3` and `.asciiz "a:b"` have none.
+pub fn split_label(code: &str) -> (Option<&str>, &str) {
+    match find_unquoted(code, ':') {
+        Some(n) if !code[..n].trim().contains(char::is_whitespace) => {
+            (Some(code[..n].trim()), &code[n + 1..])
+        }
+        _ => (None, code),
+    }
+}
+
+/// Reads one character of a string or char literal, which may be an escape such as `\n`, `\101`
+/// or `\x41`. Returns the bytes it stands for and the rest of `s`.
+fn read_char(s: &str) -> Result<(Vec<u8>, &str), String> {
+    let mut chars = s.chars();
+    let c = match chars.next() {
+        Some(c) => c,
+        None => return Err("Missing closing quote".into()),
+    };
+    if c != '\\' {
+        return Ok((c.to_string().into_bytes(), chars.as_str()));
+    }
+    let rest = chars.as_str();
+    let e = match rest.chars().next() {
+        Some(e) => e,
+        None => return Err("Missing closing quote".into()),
+    };
+    let simple = match e {
+        'n' => Some(b'\n'),
+        't' => Some(b'\t'),
+        'r' => Some(b'\r'),
+        'a' => Some(7),
+        'b' => Some(8),
+        'f' => Some(12),
+        'v' => Some(11),
+        '\\' | '"' | '\'' => Some(e as u8),
+        _ => None,
+    };
+    if let Some(b) = simple {
+        return Ok((vec![b], &rest[1..]));
+    }
+    // Octal takes up to three digits, hex up to two
+    let (digits, radix, skip) = if e == 'x' {
+        (&rest[1..], 16, 1)
+    } else {
+        (rest, 8, 0)
+    };
+    let max = if radix == 16 { 2 } else { 3 };
+    let len = digits
+        .chars()
+        .take(max)
+        .take_while(|d| d.is_digit(radix))
+        .count();
+    if len == 0 {
+        return Err(format!("Unknown escape sequence \"\\{}\"", e));
+    }
+    match u8::from_str_radix(&digits[..len], radix) {
+        Ok(b) => Ok((vec![b], &rest[skip + len..])),
+        Err(_) => Err(format!(
+            "Escape sequence \"\\{}\" is out of range",
+            &rest[..skip + len]
+        )),
+    }
+}
+
+/// Parses the string literal at the start of `s`. Returns its bytes and whatever follows the
+/// closing quote.
+pub fn parse_string(s: &str) -> Result<(Vec<u8>, &str), String> {
+    let mut rest = match s.strip_prefix('"') {
+        Some(r) => r,
+        None => return Err(format!("Expected a string but found \"{}\"", s)),
+    };
+    let mut bytes = Vec::new();
+    loop {
+        if let Some(r) = rest.strip_prefix('"') {
+            return Ok((bytes, r));
+        }
+        let (b, r) = read_char(rest)?;
+        bytes.extend(b);
+        rest = r;
+    }
+}
+
+/// Parses a char literal such as `'a'` or `'\n'` into its value.
+fn parse_char(s: &str) -> Result<i32, String> {
+    let inner = &s[1..];
+    let (bytes, rest) = read_char(inner)?;
+    if rest != "'" || inner.starts_with('\'') {
+        return Err(format!("Invalid character literal {}", s));
+    }
+    Ok(match std::str::from_utf8(&bytes) {
+        Ok(c) if bytes.len() > 1 => c.chars().next().unwrap() as i32,
+        _ => bytes[0] as i32,
+    })
+}
+
+pub fn parse_num<S: Into<String>>(arg_i: S) -> Result<i32, String> {
     let arg = arg_i.into();
+    if arg.starts_with('\'') {
+        return parse_char(&arg);
+    }
     if arg.len() < 2 {
         return match arg.parse() {
-            Ok(n) => (Ok(n)),
-            Err(e) => (Err(e.to_string())),
+            Ok(n) => Ok(n),
+            Err(e) => Err(e.to_string()),
         };
     }
     let pref = &arg[..2];
     if pref == "0x" {
-        let res = hex::decode(&arg[2..]);
-        return match res {
-            Ok(v) => {
-                let mut res: i32 = 0;
-                let mut i = 0;
-                while i < v.len() {
-                    res <<= 8;
-                    res += v[i] as i32;
-                    i += 1;
-                    if i == 4 {
-                        break;
-                    }
-                }
-                Ok(res)
-            }
+        return match u32::from_str_radix(&arg[2..], 16) {
+            Ok(n) => Ok(n as i32),
             Err(e) => Err(e.to_string()),
         };
     }
@@ -130,14 +399,226 @@ fn parse_num<S: Into<String>>(arg_i: S) -> Result<i32, String> {
             Err(e) => Err(e.to_string()),
         };
     }
-    return match arg.parse() {
-        Ok(n) => (Ok(n)),
-        Err(e) => (Err(e.to_string())),
+    match arg.parse() {
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Splits the operands of a statement on the commas between them, leaving those inside
+/// parentheses or quotes alone.
+pub(crate) fn split_operands(s: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    let mut rest = s;
+    while let Some(n) = find_unquoted_by(rest, |c| matches!(c, '(' | ')' | ',')) {
+        let at = s.len() - rest.len() + n;
+        match &s[at..at + 1] {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ if depth == 0 => {
+                operands.push(&s[start..at]);
+                start = at + 1;
+            }
+            _ => {}
+        }
+        rest = &s[at + 1..];
+    }
+    operands.push(&s[start..]);
+    operands
+}
+
+/// Where the base register of a memory operand such as `8($sp)` or `%lo(x)($t0)` opens.
+fn base_register(operand: &str) -> Option<usize> {
+    let inner = operand.strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    as_register(inner[open + 1..].trim()).ok().map(|_| open)
+}
+
+/// Whether `tok` is an expression: anything [`Expr`] can parse that is not a register or a
+/// plain number.
+pub(crate) fn is_expr(tok: &str) -> bool {
+    as_register(tok).is_err() && parse_num(tok).is_err() && Expr::parse(tok).is_ok()
+}
+
+/// Whether `tok` is `%hi(...)`, `%lo(...)` or `%gp_rel(...)`, which always fit in 16 bits.
+fn is_half(tok: &str) -> bool {
+    matches!(Expr::parse(tok), Ok(Expr::Part(..)))
+}
+
+/// Works out an operand that is a constant expression, such as `(1<<12)|3`. Anything else,
+/// including a value that does not fit in 32 bits, is returned as is, to be worked out (or
+/// refused) in pass2.
+pub(crate) fn fold(value: &str) -> String {
+    match Expr::parse(value) {
+        Ok(e) if e.is_constant() && parse_num(value).is_err() => {
+            match e.value(&HashMap::new(), 0) {
+                Ok(v) => (v as i32).to_string(),
+                Err(_) => value.to_string(),
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Splits a statement into tokens the way PseudoOps.txt counts them: the operator is token 0,
+/// then each operand is a token, except that the base register of a memory operand is a token
+/// of its own, between `(` and `)` tokens. Constant expressions are worked out, so the
+/// operand's value decides which form of the instruction it fits.
+pub fn tokenize(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let (op, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+    let mut toks = vec![op.to_string()];
+    let value = |v: &str, toks: &mut Vec<String>| {
+        let v = v.trim();
+        if v.is_empty() {
+            return;
+        }
+        if is_expr(v) || as_register(v).is_ok() || parse_num(v).is_ok() {
+            toks.push(fold(v));
+        } else {
+            // Operands separated by spaces only
+            toks.extend(example_tokens(v));
+        }
     };
+    if rest.trim().is_empty() {
+        return toks;
+    }
+    for operand in split_operands(rest) {
+        let operand = operand.trim();
+        match base_register(operand) {
+            Some(open) => {
+                value(&operand[..open], &mut toks);
+                toks.push("(".into());
+                toks.push(operand[open + 1..operand.len() - 1].trim().to_string());
+                toks.push(")".into());
+            }
+            None => value(operand, &mut toks),
+        }
+    }
+    toks
+}
+
+/// Splits an example statement of PseudoOps.txt into tokens: commas and whitespace separate
+/// tokens, and parentheses and `+` are tokens of their own.
+fn example_tokens(line: &str) -> Vec<String> {
+    let mut toks = Vec::new();
+    let mut cur = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in line.chars() {
+        // Quoted characters such as ',' or ' ' stay inside their token
+        if let Some(q) = quote {
+            cur.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+            cur.push(c);
+        } else if c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == '+' {
+            if !cur.is_empty() {
+                toks.push(cur);
+                cur = String::new();
+            }
+            if c == '(' || c == ')' || c == '+' {
+                toks.push(c.to_string());
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if !cur.is_empty() {
+        toks.push(cur);
+    }
+    toks
+}
+
+/// Reads the operand kinds out of example operands such as `$t1,-100($t2)`.
+pub fn get_pattern(example: &str) -> Vec<OpKind> {
+    example_tokens(example)
+        .iter()
+        .map(|tok| match tok.as_str() {
+            "(" => OpKind::LParen,
+            ")" => OpKind::RParen,
+            "+" => OpKind::Plus,
+            // FPU registers are written $fN, coprocessor 0 ones by number
+            _ if tok.starts_with("$f") => OpKind::Reg(RegClass::Fpr),
+            _ if register(tok).is_some_and(|r| r.1.contains(&RegClass::Cp0)) => {
+                OpKind::Reg(RegClass::Cp0)
+            }
+            _ if tok.starts_with('$') => OpKind::Reg(RegClass::Gpr),
+            _ => match parse_num(tok) {
+                Ok(n) if n < 0 => OpKind::Imm16,
+                Ok(n) if n < 32 => OpKind::Imm5,
+                Ok(n) if n <= 0xffff => OpKind::Imm16U,
+                Ok(_) => OpKind::Imm32,
+                Err(_) => OpKind::Label,
+            },
+        })
+        .collect()
+}
+
+/// Whether `tok` can name a label.
+pub fn is_label_name(tok: &str) -> bool {
+    as_register(tok).is_err()
+        && parse_num(tok).is_err()
+        && tok != "."
+        && tok.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.')
+        && tok
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+impl OpKind {
+    /// Whether `tok` can stand where the example has this kind of token. Expressions that
+    /// depend on labels could have any value, so they only fit 32-bit immediates and labels,
+    /// except for `%hi` and friends, which fit 16 bits.
+    pub fn accepts(&self, tok: &str) -> bool {
+        match self {
+            OpKind::Reg(class) => register(tok).is_some_and(|r| r.1.contains(class)),
+            OpKind::Imm5 => matches!(parse_num(tok), Ok(0..=31)),
+            OpKind::Imm16 => matches!(parse_num(tok), Ok(-32768..=32767)) || is_half(tok),
+            OpKind::Imm16U => matches!(parse_num(tok), Ok(0..=65535)) || is_half(tok),
+            OpKind::Imm32 => parse_num(tok).is_ok() || is_expr(tok),
+            OpKind::Label => is_expr(tok) && !is_half(tok),
+            OpKind::LParen => tok == "(",
+            OpKind::RParen => tok == ")",
+            OpKind::Plus => tok == "+",
+        }
+    }
+}
+
+/// Checks the operand tokens of a statement (everything after the operator) against a pattern.
+pub fn fits(pattern: &[OpKind], toks: &[String]) -> bool {
+    pattern.len() == toks.len() && pattern.iter().zip(toks).all(|(k, t)| k.accepts(t))
+}
+
+/// The first register in `toks` that is of a different class than `pattern` has there, with the
+/// class wanted, when every other token fits.
+pub fn wrong_class<'t>(pattern: &[OpKind], toks: &'t [String]) -> Option<(&'t String, RegClass)> {
+    if pattern.len() != toks.len() {
+        return None;
+    }
+    let mut wrong = None;
+    for (kind, tok) in pattern.iter().zip(toks) {
+        if kind.accepts(tok) {
+            continue;
+        }
+        match kind {
+            OpKind::Reg(class) if register(tok).is_some() => wrong = wrong.or(Some((tok, *class))),
+            _ => return None,
+        }
+    }
+    wrong
 }
 
 pub fn get_argument<S: Into<String>>(arg_s: S) -> Arg {
-    let arg_str = rem_spaces(arg_s);
+    let arg_str = fold(&rem_spaces(arg_s));
 
     let reg_test = as_register(&arg_str);
     match reg_test {
@@ -146,709 +627,716 @@ pub fn get_argument<S: Into<String>>(arg_s: S) -> Arg {
             let num_test = parse_num(&arg_str);
             match num_test {
                 Ok(n) => Arg::Imm(n),
-                Err(_) => Arg::Label(arg_str.into()),
+                Err(_) => Arg::Expr(arg_str),
             }
         }
     }
 }
 
-pub fn get_arguments<S: Into<String>>(arg_line_s: S) -> Args<Arg> {
+pub fn get_arguments<S: Into<String>>(arg_line_s: S) -> Result<Args<Arg>, String> {
     let arg_line = rem_spaces(arg_line_s);
-    if arg_line.len() == 0 {
-        return Args::None;
+    if arg_line.is_empty() {
+        return Ok(Args::None);
     }
-    let is_store: Option<usize> = arg_line.find("(");
-
-    match is_store {
-        None => {
-            let (p1, arg1) = match arg_line.find(",") {
-                None => {
-                    return Args::One(get_argument(arg_line));
-                }
-                Some(n) => (n, get_argument(&arg_line[..n])),
-            };
-
-            let argl2 = &arg_line[p1 + (1 as usize)..];
-            let p2: Option<usize> = argl2.find(",");
-            return match p2 {
-                None => Args::Two(arg1, get_argument(argl2)),
-                Some(n) => Args::Three(
-                    arg1,
-                    get_argument(&argl2[..n]),
-                    get_argument(&argl2[n + (1 as usize)..]),
-                ),
-            };
+    let mut args = Vec::new();
+    for operand in split_operands(&arg_line) {
+        let operand = operand.trim();
+        let opens = operand.matches('(').count();
+        if opens > operand.matches(')').count() && find_unquoted(operand, '(').is_some() {
+            return Err(format!("Missing ending parenthesis in {}.", arg_line));
         }
-        Some(p2) => {
-            let p3 = match arg_line.find(")") {
-                Some(n) => n,
-                None => {
-                    println!("Missing ending parenthesis in {}.", arg_line);
-                    arg_line.len()
+        match base_register(operand) {
+            Some(open) => {
+                if !operand[..open].trim().is_empty() {
+                    args.push(get_argument(&operand[..open]));
                 }
-            };
+                args.push(get_argument(&operand[open + 1..operand.len() - 1]));
+            }
+            None => args.push(get_argument(operand)),
+        }
+    }
 
-            let p1: Option<usize> = arg_line.find(",");
-            return match p1 {
-                None => Args::Two(
-                    get_argument(&arg_line[..p2]),
-                    get_argument(&arg_line[p2 + (1 as usize)..p3]),
-                ),
-                Some(n) => Args::Three(
-                    get_argument(&arg_line[..n]),
-                    get_argument(&arg_line[n + (1 as usize)..p2]),
-                    get_argument(&arg_line[p2 + (1 as usize)..p3]),
-                ),
-            };
+    let mut args = args.into_iter();
+    Ok(match (args.next(), args.next(), args.next(), args.next()) {
+        (None, ..) => Args::None,
+        (Some(a), None, ..) => Args::One(a),
+        (Some(a), Some(b), None, _) => Args::Two(a, b),
+        (Some(a), Some(b), Some(c), None) => Args::Three(a, b, c),
+        (Some(a), Some(b), Some(c), Some(d)) => {
+            if args.next().is_some() {
+                return Err(format!("Too many operands in {}.", arg_line));
+            }
+            Args::Four(a, b, c, d)
         }
-    };
+    })
 }
 
+/// Encodes one basic instruction at address `adr`. `src` is the source line, used to point
+/// diagnostics at the operand at fault.
 pub fn get_enc(
     instr: &InstrCode,
     args: Args<Arg>,
     lbl_adr: &HashMap<String, u32>,
     line: u32,
     adr: u32,
-) -> Encoding {
-    match &instr.syntax {
+    src: &str,
+) -> Result<Encoding, Diagnostic> {
+    let err = |msg: String, a: &Arg| Diagnostic::error(line, msg).at(src, &a.to_string());
+    let reg = |a: &Arg| match a {
+        Arg::Reg(r) => Ok(*r as u32),
+        Arg::Imm(0..=31) => Err(err(
+            format!("{0} needs a register here, not \"{1}\".", instr.name, a),
+            a,
+        )),
+        Arg::Imm(_) => Err(err(
+            format!(
+                "Register \"{0}\" out of range in {1}, expected 0 to 31.",
+                a, instr.name
+            ),
+            a,
+        )),
+        Arg::Expr(_) => Err(err(
+            format!("Register \"{0}\" not found in {1}.", a, instr.name),
+            a,
+        )),
+    };
+    // Expressions say what went wrong with them, such as a label that is not defined
+    let imm = |a: &Arg, what: &str| {
+        a.to_bin(lbl_adr, adr).map_err(|e| match a {
+            Arg::Expr(_) => err(format!("{0} in {1}.", e, instr.name), a),
+            _ => err(
+                format!("{0} \"{1}\" not valid in {2}.", what, a, instr.name),
+                a,
+            ),
+        })
+    };
+    let lbl = |a: &Arg| {
+        a.to_bin(lbl_adr, adr)
+            .map_err(|e| err(format!("{0} in {1}.", e, instr.name), a))
+    };
+    // The value of `a` if it fits in `range`. Otherwise the diagnostic gives the operand, its
+    // value if it is an expression, and the range.
+    let ranged = |a: &Arg, what: &str, range: RangeInclusive<i64>| {
+        let v = imm(a, what)?;
+        // %hi and %lo are halves of an address, which fit either way
+        let half = match a {
+            Arg::Expr(e) => matches!(Expr::parse(e), Ok(Expr::Part(Part::Hi | Part::Lo, _))),
+            _ => false,
+        };
+        if half {
+            return Ok(v & 0xffff);
+        }
+        let signed = v as i32 as i64;
+        if range.contains(&signed) {
+            return Ok(v);
+        }
+        let value = match a {
+            Arg::Expr(_) => format!(" ({})", signed),
+            _ => String::new(),
+        };
+        Err(err(
+            format!(
+                "{0} \"{1}\"{2} not valid in {3}, expected {4} to {5}.",
+                what,
+                a,
+                value,
+                instr.name,
+                range.start(),
+                range.end()
+            ),
+            a,
+        ))
+    };
+    let simm16 = |a: &Arg, what: &str| ranged(a, what, -0x8000..=0x7fff).map(|v| v as i16);
+    let uimm16 = |a: &Arg, what: &str| ranged(a, what, 0..=0xffff).map(|v| v as i16);
+    // The number of words from the delay slot to the target of a branch, which must fit in
+    // `bits` bits. Offsets count from there whether or not branches are delayed.
+    let offset = |a: &Arg, bits: u32| {
+        let target = lbl(a)?;
+        if target % 4 != 0 {
+            return Err(err(
+                format!(
+                    "Branch target {0:#010x} not valid in {1}, it must be a multiple of 4.",
+                    target, instr.name
+                ),
+                a,
+            ));
+        }
+        let words = (target as i64 - adr as i64 - 4) >> 2;
+        let limit = 1i64 << (bits - 1);
+        if words < -limit || words >= limit {
+            return Err(err(
+                format!(
+                    "Branch offset {0} to {1:#010x} not valid in {2}, expected -{3} to {4} words.",
+                    words,
+                    target,
+                    instr.name,
+                    limit,
+                    limit - 1
+                ),
+                a,
+            ));
+        }
+        Ok(words as i32)
+    };
+    // Doubles live in an even-odd register pair, named by the even one
+    let pair = |a: &Arg, double: bool| {
+        let r = reg(a)?;
+        if double && r % 2 != 0 {
+            // The operand displays as `$1`, but the source has it as `$f1`
+            let name = format!("$f{}", r);
+            return Err(Diagnostic::error(
+                line,
+                format!(
+                    "{0} needs an even register for a double, not \"{1}\".",
+                    instr.name, name
+                ),
+            )
+            .at(src, &name));
+        }
+        Ok(r)
+    };
+    let cc = |a: &Arg| match a.to_bin(lbl_adr, adr) {
+        Ok(n @ 0..=7) if matches!(a, Arg::Imm(_)) => Ok(n),
+        _ => Err(err(
+            format!(
+                "Condition flag \"{0}\" not valid in {1}, expected 0 to 7.",
+                a, instr.name
+            ),
+            a,
+        )),
+    };
+    // For cvt and the rounding instructions the destination format comes first
+    let parts: Vec<&str> = instr.name.split('.').collect();
+    let src_double = parts.last() == Some(&"d");
+    let dst_double = if parts.len() == 3 {
+        parts[1] == "d"
+    } else {
+        src_double
+    };
+    let fmt = instr.fmt() as i8;
+    let found = args.len();
+    let count = |n: usize| {
+        Diagnostic::error(
+            line,
+            format!(
+                "{0} takes {1} arguments but {2} were given.",
+                instr.name, n, found
+            ),
+        )
+        .at(src, instr.name)
+    };
+
+    Ok(match &instr.syntax {
         Syntax::ArithLog => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let d = a1.to_bin(lbl_adr);
-            let s = a2.to_bin(lbl_adr);
-            let t = a3.to_bin(lbl_adr);
-
-            match d {
-                Some(dr) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            return Encoding::Register(
-                                0, sr as i8, tr as i8, dr as i8, 0, instr.code,
-                            );
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a3, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a2, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+            let t = reg(&a3)?;
 
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, s as i8, t as i8, d as i8, 0, instr.code)
         }
         Syntax::DivMult => {
             let (a1, a2) = match args {
                 Args::Two(a1, a2) => (a1, a2),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(2)),
             };
-            let s = a1.to_bin(lbl_adr);
-            let t = a2.to_bin(lbl_adr);
-
-            match s {
-                Some(sr) => match t {
-                    Some(tr) => {
-                        return Encoding::Register(0, sr as i8, tr as i8, 0, 0, instr.code);
-                    }
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a2, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
+            let s = reg(&a1)?;
+            let t = reg(&a2)?;
 
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, s as i8, t as i8, 0, 0, instr.code)
         }
         Syntax::Shift => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let d = a1.to_bin(lbl_adr);
-            let t = a2.to_bin(lbl_adr);
-            let a = a3.to_bin(lbl_adr);
-
-            match a {
-                Some(ar) => match t {
-                    Some(tr) => match d {
-                        Some(dr) => {
-                            return Encoding::Register(
-                                0, 0, tr as i8, dr as i8, ar as i8, instr.code,
-                            );
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a2, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Shift amount \"{0}\" not valid in {1} on line {2}.",
-                        a3, instr.name, line
-                    )
-                }
-            }
+            let d = reg(&a1)?;
+            let t = reg(&a2)?;
+            let a = ranged(&a3, "Shift amount", 0..=31)?;
 
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, 0, t as i8, d as i8, a as i8, instr.code)
         }
         Syntax::ShiftV => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let d = a1.to_bin(lbl_adr);
-            let t = a2.to_bin(lbl_adr);
-            let s = a3.to_bin(lbl_adr);
-
-            match d {
-                Some(dr) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            return Encoding::Register(
-                                0, sr as i8, tr as i8, dr as i8, 0, instr.code,
-                            );
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a2, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a3, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
+            let d = reg(&a1)?;
+            let t = reg(&a2)?;
+            let s = reg(&a3)?;
 
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, s as i8, t as i8, d as i8, 0, instr.code)
         }
         Syntax::JumpR | Syntax::MoveTo => {
             let a1 = match args {
                 Args::One(a1) => a1,
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!()
-                }
+                _ => return Err(count(1)),
             };
-            let s = a1.to_bin(lbl_adr);
+            let s = reg(&a1)?;
+            // jalr links through $ra
+            let d = if instr.name == "jalr" { 31 } else { 0 };
 
-            match s {
-                Some(sr) => {
-                    return Encoding::Register(0, sr as i8, 0, 0, 0, instr.code);
-                }
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
-
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, s as i8, 0, d, 0, instr.code)
         }
         Syntax::MoveFrom => {
             let a1 = match args {
-                Args::One(a1) => (a1),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                Args::One(a1) => a1,
+                _ => return Err(count(1)),
             };
-            let d = a1.to_bin(lbl_adr);
+            let d = reg(&a1)?;
 
-            match d {
-                Some(dr) => {
-                    return Encoding::Register(0, 0, 0, dr as i8, 0, instr.code);
-                }
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
-
-            Encoding::Register(0, 0, 0, 0, 0, instr.code)
+            Encoding::Register(0, 0, 0, d as i8, 0, instr.code)
         }
-        Syntax::ArithLogI => {
+        Syntax::ArithLogI | Syntax::LogicI => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
+            };
+            let t = reg(&a1)?;
+            let s = reg(&a2)?;
+            // The logical instructions zero-extend their immediate, the others sign-extend it
+            let i = match instr.syntax {
+                Syntax::LogicI => uimm16(&a3, "Immediate value")?,
+                _ => simm16(&a3, "Immediate value")?,
             };
-            let t = a1.to_bin(lbl_adr);
-            let s = a2.to_bin(lbl_adr);
-            let i = a3.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            return Encoding::Immediate(instr.code, sr as i8, tr as i8, ir as i16);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a2, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Immediate value \"{0}\" not valid in {1} on line {2}.",
-                        a3, instr.name, line
-                    )
-                }
-            }
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(instr.code, s as i8, t as i8, i)
         }
         Syntax::LoadI => {
             let (a1, a2) = match args {
                 Args::Two(a1, a2) => (a1, a2),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(2)),
             };
-            let t = a1.to_bin(lbl_adr);
-            let i = a2.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => match t {
-                    Some(tr) => {
-                        return Encoding::Immediate(instr.code, 0, tr as i8, ir as i16);
-                    }
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a1, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Immediate value \"{0}\" not valid in {1} on line {2}.",
-                        a2, instr.name, line
-                    )
-                }
-            }
+            let t = reg(&a1)?;
+            let i = uimm16(&a2, "Immediate value")?;
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(instr.code, 0, t as i8, i)
         }
         Syntax::Branch => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let s = a1.to_bin(lbl_adr);
-            let t = a2.to_bin(lbl_adr);
-            let i = a3.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => {
-                    match s {
-                        Some(sr) => {
-                            match t {
-                                Some(tr) => {
-                                    let i_m: i16 = (((ir as i32 - adr as i32) >> 2) - 1) as i16;
-                                    //println!("{}", i_m);
-                                    return Encoding::Immediate(
-                                        instr.code, sr as i8, tr as i8, i_m,
-                                    );
-                                }
-                                None => {
-                                    println!(
-                                        "Register \"{0}\" not found in {1} on line {2}.",
-                                        a2, instr.name, line
-                                    )
-                                }
-                            }
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    }
-                }
-                None => {
-                    println!(
-                        "Label \"{0}\" not found, in {1} on line {2}.",
-                        a3, instr.name, line
-                    )
-                }
-            }
+            let s = reg(&a1)?;
+            let t = reg(&a2)?;
+            let i = offset(&a3, 16)?;
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(instr.code, s as i8, t as i8, i as i16)
         }
         Syntax::BranchZ => {
             let (a1, a2) = match args {
                 Args::Two(a1, a2) => (a1, a2),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(2)),
             };
-            let s = a1.to_bin(lbl_adr);
-            let i = a2.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => {
-                    match s {
-                        Some(sr) => {
-                            let i_m: i16 = (((ir as i32 - adr as i32) >> 2) - 1) as i16;
-                            //println!("{}", i_m);
-                            return Encoding::Immediate(instr.code, sr as i8, 0, i_m);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    }
-                }
-                None => {
-                    println!(
-                        "Label \"{0}\" not found, in {1} on line {2}.",
-                        a2, instr.name, line
-                    )
-                }
-            }
+            let s = reg(&a1)?;
+            let i = offset(&a2, 16)?;
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(instr.code, s as i8, 0, i as i16)
         }
-        Syntax::LoadStore => {
+        Syntax::LoadStore | Syntax::AtomicLoadStore => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let t = a1.to_bin(lbl_adr);
-            let i = a2.to_bin(lbl_adr);
-            let s = a3.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            let i_m: i16 = (((ir as i32 - adr as i32) >> 2) - 1) as i16;
-                            return Encoding::Immediate(instr.code, sr as i8, tr as i8, i_m);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a3, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Number \"{0}\" not valid, in {1} on line {2}.",
-                        a2, instr.name, line
-                    )
-                }
-            }
+            let t = pair(&a1, instr.name == "ldc1" || instr.name == "sdc1")?;
+            let i = simm16(&a2, "Offset")?;
+            let s = reg(&a3)?;
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(instr.code, s as i8, t as i8, i)
         }
         Syntax::Jump => {
             let a1 = match args {
-                Args::One(a1) => (a1),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!()
-                }
+                Args::One(a1) => a1,
+                _ => return Err(count(1)),
             };
-            let i = a1.to_bin(lbl_adr);
-
-            match i {
-                None => {
-                    println!(
-                        "Label \"{0}\" not found, in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-                Some(ir) => {
-                    return Encoding::Jump(instr.code, (ir as i32) >> 2);
-                }
+            let i = lbl(&a1)?;
+            // Jumps keep the top 4 bits of the address of the delay slot
+            let region = adr.wrapping_add(4) & 0xf000_0000;
+            if i % 4 != 0 || i & 0xf000_0000 != region {
+                return Err(err(
+                    format!(
+                        "Jump target {0:#010x} not valid in {1}, expected a multiple of 4 from {2:#010x} to {3:#010x}.",
+                        i,
+                        instr.name,
+                        region,
+                        region | 0x0fff_fffc
+                    ),
+                    &a1,
+                ));
             }
 
-            Encoding::Jump(instr.code, 0)
+            Encoding::Jump(instr.code, ((i >> 2) & 0x03ff_ffff) as i32)
         }
         Syntax::Trap => {
             let a1 = match args {
-                Args::One(a1) => (a1),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                Args::One(a1) => a1,
+                _ => return Err(count(1)),
             };
-            let i = a1.to_bin(lbl_adr);
+            let i = ranged(&a1, "Number", 0..=0x03ff_ffff)?;
 
-            match i {
-                None => {
-                    println!(
-                        "Number \"{0}\" not valid, in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-                Some(ir) => {
-                    return Encoding::Jump(instr.code, ir as i32);
-                }
-            }
-
-            Encoding::Jump(instr.code, 0)
+            Encoding::Jump(instr.code, i as i32)
         }
         Syntax::Syscall => Encoding::Jump(0, instr.code as i32),
         Syntax::S2ArithLog => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let d = a1.to_bin(lbl_adr);
-            let s = a2.to_bin(lbl_adr);
-            let t = a3.to_bin(lbl_adr);
-
-            match d {
-                Some(dr) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            return Encoding::Register(
-                                28, sr as i8, tr as i8, dr as i8, 0, instr.code,
-                            );
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a3, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a2, instr.name, line
-                        )
-                    }
-                },
-                None => {
-                    println!(
-                        "Register \"{0}\" not found in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+            let t = reg(&a3)?;
 
-            Encoding::Register(28, 0, 0, 0, 0, instr.code)
+            Encoding::Register(28, s as i8, t as i8, d as i8, 0, instr.code)
         }
-        Syntax::Pseudo(func) => {func(args)}
         Syntax::RegImmBranch => {
             let (a1, a2) = match args {
                 Args::Two(a1, a2) => (a1, a2),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(2)),
             };
-            let s = a1.to_bin(lbl_adr);
-            let i = a2.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => {
-                    match s {
-                        Some(sr) => {
-                            let i_m: i16 = (((ir as i32 - adr as i32) >> 2) - 1) as i16;
-                            //println!("{}", i_m);
-                            return Encoding::Immediate(1, sr as i8, instr.code, i_m);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    }
-                }
-                None => {
-                    println!(
-                        "Label \"{0}\" not found, in {1} on line {2}.",
-                        a2, instr.name, line
-                    )
-                }
-            }
+            let s = reg(&a1)?;
+            let i = offset(&a2, 16)?;
 
-            Encoding::Immediate(instr.code, 0, 0, 0)
+            Encoding::Immediate(1, s as i8, instr.code, i as i16)
         }
         Syntax::CoProc1Move => {
             let (a1, a2) = match args {
-                Args::Two(a, b) => {(a,b)}
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                Args::Two(a, b) => (a, b),
+                _ => return Err(count(2)),
             };
+            let t = reg(&a1)?;
+            let s = reg(&a2)?;
 
-            let t = a1.to_bin(lbl_adr);
-            let s = a2.to_bin(lbl_adr);
-
-            match t {
-                Some(tr) => {
-                    match s {
-                        Some(sr) => {
-                            return Encoding::Register(17, instr.code, tr as i8, sr as i8, 0, 0);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a2, instr.name, line
-                            )
-                        }
-                    }
-                }
-                None => {
-                    println!(
-                        "Register \"{0}\" not found, in {1} on line {2}.",
-                        a1, instr.name, line
-                    )
-                }
-            }
-            Encoding::Register(17, instr.code, 0, 0, 0, 0)
+            Encoding::Register(17, instr.code, t as i8, s as i8, 0, 0)
         }
         Syntax::Break => {
-            match args {
-                Args::None => (),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!()
-                }
-            };
+            if !matches!(args, Args::None) {
+                return Err(count(0));
+            }
 
             Encoding::Register(0, 0, 0, 0, 0, instr.code)
         }
-        Syntax::AtomicLoadStore => {
+        Syntax::CoProc0Move => {
+            let (a1, a2) = match args {
+                Args::Two(a, b) => (a, b),
+                _ => return Err(count(2)),
+            };
+            let t = reg(&a1)?;
+            let d = reg(&a2)?;
+
+            Encoding::Register(16, instr.code, t as i8, d as i8, 0, 0)
+        }
+        Syntax::ExcReturn => {
+            if !matches!(args, Args::None) {
+                return Err(count(0));
+            }
+
+            // The CO bit is set for operations of coprocessor 0 itself
+            Encoding::Register(16, 16, 0, 0, 0, instr.code)
+        }
+        Syntax::FpArith => {
             let (a1, a2, a3) = match args {
                 Args::Three(a1, a2, a3) => (a1, a2, a3),
-                _ => {
-                    println!("Invalid number of arguments found on line {0}.", line);
-                    panic!();
-                }
+                _ => return Err(count(3)),
             };
-            let t = a1.to_bin(lbl_adr);
-            let i = a2.to_bin(lbl_adr);
-            let s = a3.to_bin(lbl_adr);
-
-            match i {
-                Some(ir) => match s {
-                    Some(sr) => match t {
-                        Some(tr) => {
-                            let i_m: i16 = (((ir as i32 - adr as i32) >> 2) - 1) as i16;
-                            return Encoding::Register(31, sr as i8, tr as i8, (i_m/2) as i8, (i_m << 1) as i8, instr.code);
-                        }
-                        None => {
-                            println!(
-                                "Register \"{0}\" not found in {1} on line {2}.",
-                                a1, instr.name, line
-                            )
-                        }
-                    },
-                    None => {
-                        println!(
-                            "Register \"{0}\" not found in {1} on line {2}.",
-                            a3, instr.name, line
-                        )
+            let d = pair(&a1, src_double)?;
+            let s = pair(&a2, src_double)?;
+            let t = pair(&a3, src_double)?;
+
+            Encoding::Register(17, fmt, t as i8, s as i8, d as i8, instr.code)
+        }
+        Syntax::FpUnary => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let d = pair(&a1, dst_double)?;
+            let s = pair(&a2, src_double)?;
+
+            Encoding::Register(17, fmt, 0, s as i8, d as i8, instr.code)
+        }
+        Syntax::FpCompare => {
+            // The condition flag is optional and defaults to 0
+            let (c, a1, a2) = match args {
+                Args::Two(a1, a2) => (0, a1, a2),
+                Args::Three(c, a1, a2) => (cc(&c)?, a1, a2),
+                _ => return Err(count(2)),
+            };
+            let s = pair(&a1, src_double)?;
+            let t = pair(&a2, src_double)?;
+
+            Encoding::Register(17, fmt, t as i8, s as i8, (c << 2) as i8, instr.code)
+        }
+        Syntax::FpBranch => {
+            let (c, a1) = match args {
+                Args::One(a1) => (0, a1),
+                Args::Two(c, a1) => (cc(&c)?, a1),
+                _ => return Err(count(1)),
+            };
+            let i = offset(&a1, 16)?;
+
+            let t = (c << 2) | instr.tf();
+            Encoding::Immediate(17, instr.code, t as i8, i as i16)
+        }
+        Syntax::FpMoveCond => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = pair(&a1, src_double)?;
+            let s = pair(&a2, src_double)?;
+            let t = (cc(&a3)? << 2) | instr.tf();
+
+            Encoding::Register(17, fmt, t as i8, s as i8, d as i8, instr.code)
+        }
+        Syntax::FpMoveZero => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = pair(&a1, src_double)?;
+            let s = pair(&a2, src_double)?;
+            let t = reg(&a3)?;
+
+            Encoding::Register(17, fmt, t as i8, s as i8, d as i8, instr.code)
+        }
+        Syntax::MoveCond => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+            let t = (cc(&a3)? << 2) | instr.tf();
+
+            Encoding::Register(0, s as i8, t as i8, d as i8, 0, instr.code)
+        }
+        Syntax::BitField => {
+            let (a1, a2, a3, a4) = match args {
+                Args::Four(a1, a2, a3, a4) => (a1, a2, a3, a4),
+                _ => return Err(count(4)),
+            };
+            let t = reg(&a1)?;
+            let s = reg(&a2)?;
+            let pos = imm(&a3, "Bit position")?;
+            let size = imm(&a4, "Field size")?;
+            if pos > 31 || size == 0 || size > 32 - pos {
+                return Err(Diagnostic::error(
+                    line,
+                    format!(
+                        "Field of {0} bits at bit {1} not valid in {2}, it must fit in 32 bits.",
+                        size as i32, pos as i32, instr.name
+                    ),
+                )
+                .at(src, &a3.to_string()));
+            }
+            // ext stores the size less one, ins the last bit of the field
+            let msb = if instr.name == "ext" {
+                size - 1
+            } else {
+                pos + size - 1
+            };
+
+            Encoding::Register(31, s as i8, t as i8, msb as i8, pos as i8, instr.code)
+        }
+        Syntax::ByteSwap => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let d = reg(&a1)?;
+            let t = reg(&a2)?;
+
+            Encoding::Register(31, 0, t as i8, d as i8, instr.code, 32)
+        }
+        Syntax::Rotate => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = reg(&a1)?;
+            let t = reg(&a2)?;
+            let a = ranged(&a3, "Shift amount", 0..=31)?;
+
+            Encoding::Register(0, 1, t as i8, d as i8, a as i8, instr.code)
+        }
+        Syntax::RotateV => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = reg(&a1)?;
+            let t = reg(&a2)?;
+            let s = reg(&a3)?;
+
+            Encoding::Register(0, s as i8, t as i8, d as i8, 1, instr.code)
+        }
+        Syntax::CountBits => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+
+            // The destination goes in both rt and rd
+            Encoding::Register(28, s as i8, d as i8, d as i8, 0, instr.code)
+        }
+        Syntax::MulAcc => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let s = reg(&a1)?;
+            let t = reg(&a2)?;
+
+            Encoding::Register(28, s as i8, t as i8, 0, 0, instr.code)
+        }
+        Syntax::TrapReg => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let s = reg(&a1)?;
+            let t = reg(&a2)?;
+
+            Encoding::Register(0, s as i8, t as i8, 0, 0, instr.code)
+        }
+        Syntax::TrapImm => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let s = reg(&a1)?;
+            let i = simm16(&a2, "Immediate value")?;
+
+            Encoding::Immediate(1, s as i8, instr.code, i)
+        }
+        Syntax::Sync => {
+            // The type of barrier is optional and defaults to 0, a full one
+            let stype = match args {
+                Args::None => 0,
+                Args::One(a1) => match imm(&a1, "Barrier type")? {
+                    n @ 0..=31 => n,
+                    _ => {
+                        return Err(err(
+                            format!(
+                                "Barrier type \"{0}\" not valid in {1}, expected 0 to 31.",
+                                a1, instr.name
+                            ),
+                            &a1,
+                        ))
                     }
                 },
-                None => {
-                    println!(
-                        "Number \"{0}\" not valid, in {1} on line {2}.",
-                        a2, instr.name, line
-                    )
+                _ => return Err(count(0)),
+            };
+
+            Encoding::Register(0, 0, 0, 0, stype as i8, instr.code)
+        }
+        Syntax::MulDivR6 => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+            let t = reg(&a3)?;
+
+            Encoding::Register(
+                0,
+                s as i8,
+                t as i8,
+                d as i8,
+                instr.r6_sa() as i8,
+                instr.code,
+            )
+        }
+        Syntax::CountBitsR6 => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let d = reg(&a1)?;
+            let s = reg(&a2)?;
+
+            Encoding::Register(0, s as i8, 0, d as i8, 1, instr.code)
+        }
+        Syntax::AtomicR6 => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            let t = reg(&a1)?;
+            let i = match imm(&a2, "Number")? as i32 {
+                i @ -256..=255 => i,
+                _ => {
+                    return Err(err(
+                        format!(
+                            "Offset \"{0}\" not valid in {1}, expected -256 to 255.",
+                            a2, instr.name
+                        ),
+                        &a2,
+                    ))
                 }
+            };
+            let s = reg(&a3)?;
+
+            // The offset has 9 bits, from bit 7 up
+            let (d, a) = ((i >> 4) & 31, (i & 15) << 1);
+            Encoding::Register(31, s as i8, t as i8, d as i8, a as i8, instr.code)
+        }
+        Syntax::CompactJump => {
+            let a1 = match args {
+                Args::One(a1) => a1,
+                _ => return Err(count(1)),
+            };
+            // Compact branches have no delay slot, but still count from the next instruction
+            let i = offset(&a1, 26)?;
+
+            Encoding::Jump(instr.code, i & 0x03ff_ffff)
+        }
+        Syntax::CompactBranchZ => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let s = reg(&a1)?;
+            // With $zero this would be another instruction
+            if s == 0 {
+                return Err(Diagnostic::error(
+                    line,
+                    format!("{0} cannot test $zero, use bc instead.", instr.name),
+                )
+                .at(src, instr.name));
             }
+            let i = offset(&a2, 21)?;
 
-            Encoding::Register(31, 0, 0, 0, 0, instr.code)
+            Encoding::Jump(instr.code, ((s as i32) << 21) | (i & 0x001f_ffff))
         }
-    }
+        Syntax::FpCompareR6 => {
+            let (a1, a2, a3) = match args {
+                Args::Three(a1, a2, a3) => (a1, a2, a3),
+                _ => return Err(count(3)),
+            };
+            // The result is a mask of the width of the format
+            let d = pair(&a1, src_double)?;
+            let s = pair(&a2, src_double)?;
+            let t = pair(&a3, src_double)?;
+
+            Encoding::Register(17, fmt + 4, t as i8, s as i8, d as i8, instr.code)
+        }
+        Syntax::FpBranchR6 => {
+            let (a1, a2) = match args {
+                Args::Two(a1, a2) => (a1, a2),
+                _ => return Err(count(2)),
+            };
+            let t = reg(&a1)?;
+            let i = offset(&a2, 16)?;
+
+            Encoding::Immediate(17, instr.code, t as i8, i as i16)
+        }
+    })
 }
@@ -0,0 +1,124 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use crate::codes::strip_comment;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// More about another diagnostic, such as the macro call a line came from.
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A problem found while assembling. `cols` is a byte range into the source line; an empty range
+/// means the whole statement.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: String,
+    pub line: u32,
+    pub cols: Range<usize>,
+    pub message: String,
+    /// Other places that explain this one, each rendered after it.
+    pub notes: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(line: u32, message: S) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: String::new(),
+            line,
+            cols: 0..0,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning<S: Into<String>>(line: u32, message: S) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(line, message)
+        }
+    }
+
+    pub fn note<S: Into<String>>(line: u32, message: S) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Note,
+            ..Diagnostic::error(line, message)
+        }
+    }
+
+    /// Points the diagnostic at the first whole-word occurrence of `text` in the source line.
+    /// If there is none it keeps pointing at the whole statement.
+    pub fn at(mut self, src: &str, text: &str) -> Diagnostic {
+        if text.is_empty() {
+            return self;
+        }
+        let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+        for (i, _) in src.match_indices(text) {
+            let before = src[..i].chars().next_back();
+            let after = src[i + text.len()..].chars().next();
+            let starts = !text.starts_with(is_word) || !before.is_some_and(is_word);
+            let ends = !text.ends_with(is_word) || !after.is_some_and(is_word);
+            if starts && ends {
+                self.cols = i..i + text.len();
+                break;
+            }
+        }
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Formats the diagnostic like rustc does, quoting `src_line` with a caret under the span.
+    /// Line 0 means the diagnostic is about no line in particular.
+    pub fn render(&self, src_line: &str) -> String {
+        if self.line == 0 {
+            return format!("{}: {}\n", self.severity, self.message);
+        }
+        let cols = if self.cols.is_empty() {
+            // Whole statement, without indentation or comment
+            let code = strip_comment(src_line);
+            let start = code.len() - code.trim_start().len();
+            start..code.trim_end().len().max(start + 1)
+        } else {
+            self.cols.clone()
+        };
+
+        let num = self.line.to_string();
+        let pad = " ".repeat(num.len());
+        // Keep tabs so the caret lines up with the quoted line
+        let indent: String = src_line
+            .get(..cols.start)
+            .unwrap_or("")
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{0}: {1}\n{2}--> {3}:{4}:{5}\n{2} |\n{4} | {6}\n{2} | {7}{8}\n",
+            self.severity,
+            self.message,
+            pad,
+            self.file,
+            self.line,
+            cols.start + 1,
+            src_line,
+            indent,
+            "^".repeat(cols.len().max(1)),
+        )
+    }
+}
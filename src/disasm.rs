@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::codes::{get_arguments, get_enc, Syntax};
+use crate::get_bin;
+use crate::isa::Isa;
+use crate::tables::{decode, InstrCode, REGS};
+
+/// Reads the digits of a .ho file, ignoring whitespace.
+pub fn read_ho(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("Odd number of hex digits.".into());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex \"{}\".", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// Reads the digits of a .bo file, ignoring whitespace.
+pub fn read_bo(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(8) {
+        return Err("Number of binary digits is not a multiple of 8.".into());
+    }
+    (0..digits.len())
+        .step_by(8)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 8], 2)
+                .map_err(|_| format!("Invalid binary \"{}\".", &digits[i..i + 8]))
+        })
+        .collect()
+}
+
+/// Where a branch or jump at `adr` goes, if `instr` is one.
+fn target(instr: &InstrCode, word: u32, adr: u32) -> Option<u32> {
+    let next = adr.wrapping_add(4);
+    match instr.syntax {
+        Syntax::Branch
+        | Syntax::BranchZ
+        | Syntax::RegImmBranch
+        | Syntax::FpBranch
+        | Syntax::FpBranchR6 => Some(next.wrapping_add((word as i16 as i32 as u32) << 2)),
+        Syntax::Jump => Some((next & 0xf000_0000) | ((word & 0x03ff_ffff) << 2)),
+        Syntax::CompactJump => Some(next.wrapping_add((((word << 6) as i32) >> 4) as u32)),
+        Syntax::CompactBranchZ => Some(next.wrapping_add((((word << 11) as i32) >> 9) as u32)),
+        _ => None,
+    }
+}
+
+/// Writes the operands of `word` the way `get_enc` reads them for `instr`. Targets are
+/// written as labels from `names` where there is one, otherwise as addresses.
+fn operands(instr: &InstrCode, word: u32, adr: u32, names: &BTreeMap<u32, Vec<String>>) -> String {
+    let reg = |n: u32| format!("${}", REGS[n as usize]);
+    let (rs, rt, rd) = (
+        reg((word >> 21) & 31),
+        reg((word >> 16) & 31),
+        reg((word >> 11) & 31),
+    );
+    let sa = (word >> 6) & 31;
+    let (ft, fs, fd) = (
+        format!("$f{}", (word >> 16) & 31),
+        format!("$f{}", (word >> 11) & 31),
+        format!("$f{}", sa),
+    );
+    // Condition flag, written only when it is not 0
+    let cc = |n: u32| {
+        if n == 0 {
+            String::new()
+        } else {
+            format!("{}, ", n)
+        }
+    };
+    let imm = word & 0xffff;
+    let simm = word as i16;
+    let tgt = target(instr, word, adr).map(|t| match names.get(&t) {
+        Some(n) => n[0].clone(),
+        None => format!("{:#010x}", t),
+    });
+    let tgt = tgt.unwrap_or_default();
+
+    match instr.syntax {
+        Syntax::ArithLog | Syntax::S2ArithLog => format!("{}, {}, {}", rd, rs, rt),
+        Syntax::DivMult => format!("{}, {}", rs, rt),
+        Syntax::Shift => format!("{}, {}, {}", rd, rt, sa),
+        Syntax::ShiftV => format!("{}, {}, {}", rd, rt, rs),
+        Syntax::JumpR | Syntax::MoveTo => rs,
+        Syntax::MoveFrom => rd,
+        Syntax::ArithLogI => format!("{}, {}, {}", rt, rs, simm),
+        Syntax::LogicI => format!("{}, {}, {}", rt, rs, imm),
+        Syntax::LoadI => format!("{}, {}", rt, imm),
+        Syntax::Branch => format!("{}, {}, {}", rs, rt, tgt),
+        Syntax::BranchZ | Syntax::RegImmBranch => format!("{}, {}", rs, tgt),
+        Syntax::LoadStore | Syntax::AtomicLoadStore if instr.name.ends_with("c1") => {
+            format!("{}, {}({})", ft, simm, rs)
+        }
+        Syntax::LoadStore | Syntax::AtomicLoadStore => format!("{}, {}({})", rt, simm, rs),
+        Syntax::Jump => tgt,
+        Syntax::Trap => (word & 0x03ff_ffff).to_string(),
+        Syntax::Syscall | Syntax::Break => String::new(),
+        Syntax::CoProc1Move => format!("{}, {}", rt, fs),
+        Syntax::CoProc0Move => format!("{}, ${}", rt, (word >> 11) & 31),
+        Syntax::ExcReturn => String::new(),
+        Syntax::FpArith => format!("{}, {}, {}", fd, fs, ft),
+        Syntax::FpUnary => format!("{}, {}", fd, fs),
+        Syntax::FpCompare => format!("{}{}, {}", cc(sa >> 2), fs, ft),
+        Syntax::FpBranch => format!("{}{}", cc((word >> 18) & 7), tgt),
+        Syntax::FpMoveCond => format!("{}, {}, {}", fd, fs, (word >> 18) & 7),
+        Syntax::FpMoveZero => format!("{}, {}, {}", fd, fs, rt),
+        Syntax::MoveCond => format!("{}, {}, {}", rd, rs, (word >> 18) & 7),
+        Syntax::BitField => {
+            let msb = (word >> 11) & 31;
+            let size = if instr.name == "ext" {
+                msb + 1
+            } else {
+                (msb + 1).wrapping_sub(sa)
+            };
+            format!("{}, {}, {}, {}", rt, rs, sa, size as i32)
+        }
+        Syntax::ByteSwap => format!("{}, {}", rd, rt),
+        Syntax::Rotate => format!("{}, {}, {}", rd, rt, sa),
+        Syntax::RotateV => format!("{}, {}, {}", rd, rt, rs),
+        Syntax::CountBits => format!("{}, {}", rd, rs),
+        Syntax::MulAcc | Syntax::TrapReg => format!("{}, {}", rs, rt),
+        Syntax::TrapImm => format!("{}, {}", rs, simm),
+        Syntax::Sync if sa == 0 => String::new(),
+        Syntax::Sync => sa.to_string(),
+        Syntax::MulDivR6 => format!("{}, {}, {}", rd, rs, rt),
+        Syntax::CountBitsR6 => format!("{}, {}", rd, rs),
+        Syntax::AtomicR6 => format!("{}, {}({})", rt, ((word << 16) as i32) >> 23, rs),
+        Syntax::CompactJump => tgt,
+        Syntax::CompactBranchZ => format!("{}, {}", rs, tgt),
+        Syntax::FpCompareR6 => format!("{}, {}, {}", fd, fs, ft),
+        Syntax::FpBranchR6 => format!("{}, {}", ft, tgt),
+    }
+}
+
+/// Turns machine code back into assembly that assembles to the same bytes when placed at
+/// `start`. Branch and jump targets inside the code get a label: the name from `symbols` if it
+/// has one, otherwise `L_` and the address. Words that are not instructions of `isa`, or that use
+/// fields the assembler cannot write, come out as `.word`.
+pub fn disassemble(bytes: &[u8], start: u32, symbols: &HashMap<String, u32>, isa: Isa) -> String {
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]]))
+        .collect();
+    let end = start.wrapping_add(4 * words.len() as u32);
+    let inside = |adr: u32| (start..=end).contains(&adr);
+
+    let mut names: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (name, adr) in symbols {
+        if inside(*adr) {
+            names.entry(*adr).or_default().push(name.clone());
+        }
+    }
+    for n in names.values_mut() {
+        n.sort();
+    }
+    for (i, word) in words.iter().enumerate() {
+        let adr = start + 4 * i as u32;
+        if let Some(t) = decode(*word, isa).and_then(|instr| target(instr, *word, adr)) {
+            if inside(t) && t % 4 == 0 && !names.contains_key(&t) {
+                names.insert(t, vec![format!("L_{:08x}", t)]);
+            }
+        }
+    }
+    let lbl_adr: HashMap<String, u32> = names
+        .iter()
+        .flat_map(|(adr, n)| n.iter().map(move |n| (n.clone(), *adr)))
+        .collect();
+
+    let mut out = String::from(".text\n");
+    for (i, word) in words.iter().enumerate() {
+        let adr = start + 4 * i as u32;
+        for name in names.get(&adr).into_iter().flatten() {
+            out += &format!("{}:\n", name);
+        }
+        let text = decode(*word, isa).and_then(|instr| {
+            let ops = operands(instr, *word, adr, &names);
+            // Only keep it if it encodes back to the same word
+            let args = get_arguments(&ops).ok()?;
+            let enc = get_enc(instr, args, &lbl_adr, 0, adr, "").ok()?;
+            (get_bin(enc) == word.to_be_bytes()).then(|| format!("{} {}", instr.name, ops))
+        });
+        let text = text.unwrap_or_else(|| format!(".word {:#010x}", word));
+        out += &format!("\t{:<32}# {:#010x}\n", text.trim_end(), adr);
+    }
+    for name in names.get(&end).into_iter().flatten() {
+        out += &format!("{}:\n", name);
+    }
+    let rest = &bytes[4 * words.len()..];
+    if !rest.is_empty() {
+        let rest: Vec<String> = rest.iter().map(|b| format!("{:#04x}", b)).collect();
+        out += &format!("\t.byte {}\n", rest.join(", "));
+    }
+    out
+}
@@ -0,0 +1,522 @@
+use crate::{Program, RelocKind, Symbol};
+
+/// Whether [`Program::elf`] writes a program ready to run or one for a linker.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ElfKind {
+    /// `ET_EXEC`, loaded at the addresses of the memory map.
+    Executable,
+    /// `ET_REL`, with sections at 0 and relocations for every use of a label.
+    Object,
+}
+
+/// Byte order of an ELF file, which also applies to the code and data in it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+const EM_MIPS: u16 = 8;
+// EF_MIPS_ABI_O32, with the EF_MIPS_ARCH bits of the ISA added
+const EF_MIPS_ABI_O32: u32 = 0x0000_1000;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const SHN_ABS: u16 = 0xfff1;
+const STB_GLOBAL: u8 = 1 << 4;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+// Indices of the sections that are always written
+const TEXT: u16 = 1;
+const DATA: u16 = 2;
+const BSS: u16 = 3;
+const KTEXT: u16 = 4;
+const KDATA: u16 = 5;
+const SYMTAB: u32 = 6;
+const STRTAB: u32 = 7;
+
+const PAGE: u32 = 0x1000;
+
+/// Appends 16- and 32-bit fields in the byte order of the file.
+struct Writer {
+    bytes: Vec<u8>,
+    endian: Endian,
+}
+
+impl Writer {
+    fn half(&mut self, v: u16) {
+        match self.endian {
+            Endian::Big => self.bytes.extend(v.to_be_bytes()),
+            Endian::Little => self.bytes.extend(v.to_le_bytes()),
+        }
+    }
+
+    fn word(&mut self, v: u32) {
+        match self.endian {
+            Endian::Big => self.bytes.extend(v.to_be_bytes()),
+            Endian::Little => self.bytes.extend(v.to_le_bytes()),
+        }
+    }
+
+    fn pad_to(&mut self, offset: u32) {
+        self.bytes.resize(offset as usize, 0);
+    }
+}
+
+/// A string table under construction.
+struct Strings(Vec<u8>);
+
+impl Strings {
+    fn new() -> Strings {
+        Strings(vec![0])
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        let at = self.0.len() as u32;
+        self.0.extend(s.as_bytes());
+        self.0.push(0);
+        at
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+/// Rewrites the label fields of a big-endian segment to hold offsets into the target's section,
+/// as the relocations of an object file expect. `ori` under a HI16 becomes `addiu`, since the
+/// linker always rounds the upper half for a signed lower half.
+fn relocate(bytes: &mut [u8], off: usize, kind: RelocKind, target: u32) {
+    let field = &mut bytes[off..off + 4];
+    let word = u32::from_be_bytes([field[0], field[1], field[2], field[3]]);
+    let word = match kind {
+        RelocKind::Mips32 => target,
+        RelocKind::Mips26 => (word & 0xfc00_0000) | ((target >> 2) & 0x03ff_ffff),
+        RelocKind::Hi16 => (word & 0xffff_0000) | (target.wrapping_add(0x8000) >> 16),
+        RelocKind::Lo16 if word >> 26 == 13 => (9 << 26) | (word & 0x03ff_0000) | (target & 0xffff),
+        RelocKind::Lo16 => (word & 0xffff_0000) | (target & 0xffff),
+        RelocKind::Pc16 => (word & 0xffff_0000) | ((target.wrapping_sub(4) >> 2) & 0xffff),
+        RelocKind::Pc21 => (word & 0xffe0_0000) | ((target.wrapping_sub(4) >> 2) & 0x001f_ffff),
+        RelocKind::Pc26 => (word & 0xfc00_0000) | ((target.wrapping_sub(4) >> 2) & 0x03ff_ffff),
+    };
+    field.copy_from_slice(&word.to_be_bytes());
+}
+
+impl Program {
+    /// Section holding `adr`, with the section's start. The extern area is `.bss`. A label just
+    /// past the end of a segment belongs to it, so labels at the end of text or data have a
+    /// section too.
+    fn section_of(&self, adr: u32) -> Option<(u16, u32)> {
+        let segments = [
+            (TEXT, self.text_start, self.text.len() as u32),
+            (DATA, self.data_start, self.data.len() as u32),
+            (BSS, self.memory.extern_, self.extern_size),
+            (KTEXT, self.ktext_start, self.ktext.len() as u32),
+            (KDATA, self.kdata_start, self.kdata.len() as u32),
+        ];
+        segments
+            .iter()
+            .find(|(_, start, len)| adr >= *start && adr - start < *len)
+            .or_else(|| {
+                segments
+                    .iter()
+                    .find(|(_, start, len)| adr.wrapping_sub(*start) == *len)
+            })
+            .map(|(idx, start, _)| (*idx, *start))
+    }
+
+    /// Writes the program as a MIPS32 ELF file. Executables keep the addresses of the memory
+    /// map and start at the beginning of text. Object files have their sections at 0 and a
+    /// `.rel.text`, `.rel.data`, `.rel.ktext` or `.rel.kdata` entry for every label field that
+    /// refers to another section or,
+    /// for jumps and address loads, to any section. In little-endian files, data is swapped one
+    /// value at a time, so `.half`, `.word` and `.double` keep their meaning.
+    pub fn elf(&self, kind: ElfKind, endian: Endian) -> Vec<u8> {
+        let object = kind == ElfKind::Object;
+        let mut text = self.text.clone();
+        let mut data = self.data.clone();
+        let mut ktext = self.ktext.clone();
+        let mut kdata = self.kdata.clone();
+        let base = |start: u32| if object { 0 } else { start };
+
+        // (offset, type, symbol index) for .rel.text, .rel.data, .rel.ktext and .rel.kdata
+        let mut rel_text = Vec::new();
+        let mut rel_data = Vec::new();
+        let mut rel_ktext = Vec::new();
+        let mut rel_kdata = Vec::new();
+        if object {
+            for r in &self.relocations {
+                let (place, place_start) = match self.section_of(r.address) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let target = match self.section_of(r.target) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let pc_relative =
+                    matches!(r.kind, RelocKind::Pc16 | RelocKind::Pc21 | RelocKind::Pc26);
+                if pc_relative && target.0 == place {
+                    continue;
+                }
+                let off = r.address - place_start;
+                let value = r.value.wrapping_sub(target.1);
+                let (bytes, rels) = match place {
+                    TEXT => (&mut text, &mut rel_text),
+                    DATA => (&mut data, &mut rel_data),
+                    KTEXT => (&mut ktext, &mut rel_ktext),
+                    KDATA => (&mut kdata, &mut rel_kdata),
+                    _ => continue,
+                };
+                relocate(bytes, off as usize, r.kind, value);
+                let rtype = match r.kind {
+                    RelocKind::Mips32 => 2,
+                    RelocKind::Mips26 => 4,
+                    RelocKind::Hi16 => 5,
+                    RelocKind::Lo16 => 6,
+                    RelocKind::Pc16 => 10,
+                    RelocKind::Pc21 => 60,
+                    RelocKind::Pc26 => 61,
+                };
+                // Section symbols come right after the null symbol
+                rels.push((off, rtype, target.0 as u32));
+            }
+        }
+
+        if endian == Endian::Little {
+            for st in &self.statements {
+                let (seg, start) = match self.section_of(st.address) {
+                    Some((TEXT, start)) => (&mut text, start),
+                    Some((DATA, start)) => (&mut data, start),
+                    Some((KTEXT, start)) => (&mut ktext, start),
+                    Some((KDATA, start)) => (&mut kdata, start),
+                    _ => continue,
+                };
+                let off = (st.address - start) as usize;
+                if let Some(bytes) = seg.get_mut(off..off + st.bytes.len()) {
+                    for value in bytes.chunks_mut(st.unit.max(1) as usize) {
+                        value.reverse();
+                    }
+                }
+            }
+        }
+
+        // Locals come first, each file's after a symbol naming the file, then the globals
+        let mut strtab = Strings::new();
+        // name, value, info, section
+        let mut symtab: Vec<(u32, u32, u8, u16)> = vec![(0, 0, 0, 0)];
+        for idx in [TEXT, DATA, BSS, KTEXT, KDATA] {
+            symtab.push((0, 0, STT_SECTION, idx));
+        }
+        let entry = |strtab: &mut Strings, sym: &Symbol, info: u8| {
+            let (shndx, value) = match self.section_of(sym.address) {
+                Some((idx, start)) => (idx, sym.address - start + base(start)),
+                None => (SHN_ABS, sym.address),
+            };
+            (strtab.add(&sym.name), value, info, shndx)
+        };
+        for (i, file) in self.files.iter().enumerate() {
+            symtab.push((strtab.add(file), 0, STT_FILE, SHN_ABS));
+            for sym in self.symbols.iter().filter(|s| s.file == i && !s.global) {
+                symtab.push(entry(&mut strtab, sym, 0));
+            }
+        }
+        let first_global = symtab.len() as u32;
+        for sym in self.symbols.iter().filter(|s| s.global) {
+            symtab.push(entry(&mut strtab, sym, STB_GLOBAL));
+        }
+
+        let mut shstrtab = Strings::new();
+        let mut sections = vec![SectionHeader {
+            name: 0,
+            kind: 0,
+            flags: 0,
+            addr: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 0,
+            entsize: 0,
+        }];
+
+        let phnum: u32 = if object {
+            0
+        } else {
+            [
+                text.len() as u32,
+                data.len() as u32,
+                self.extern_size,
+                ktext.len() as u32,
+                kdata.len() as u32,
+            ]
+            .iter()
+            .filter(|len| **len > 0)
+            .count() as u32
+        };
+        let mut out = Writer {
+            bytes: Vec::new(),
+            endian,
+        };
+        out.pad_to(52 + 32 * phnum);
+
+        // Loadable segments sit at a file offset congruent to their address modulo the page
+        // size, so they can be mapped directly
+        let place = |out: &mut Writer, bytes: &[u8], adr: u32| -> u32 {
+            let mut offset = out.bytes.len() as u32;
+            if object {
+                offset = (offset + 15) & !15;
+            } else {
+                offset += (adr % PAGE).wrapping_sub(offset % PAGE) % PAGE;
+            }
+            out.pad_to(offset);
+            out.bytes.extend(bytes);
+            offset
+        };
+        let text_offset = place(&mut out, &text, self.text_start);
+        let data_offset = place(&mut out, &data, self.data_start);
+        let bss_offset = place(&mut out, &[], self.memory.extern_);
+        let ktext_offset = place(&mut out, &ktext, self.ktext_start);
+        let kdata_offset = place(&mut out, &kdata, self.kdata_start);
+        for (name, flags, addr, offset, size, kind, align) in [
+            (
+                ".text",
+                SHF_ALLOC | SHF_EXECINSTR,
+                base(self.text_start),
+                text_offset,
+                text.len() as u32,
+                SHT_PROGBITS,
+                4,
+            ),
+            (
+                ".data",
+                SHF_ALLOC | SHF_WRITE,
+                base(self.data_start),
+                data_offset,
+                data.len() as u32,
+                SHT_PROGBITS,
+                8,
+            ),
+            (
+                ".bss",
+                SHF_ALLOC | SHF_WRITE,
+                base(self.memory.extern_),
+                bss_offset,
+                self.extern_size,
+                SHT_NOBITS,
+                8,
+            ),
+            (
+                ".ktext",
+                SHF_ALLOC | SHF_EXECINSTR,
+                base(self.ktext_start),
+                ktext_offset,
+                ktext.len() as u32,
+                SHT_PROGBITS,
+                4,
+            ),
+            (
+                ".kdata",
+                SHF_ALLOC | SHF_WRITE,
+                base(self.kdata_start),
+                kdata_offset,
+                kdata.len() as u32,
+                SHT_PROGBITS,
+                8,
+            ),
+        ] {
+            sections.push(SectionHeader {
+                name: shstrtab.add(name),
+                kind,
+                flags,
+                addr,
+                offset,
+                size,
+                link: 0,
+                info: 0,
+                align,
+                entsize: 0,
+            });
+        }
+
+        out.pad_to((out.bytes.len() as u32 + 3) & !3);
+        let symtab_offset = out.bytes.len() as u32;
+        for (name, value, info, shndx) in &symtab {
+            out.word(*name);
+            out.word(*value);
+            out.word(0);
+            out.bytes.push(*info);
+            out.bytes.push(0);
+            out.half(*shndx);
+        }
+        sections.push(SectionHeader {
+            name: shstrtab.add(".symtab"),
+            kind: SHT_SYMTAB,
+            flags: 0,
+            addr: 0,
+            offset: symtab_offset,
+            size: 16 * symtab.len() as u32,
+            link: STRTAB,
+            info: first_global,
+            align: 4,
+            entsize: 16,
+        });
+        sections.push(SectionHeader {
+            name: shstrtab.add(".strtab"),
+            kind: SHT_STRTAB,
+            flags: 0,
+            addr: 0,
+            offset: out.bytes.len() as u32,
+            size: strtab.0.len() as u32,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+        out.bytes.extend(&strtab.0);
+
+        for (name, target, rels) in [
+            (".rel.text", TEXT, rel_text),
+            (".rel.data", DATA, rel_data),
+            (".rel.ktext", KTEXT, rel_ktext),
+            (".rel.kdata", KDATA, rel_kdata),
+        ] {
+            if rels.is_empty() {
+                continue;
+            }
+            out.pad_to((out.bytes.len() as u32 + 3) & !3);
+            let offset = out.bytes.len() as u32;
+            for (off, rtype, sym) in &rels {
+                out.word(*off);
+                out.word((sym << 8) | rtype);
+            }
+            sections.push(SectionHeader {
+                name: shstrtab.add(name),
+                kind: SHT_REL,
+                flags: SHF_INFO_LINK,
+                addr: 0,
+                offset,
+                size: 8 * rels.len() as u32,
+                link: SYMTAB,
+                info: target as u32,
+                align: 4,
+                entsize: 8,
+            });
+        }
+
+        let shstrndx = sections.len() as u16;
+        let name = shstrtab.add(".shstrtab");
+        sections.push(SectionHeader {
+            name,
+            kind: SHT_STRTAB,
+            flags: 0,
+            addr: 0,
+            offset: out.bytes.len() as u32,
+            size: shstrtab.0.len() as u32,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+        out.bytes.extend(&shstrtab.0);
+
+        out.pad_to((out.bytes.len() as u32 + 3) & !3);
+        let shoff = out.bytes.len() as u32;
+        for sh in &sections {
+            for field in [
+                sh.name, sh.kind, sh.flags, sh.addr, sh.offset, sh.size, sh.link, sh.info,
+                sh.align, sh.entsize,
+            ] {
+                out.word(field);
+            }
+        }
+
+        // Now that offsets are known, go back for the headers
+        let body = std::mem::take(&mut out.bytes);
+        out.bytes.extend(b"\x7fELF");
+        out.bytes.push(1); // ELFCLASS32
+        out.bytes.push(match endian {
+            Endian::Little => 1,
+            Endian::Big => 2,
+        });
+        out.bytes.push(1); // EV_CURRENT
+        out.pad_to(16);
+        out.half(if object { 1 } else { 2 });
+        out.half(EM_MIPS);
+        out.word(1);
+        out.word(if object { 0 } else { self.text_start });
+        out.word(if phnum > 0 { 52 } else { 0 });
+        out.word(shoff);
+        out.word(EF_MIPS_ABI_O32 | self.isa.elf_arch());
+        out.half(52);
+        out.half(32);
+        out.half(phnum as u16);
+        out.half(40);
+        out.half(sections.len() as u16);
+        out.half(shstrndx);
+
+        for (filesz, memsz, offset, adr, flags) in [
+            (
+                text.len() as u32,
+                text.len() as u32,
+                text_offset,
+                self.text_start,
+                5,
+            ),
+            (
+                data.len() as u32,
+                data.len() as u32,
+                data_offset,
+                self.data_start,
+                6,
+            ),
+            (0, self.extern_size, bss_offset, self.memory.extern_, 6),
+            (
+                ktext.len() as u32,
+                ktext.len() as u32,
+                ktext_offset,
+                self.ktext_start,
+                5,
+            ),
+            (
+                kdata.len() as u32,
+                kdata.len() as u32,
+                kdata_offset,
+                self.kdata_start,
+                6,
+            ),
+        ] {
+            if object || memsz == 0 {
+                continue;
+            }
+            // PT_LOAD
+            for field in [1, offset, adr, adr, filesz, memsz, flags, PAGE] {
+                out.word(field);
+            }
+        }
+
+        let headers = out.bytes.len();
+        let mut bytes = body;
+        bytes[..headers].copy_from_slice(&out.bytes);
+        bytes
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use crate::codes::parse_num;
+
+/// A binary operator of an [`Expr`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Op {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// The halves of an address an operand can ask for, as in `%hi(label)`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Part {
+    /// The upper half, rounded up when the lower half is negative, for `lui` paired with `%lo`.
+    Hi,
+    /// The lower half, sign-extended.
+    Lo,
+    /// The offset from `_gp`.
+    GpRel,
+}
+
+/// An operand expression, with C operators and precedence. Values are 64-bit while the
+/// expression is evaluated, so `0x80000000 >> 4` does not sign-extend, and are cut to 32 bits
+/// where they are used.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Expr {
+    Num(i64),
+    Symbol(String),
+    /// `.`, the address of the statement.
+    Dot,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Part(Part, Box<Expr>),
+}
+
+/// Binary operators from the loosest to the tightest binding.
+const LEVELS: [&[(&str, Op)]; 6] = [
+    &[("|", Op::Or)],
+    &[("^", Op::Xor)],
+    &[("&", Op::And)],
+    &[("<<", Op::Shl), (">>", Op::Shr)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+    &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+];
+
+const PARTS: [(&str, Part); 3] = [
+    ("%hi", Part::Hi),
+    ("%lo", Part::Lo),
+    ("%gp_rel", Part::GpRel),
+];
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// The operator `%hi(`, `%lo(` or `%gp_rel(` that comes next, if any.
+    fn part(&self) -> Option<(&'a str, Part)> {
+        PARTS.iter().find_map(|(name, part)| {
+            let after = self.rest.strip_prefix(name)?;
+            after
+                .trim_start()
+                .starts_with('(')
+                .then_some((after, *part))
+        })
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        self.skip_space();
+        match self.rest.strip_prefix(tok) {
+            Some(r) => {
+                self.rest = r;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'next: loop {
+            self.skip_space();
+            for (tok, op) in LEVELS[level] {
+                // %hi and friends only start an operand
+                if *op == Op::Rem && self.part().is_some() {
+                    continue;
+                }
+                if self.eat(tok) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'next;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat("~") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_space();
+        if self.eat("(") {
+            let e = self.binary(0)?;
+            if !self.eat(")") {
+                return Err("Missing ending parenthesis".into());
+            }
+            return Ok(e);
+        }
+        if let Some((after, part)) = self.part() {
+            self.rest = after;
+            self.eat("(");
+            let e = self.binary(0)?;
+            if !self.eat(")") {
+                return Err("Missing ending parenthesis".into());
+            }
+            return Ok(Expr::Part(part, Box::new(e)));
+        }
+
+        let s = self.rest;
+        let first = match s.chars().next() {
+            Some(c) => c,
+            None => return Err("Expected a value".into()),
+        };
+        if first == '\'' {
+            // The closing quote of a char literal, which may be escaped
+            let end = s[1..]
+                .char_indices()
+                .scan(false, |escaped, (i, c)| {
+                    let close = c == '\'' && !*escaped;
+                    *escaped = c == '\\' && !*escaped;
+                    Some((i, close))
+                })
+                .find(|(_, close)| *close)
+                .map(|(i, _)| i + 2)
+                .ok_or("Missing closing quote")?;
+            self.rest = &s[end..];
+            return parse_num(&s[..end]).map(|n| Expr::Num(n as i64));
+        }
+        let len = s.find(|c| !is_symbol_char(c)).unwrap_or(s.len());
+        let word = &s[..len];
+        self.rest = &s[len..];
+        if first.is_ascii_digit() {
+            // Hexadecimal and binary literals are bit patterns, so they are never negative
+            let n = match word.parse::<i64>() {
+                Ok(n) => n,
+                Err(_) => parse_num(word)
+                    .map(|n| n as u32 as i64)
+                    .map_err(|_| format!("Number \"{}\" not valid", word))?,
+            };
+            return Ok(Expr::Num(n));
+        }
+        match word {
+            "." => Ok(Expr::Dot),
+            _ if first.is_alphabetic() || first == '_' || first == '.' => {
+                Ok(Expr::Symbol(word.to_string()))
+            }
+            _ => Err(format!("Unexpected \"{}\"", s.trim_end())),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, String> {
+        let mut p = Parser { rest: s };
+        let e = p.binary(0)?;
+        p.skip_space();
+        if !p.rest.is_empty() {
+            return Err(format!("Unexpected \"{}\"", p.rest.trim_end()));
+        }
+        Ok(e)
+    }
+
+    /// The value at address `dot`, with `symbols` giving the address of each label.
+    pub fn eval(&self, symbols: &HashMap<String, u32>, dot: u32) -> Result<i64, String> {
+        let eval = |e: &Expr| e.eval(symbols, dot);
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Symbol(name) => match symbols.get(name) {
+                Some(adr) => *adr as i64,
+                None => return Err(format!("Label \"{}\" not found", name)),
+            },
+            Expr::Dot => dot as i64,
+            Expr::Neg(e) => eval(e)?.wrapping_neg(),
+            Expr::Not(e) => !eval(e)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (eval(a)?, eval(b)?);
+                match op {
+                    Op::Or => a | b,
+                    Op::Xor => a ^ b,
+                    Op::And => a & b,
+                    Op::Shl => a.wrapping_shl(b as u32),
+                    Op::Shr => a.wrapping_shr(b as u32),
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                    Op::Mul => a.wrapping_mul(b),
+                    Op::Div | Op::Rem if b == 0 => return Err("Division by zero".into()),
+                    Op::Div => a.wrapping_div(b),
+                    Op::Rem => a.wrapping_rem(b),
+                }
+            }
+            Expr::Part(part, e) => {
+                let v = e.value(symbols, dot)?;
+                match part {
+                    Part::Hi => (v.wrapping_add(0x8000) >> 16) as i64,
+                    Part::Lo => v as i16 as i64,
+                    Part::GpRel => {
+                        let gp = Expr::Symbol("_gp".into()).eval(symbols, dot)? as u32;
+                        v.wrapping_sub(gp) as i32 as i64
+                    }
+                }
+            }
+        })
+    }
+
+    /// [`Expr::eval`] as a 32-bit word, which the value has to fit as either a signed or an
+    /// unsigned number.
+    pub fn value(&self, symbols: &HashMap<String, u32>, dot: u32) -> Result<u32, String> {
+        match self.eval(symbols, dot)? {
+            v if (-(1 << 31)..1 << 32).contains(&v) => Ok(v as u32),
+            _ => Err("Expression value does not fit in 32 bits".into()),
+        }
+    }
+
+    /// The labels the expression uses, in order.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Symbol(name) => vec![name],
+            Expr::Num(_) | Expr::Dot => vec![],
+            Expr::Neg(e) | Expr::Not(e) | Expr::Part(_, e) => e.symbols(),
+            Expr::Binary(_, a, b) => [a.symbols(), b.symbols()].concat(),
+        }
+    }
+
+    /// Whether the value is known without any addresses, so pass1 can work it out.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Num(_) => true,
+            Expr::Symbol(_) | Expr::Dot => false,
+            Expr::Part(Part::GpRel, _) => false,
+            Expr::Neg(e) | Expr::Not(e) | Expr::Part(_, e) => e.is_constant(),
+            Expr::Binary(_, a, b) => a.is_constant() && b.is_constant(),
+        }
+    }
+
+    /// The label an address is relative to, for a relocation: the label of `label`,
+    /// `label + n`, `n + label` or `label - n`, where `n` is constant.
+    pub fn base_symbol(&self) -> Option<&str> {
+        match self {
+            Expr::Symbol(name) => Some(name),
+            Expr::Binary(Op::Add | Op::Sub, a, b) if b.is_constant() => a.base_symbol(),
+            Expr::Binary(Op::Add, a, b) if a.is_constant() => b.base_symbol(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::simulator::{Cpu, Exception};
+use crate::tables::InstrCode;
+
+/// Converts to a word the way MIPS does, with 2^31 - 1 for NaN and values out of range.
+fn to_word(v: f64) -> u32 {
+    if v.is_nan() || v < i32::MIN as f64 || v > i32::MAX as f64 {
+        i32::MAX as u32
+    } else {
+        v as i32 as u32
+    }
+}
+
+impl Cpu {
+    fn single(&self, r: u32) -> f32 {
+        f32::from_bits(self.fpr[r as usize])
+    }
+
+    /// The double in the pair starting at even register `r`, whose low word is in `r`.
+    fn double(&self, r: u32) -> f64 {
+        let (lo, hi) = (self.fpr[r as usize], self.fpr[r as usize + 1]);
+        f64::from_bits(((hi as u64) << 32) | lo as u64)
+    }
+
+    fn set_double(&mut self, r: u32, v: f64) {
+        self.fpr[r as usize] = v.to_bits() as u32;
+        self.fpr[r as usize + 1] = (v.to_bits() >> 32) as u32;
+    }
+
+    /// Condition flag `cc`, set by `c.eq`/`c.lt`/`c.le`.
+    pub(crate) fn flag(&self, cc: u32) -> bool {
+        self.fcc & (1 << cc) != 0
+    }
+
+    /// Executes a coprocessor 1 instruction in the FR format: arithmetic, conversion,
+    /// comparison, including the Release 6 compares into a register, and conditional moves.
+    /// Double operands must be even registers.
+    pub(crate) fn fpu(&mut self, instr: &InstrCode, word: u32) -> Result<(), Exception> {
+        let ft = (word >> 16) & 31;
+        let fs = (word >> 11) & 31;
+        let fd = (word >> 6) & 31;
+        let parts: Vec<&str> = instr.name.split('.').collect();
+        // The source format is the last suffix, the destination's the first
+        let src = parts[parts.len() - 1];
+        let compare = matches!(parts[0], "c" | "cmp");
+        let dest = if parts.len() == 3 && !compare {
+            parts[1]
+        } else {
+            src
+        };
+        let odd = |r: u32| !r.is_multiple_of(2);
+        // ft is a register of the source format only for arithmetic and compares
+        let ft_is_src = compare || matches!(parts[0], "add" | "sub" | "mul" | "div");
+        if src == "d" && (odd(fs) || ft_is_src && odd(ft))
+            || dest == "d" && parts[0] != "c" && odd(fd)
+        {
+            return Err(Exception::ReservedInstruction(word));
+        }
+        // Source operands, widened so one body serves both formats
+        let value = |r: u32| match src {
+            "d" => self.double(r),
+            "w" => self.fpr[r as usize] as i32 as f64,
+            _ => self.single(r) as f64,
+        };
+        let a = value(fs);
+        let b = if ft_is_src { value(ft) } else { 0.0 };
+        // Doing single precision arithmetic in double precision and rounding once at the end
+        // gives the same results, since a double has more than twice the bits of a single
+        let result = match parts[0] {
+            "add" => a + b,
+            "sub" => a - b,
+            "mul" => a * b,
+            "div" => a / b,
+            "sqrt" => a.sqrt(),
+            "abs" => a.abs(),
+            "neg" => -a,
+            "mov" | "cvt" => a,
+            "round" => {
+                self.fpr[fd as usize] = to_word(a.round_ties_even());
+                return Ok(());
+            }
+            "trunc" => {
+                self.fpr[fd as usize] = to_word(a.trunc());
+                return Ok(());
+            }
+            "ceil" => {
+                self.fpr[fd as usize] = to_word(a.ceil());
+                return Ok(());
+            }
+            "floor" => {
+                self.fpr[fd as usize] = to_word(a.floor());
+                return Ok(());
+            }
+            "cmp" => {
+                let unordered = a.is_nan() || b.is_nan();
+                let holds = match parts[1] {
+                    "af" => false,
+                    "un" => unordered,
+                    "eq" => a == b,
+                    "ueq" => unordered || a == b,
+                    "lt" => a < b,
+                    "ult" => unordered || a < b,
+                    "le" => a <= b,
+                    _ => unordered || a <= b,
+                };
+                let mask = if holds { u32::MAX } else { 0 };
+                self.fpr[fd as usize] = mask;
+                if dest == "d" {
+                    self.fpr[fd as usize + 1] = mask;
+                }
+                return Ok(());
+            }
+            "c" => {
+                let cc = fd >> 2;
+                let holds = match parts[1] {
+                    "eq" => a == b,
+                    "lt" => a < b,
+                    _ => a <= b,
+                };
+                self.fcc = (self.fcc & !(1 << cc)) | ((holds as u8) << cc);
+                return Ok(());
+            }
+            "movf" | "movt" => {
+                if self.flag(ft >> 2) != (instr.name.starts_with("movt")) {
+                    return Ok(());
+                }
+                a
+            }
+            "movz" | "movn" => {
+                if (self.regs[ft as usize] == 0) != (parts[0] == "movz") {
+                    return Ok(());
+                }
+                a
+            }
+            _ => return Err(Exception::ReservedInstruction(word)),
+        };
+        // mov, movf and friends copy bits, so NaN payloads survive
+        let copy = matches!(parts[0], "mov" | "movf" | "movt" | "movz" | "movn");
+        match dest {
+            "d" if copy => {
+                self.fpr[fd as usize] = self.fpr[fs as usize];
+                self.fpr[fd as usize + 1] = self.fpr[fs as usize + 1];
+            }
+            _ if copy => self.fpr[fd as usize] = self.fpr[fs as usize],
+            "d" => self.set_double(fd, result),
+            "w" => self.fpr[fd as usize] = to_word(result.round_ties_even()),
+            _ => self.fpr[fd as usize] = (result as f32).to_bits(),
+        }
+        Ok(())
+    }
+}
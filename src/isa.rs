@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+
+/// A revision of the MIPS instruction set, oldest first. The MIPS32 additions to MIPS II, such
+/// as `mul` and `movn`, count as Release 2, since that is the first MIPS32 revision offered.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Isa {
+    Mips1,
+    Mips2,
+    #[default]
+    Mips32r2,
+    /// Release 6, which re-encodes or removes many of the older instructions.
+    Mips32r6,
+}
+
+/// Names accepted by [`Isa::parse`].
+pub const ISAS: [(&str, Isa); 4] = [
+    ("mips1", Isa::Mips1),
+    ("mips2", Isa::Mips2),
+    ("mips32r2", Isa::Mips32r2),
+    ("mips32r6", Isa::Mips32r6),
+];
+
+impl Isa {
+    pub fn parse(name: &str) -> Option<Isa> {
+        ISAS.iter().find(|i| i.0 == name).map(|i| i.1)
+    }
+
+    pub fn name(self) -> &'static str {
+        ISAS.iter().find(|i| i.1 == self).map_or("", |i| i.0)
+    }
+
+    /// The EF_MIPS_ARCH bits of an ELF header for this revision.
+    pub(crate) fn elf_arch(self) -> u32 {
+        match self {
+            Isa::Mips1 => 0x0000_0000,
+            Isa::Mips2 => 0x1000_0000,
+            Isa::Mips32r2 => 0x7000_0000,
+            Isa::Mips32r6 => 0x9000_0000,
+        }
+    }
+}
+
+impl Display for Isa {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
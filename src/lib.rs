@@ -0,0 +1,1477 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::codes::{
+    find_unquoted, fits, fold, get_arguments, get_enc, get_pattern, is_expr, is_label_name,
+    parse_num, parse_string, split_items, split_label, tokenize, wrong_class, Arg, Args, Syntax,
+};
+use crate::expr::{Expr, Part};
+use crate::preprocess::{add_notes, preprocess, SourceFile, Stmt};
+use crate::tables::{find_code, get_ops, get_relaxations, register, InstrCode, PseudoOp};
+
+pub use crate::diagnostic::{Diagnostic, Severity};
+pub use crate::disasm::{disassemble, read_bo, read_ho};
+pub use crate::elf::{ElfKind, Endian};
+pub use crate::isa::{Isa, ISAS};
+pub use crate::memory::{MemoryMap, PRESETS};
+pub use crate::simulator::{Cpu, Exception, Memory, Stop};
+
+mod codes;
+mod diagnostic;
+mod disasm;
+mod elf;
+mod expr;
+mod fpu;
+mod isa;
+mod listing;
+mod memory;
+mod preprocess;
+mod simulator;
+mod syscalls;
+mod tables;
+
+/// Settings for [`assemble`].
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Name of the source file, used in diagnostics.
+    pub file: String,
+    /// Pseudo-op table in the format of res/PseudoOps.txt. The built-in table is used if `None`.
+    pub pseudo_ops: Option<String>,
+    /// Where text and data are placed.
+    pub memory: MemoryMap,
+    /// The instruction set revision. Instructions it does not have are errors.
+    pub isa: Isa,
+    /// Whether the instruction after a branch or jump runs before it lands, as on hardware.
+    /// Where `.set reorder` is in effect, a nop fills each delay slot.
+    pub delayed_branches: bool,
+    /// Where `.include` looks for files not found next to the file including them.
+    pub include_dirs: Vec<PathBuf>,
+    /// Constants defined before each file, by name and value, like `.eqv`.
+    pub defines: Vec<(String, String)>,
+}
+
+/// Links an instruction word in the text segment back to the source line it came from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SourceLine {
+    pub address: u32,
+    /// Index into [`Program::files`].
+    pub file: usize,
+    pub line: u32,
+}
+
+/// The bytes a statement assembled to. A line can have more than one of these, when a directive
+/// is padded for alignment.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Statement {
+    /// Index into [`Program::files`].
+    pub file: usize,
+    pub line: u32,
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Size of each value in `bytes`: 4 for instructions, the element size for data.
+    pub unit: u32,
+    /// The basic instructions a pseudo-op expanded to.
+    pub expansion: Vec<String>,
+}
+
+/// The ways a label's address is put into code or data, named after the matching ELF
+/// relocation types.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RelocKind {
+    /// A whole word, from `.word label`.
+    Mips32,
+    /// The target field of `j`/`jal`.
+    Mips26,
+    /// The upper half of an address, as `lui` loads it.
+    Hi16,
+    /// The lower half of an address.
+    Lo16,
+    /// A branch offset.
+    Pc16,
+    /// The offset of `beqzc`/`bnezc`.
+    Pc21,
+    /// The offset of `bc`/`balc`.
+    Pc26,
+}
+
+/// A place in the program holding (part of) a label's address.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Relocation {
+    /// Address of the word.
+    pub address: u32,
+    pub kind: RelocKind,
+    pub symbol: String,
+    /// Address of the label.
+    pub target: u32,
+    /// The address the field was computed from, which may be the label plus an offset.
+    pub value: u32,
+}
+
+/// A label. Labels are local to the file defining them unless declared with `.globl` or
+/// `.extern`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    /// Index into [`Program::files`].
+    pub file: usize,
+    pub line: u32,
+    pub global: bool,
+}
+
+/// A file brought in by `.include`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Include {
+    /// Index into [`Program::files`].
+    pub file: usize,
+    /// Index into [`Program::files`] of the file with the `.include`.
+    pub parent: usize,
+    /// The line of the `.include`.
+    pub line: u32,
+    /// The text of the file.
+    pub source: String,
+}
+
+/// An assembled program.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub text: Vec<u8>,
+    pub text_start: u32,
+    pub data: Vec<u8>,
+    pub data_start: u32,
+    /// Kernel text, from `.ktext`, where the exception handler goes.
+    pub ktext: Vec<u8>,
+    pub ktext_start: u32,
+    /// Kernel data, from `.kdata`.
+    pub kdata: Vec<u8>,
+    pub kdata_start: u32,
+    /// Bytes reserved by `.extern` from `memory.extern_` on. They start out as zero.
+    pub extern_size: u32,
+    /// The memory map the program was assembled for.
+    pub memory: MemoryMap,
+    pub isa: Isa,
+    /// Whether the program was assembled for delayed branches, and so needs them to run.
+    pub delayed_branches: bool,
+    /// Names of the source files, in the order they were linked, followed by the files they
+    /// include.
+    pub files: Vec<String>,
+    /// Each file brought in by `.include`, in the order they were reached.
+    pub includes: Vec<Include>,
+    /// Every label of every file, in file order and then address order.
+    pub symbols: Vec<Symbol>,
+    /// One entry per instruction word, in address order.
+    pub source_map: Vec<SourceLine>,
+    /// What each statement assembled to, in source order.
+    pub statements: Vec<Statement>,
+    /// Every use of a label's address, in source order.
+    pub relocations: Vec<Relocation>,
+    /// Diagnostics that did not stop assembly.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    /// The segments in address order, as written to .ho/.bo files. When the program spans no
+    /// more than 64K, as with the compact memory maps, each segment is padded out to its offset
+    /// from the lowest one so the image can be loaded as is. Otherwise they are concatenated.
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut segments: Vec<(u32, &Vec<u8>)> = [
+            (self.text_start, &self.text),
+            (self.data_start, &self.data),
+            (self.ktext_start, &self.ktext),
+            (self.kdata_start, &self.kdata),
+        ]
+        .into_iter()
+        .filter(|s| !s.1.is_empty())
+        .collect();
+        segments.sort_by_key(|s| s.0);
+        let base = segments.first().map_or(0, |s| s.0);
+        let end = segments
+            .iter()
+            .map(|(start, seg)| *start as u64 + seg.len() as u64)
+            .max()
+            .unwrap_or(0);
+        let pad = end.saturating_sub(base as u64) <= 0x10000;
+
+        let mut bytes = Vec::new();
+        for (start, seg) in segments {
+            if pad && bytes.len() < (start - base) as usize {
+                bytes.resize((start - base) as usize, 0);
+            }
+            bytes.extend(seg);
+        }
+        bytes
+    }
+}
+
+/// Assembles `source`. On failure returns every diagnostic found, errors and warnings alike.
+pub fn assemble(source: &str, options: &Options) -> Result<Program, Vec<Diagnostic>> {
+    assemble_files(&[(&options.file, source)], options)
+}
+
+/// Assembles several files, given as name and source, into one program. Each file's text and
+/// data follow those of the file before it. Labels are local to their file unless declared with
+/// `.globl`, in which case the other files can use them too. `options.file` is not used.
+pub fn assemble_files(
+    files: &[(&str, &str)],
+    options: &Options,
+) -> Result<Program, Vec<Diagnostic>> {
+    let (pseudos, mut diags) = get_ops(options.pseudo_ops.as_ref());
+    if diags.iter().any(|d| d.is_error()) {
+        return Err(diags);
+    }
+
+    let relaxations = get_relaxations();
+
+    let mem = &options.memory;
+    let mut counters = Counters::new(mem);
+    let mut units = Vec::new();
+    for (name, source) in files {
+        // Relaxing a branch moves everything after it, which can put other branches out of
+        // range, so addresses are assigned again until no more branches need relaxing
+        let mut relaxed = HashMap::new();
+        let unit = loop {
+            let unit = pass1(
+                name,
+                source,
+                counters,
+                &pseudos,
+                &relaxations,
+                &relaxed,
+                options,
+            );
+            if !relax(&unit, &mut relaxed, options.delayed_branches) {
+                break unit;
+            }
+        };
+        counters = unit.end;
+        units.push((counters, unit));
+    }
+
+    // Included files come after the files given, in the order they were included. Each unit's
+    // files get their index into Program::files.
+    let mut file_names: Vec<String> = files.iter().map(|f| f.0.to_string()).collect();
+    let mut includes = Vec::new();
+    let mut file_ids: Vec<Vec<usize>> = Vec::new();
+    for (i, (_, unit)) in units.iter().enumerate() {
+        let mut ids = vec![i];
+        for f in &unit.files[1..] {
+            let (parent, line) = f.parent.unwrap_or((0, 0));
+            ids.push(file_names.len());
+            includes.push(Include {
+                file: file_names.len(),
+                parent: ids[parent],
+                line,
+                source: f.text.clone(),
+            });
+            file_names.push(f.name.clone());
+        }
+        file_ids.push(ids);
+    }
+
+    // Link: every global must be defined once, and is visible to every file
+    let mut src_diags: Vec<Vec<Diagnostic>> = vec![Vec::new(); files.len()];
+    // The address, unit, file name and line of each global
+    let mut globals: HashMap<String, (u32, usize, String, u32)> = HashMap::new();
+    for (i, (_, unit)) in units.iter().enumerate() {
+        for (name, idx) in &unit.globals {
+            let adr = match unit.symbols.get(name) {
+                Some(adr) => *adr,
+                None => {
+                    let e = format!("Label \"{}\" is declared global but not defined.", name);
+                    src_diags[i].push(unit.error(*idx, e, name));
+                    continue;
+                }
+            };
+            let def = unit.symbol_stmts.get(name).copied().unwrap_or(*idx);
+            match globals.get(name) {
+                Some((_, _, file, first)) => {
+                    let e = format!(
+                        "Duplicate global symbol {0}, first defined in {1} on line {2}.",
+                        name, file, first
+                    );
+                    src_diags[i].push(unit.error(def, e, name));
+                }
+                None => {
+                    let stmt = &unit.stmts[def];
+                    let file = unit.files[stmt.file].name.clone();
+                    globals.insert(name.clone(), (adr, i, file, stmt.line));
+                }
+            }
+        }
+    }
+    for (i, (_, unit)) in units.iter().enumerate() {
+        for (name, idx) in &unit.externs {
+            if !globals.contains_key(name) {
+                let e = format!(
+                    "Undefined global symbol {}, no file declares it .globl.",
+                    name
+                );
+                src_diags[i].push(unit.error(*idx, e, name));
+            }
+        }
+    }
+
+    let mut start = Counters::new(mem);
+    let mut segments: [Vec<u8>; 4] = Default::default();
+    let (mut source_map, mut statements, mut relocations) = (Vec::new(), Vec::new(), Vec::new());
+    let mut symbols = Vec::new();
+    let mut unit_files = Vec::new();
+    for (i, (end, unit)) in units.into_iter().enumerate() {
+        let mut visible: HashMap<String, u32> = globals
+            .iter()
+            .map(|(name, g)| (name.clone(), g.0))
+            .collect();
+        visible.extend(unit.symbols.iter().map(|(n, a)| (n.clone(), *a)));
+        // %gp_rel is relative to _gp, which is where $gp starts unless the program says otherwise
+        visible.entry("_gp".to_string()).or_insert(mem.gp);
+
+        let ids = &file_ids[i];
+        let mut file_symbols: Vec<Symbol> = unit
+            .symbols
+            .iter()
+            .map(|(name, adr)| {
+                let stmt = unit.symbol_stmts.get(name).map(|s| &unit.stmts[*s]);
+                Symbol {
+                    name: name.clone(),
+                    address: *adr,
+                    file: stmt.map_or(i, |s| ids[s.file]),
+                    line: stmt.map_or(0, |s| s.line),
+                    global: globals.get(name).is_some_and(|g| g.1 == i),
+                }
+            })
+            .collect();
+        file_symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+        symbols.extend(file_symbols);
+
+        let pass2 = pass2(
+            unit.lines,
+            &unit.stmts,
+            &unit.files,
+            &visible,
+            start,
+            ids,
+            options,
+        );
+        for (segment, code) in segments.iter_mut().zip(pass2.0) {
+            segment.extend(code);
+        }
+        source_map.extend(pass2.1);
+        statements.extend(pass2.2);
+        relocations.extend(pass2.3);
+        src_diags[i].extend(unit.diags.into_iter().chain(pass2.4));
+        unit_files.push(unit.files);
+        start = end;
+    }
+    for (i, mut file_diags) in src_diags.into_iter().enumerate() {
+        // Diagnostics about included files already name them
+        for d in &mut file_diags {
+            if d.file.is_empty() {
+                d.file = files[i].0.to_string();
+            }
+            for note in &mut d.notes {
+                if note.file.is_empty() {
+                    note.file = files[i].0.to_string();
+                }
+            }
+        }
+        let rank = |d: &Diagnostic| unit_files[i].iter().position(|f| f.name == d.file);
+        file_diags.sort_by_key(|d| (rank(d), d.line));
+        diags.extend(file_diags);
+    }
+
+    let [text, data, ktext, kdata] = segments;
+    let extents = [
+        ("Text", mem.text as u64, text.len() as u64),
+        ("Data", mem.data as u64, data.len() as u64),
+        (
+            "Extern",
+            mem.extern_ as u64,
+            (start.extern_ - mem.extern_) as u64,
+        ),
+        ("Kernel text", mem.ktext as u64, ktext.len() as u64),
+        ("Kernel data", mem.kdata as u64, kdata.len() as u64),
+    ];
+    for (i, (name, adr, len)) in extents.iter().enumerate() {
+        for (other, o_adr, o_len) in &extents[i + 1..] {
+            if *len > 0 && *o_len > 0 && adr < &(o_adr + o_len) && o_adr < &(adr + len) {
+                diags.push(Diagnostic::error(
+                    0,
+                    format!(
+                        "{} segment {:#x}..{:#x} overlaps {} segment {:#x}..{:#x}.",
+                        name,
+                        adr,
+                        adr + len,
+                        other.to_lowercase(),
+                        o_adr,
+                        o_adr + o_len
+                    ),
+                ));
+            }
+        }
+    }
+
+    if diags.iter().any(|d| d.is_error()) {
+        return Err(diags);
+    }
+    Ok(Program {
+        text,
+        text_start: mem.text,
+        data,
+        data_start: mem.data,
+        ktext,
+        ktext_start: mem.ktext,
+        kdata,
+        kdata_start: mem.kdata,
+        extern_size: start.extern_ - mem.extern_,
+        memory: options.memory,
+        isa: options.isa,
+        delayed_branches: options.delayed_branches,
+        files: file_names,
+        includes,
+        symbols,
+        source_map,
+        statements,
+        relocations,
+        warnings: diags,
+    })
+}
+
+pub(crate) enum Line<'a> {
+    Instr(&'a InstrCode<'a>, Args<Arg>),
+    // Expanded in pass2, once label addresses are known
+    Pseudo(&'a PseudoOp, Vec<String>),
+    // Bytes, the offsets and text of values that depend on labels, and the size of each value
+    Data(Vec<u8>, Vec<(usize, String)>, u32),
+    // A nop the assembler put in the delay slot of the line's branch
+    DelaySlot,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Section {
+    Text,
+    Data,
+    KText,
+    KData,
+}
+
+pub(crate) fn rem_spaces<S: Into<String>>(inp_s: S) -> String {
+    let inp = inp_s.into();
+
+    if inp.is_empty() {
+        return String::from("");
+    }
+    let mut begin: usize = 0;
+    let mut end: usize = inp.len();
+    while inp[begin..begin + 1].eq(" ") || inp[begin..begin + 1].eq("\t") {
+        begin += 1;
+        if begin == end {
+            return String::from("");
+        }
+    }
+    while inp[end - 1..end].eq(" ") || inp[end - 1..end].eq("\t") {
+        end -= 1;
+    }
+    //let sub : &'a str = &inp[begin..end];
+    //let ret : String = String::from(sub);
+    String::from(&inp[begin..end])
+}
+
+/// Where the next byte of each segment goes.
+#[derive(Clone, Copy)]
+pub(crate) struct Counters {
+    text: u32,
+    data: u32,
+    extern_: u32,
+    ktext: u32,
+    kdata: u32,
+}
+
+impl Counters {
+    fn new(mem: &MemoryMap) -> Counters {
+        Counters {
+            text: mem.text,
+            data: mem.data,
+            extern_: mem.extern_,
+            ktext: mem.ktext,
+            kdata: mem.kdata,
+        }
+    }
+}
+
+/// Where statement `first` is, for a diagnostic about statement `from`: its line, and its file
+/// as well if that is another one.
+fn place(stmts: &[Stmt], files: &[SourceFile], first: usize, from: usize) -> String {
+    let first = &stmts[first];
+    if first.file == stmts[from].file {
+        format!("line {}", first.line)
+    } else {
+        format!("line {} of {}", first.line, files[first.file].name)
+    }
+}
+
+/// One file after pass1.
+pub(crate) struct Pass1<'a> {
+    // Each with the index of its statement in `stmts`
+    lines: Vec<(Line<'a>, usize, Section)>,
+    stmts: Vec<Stmt>,
+    // The file itself, then the files it includes
+    files: Vec<SourceFile>,
+    symbols: HashMap<String, u32>,
+    // The statement defining each label
+    symbol_stmts: HashMap<String, usize>,
+    // Labels declared with .globl or .extern and a size, and the statement declaring them
+    globals: Vec<(String, usize)>,
+    // Labels declared with .extern and no size, which another file must define
+    externs: Vec<(String, usize)>,
+    // Conditional branches that can be relaxed: the statement, its address and its target
+    branches: Vec<(usize, u32, String)>,
+    end: Counters,
+    diags: Vec<Diagnostic>,
+}
+
+impl Pass1<'_> {
+    /// An error about statement `idx`, found while linking, pointing at `at` in it.
+    fn error(&self, idx: usize, message: String, at: &str) -> Diagnostic {
+        let stmt = &self.stmts[idx];
+        Diagnostic {
+            file: self.files[stmt.file].name.clone(),
+            notes: stmt.notes.clone(),
+            ..Diagnostic::error(stmt.line, message).at(&stmt.source, at)
+        }
+    }
+}
+
+/// Assigns addresses to the statements of `assem`. The branches in `relaxed` become their forms
+/// in `relaxations`, over a `jr $at` when the statement maps to true.
+pub(crate) fn pass1<'a>(
+    name: &str,
+    assem: &str,
+    start: Counters,
+    pseudos: &'a HashMap<String, Vec<PseudoOp>>,
+    relaxations: &'a HashMap<String, Vec<PseudoOp>>,
+    relaxed: &HashMap<usize, bool>,
+    options: &Options,
+) -> Pass1<'a> {
+    let (isa, delayed) = (options.isa, options.delayed_branches);
+    let mut lines: Vec<(Line, usize, Section)> = vec![];
+    let mut labels: Vec<(String, usize)> = vec![];
+    let mut diags: Vec<Diagnostic> = vec![];
+
+    let mut lbl_adr: HashMap<String, u32> = HashMap::new();
+    let mut data_lbl_adr: HashMap<String, u32> = HashMap::new();
+
+    let mut cur_label: Option<String> = None;
+    let mut cur_section = Section::Text;
+    let mut auto_align = true;
+    // Under .set noreorder the delay slots are left to the programmer
+    let mut reorder = true;
+
+    let mut text_counter: u32 = start.text;
+    let mut data_counter: u32 = start.data;
+    let mut ktext_counter: u32 = start.ktext;
+    let mut kdata_counter: u32 = start.kdata;
+    let mut extern_counter: u32 = start.extern_;
+    let mut globals: Vec<(String, usize)> = vec![];
+    let mut externs: Vec<(String, usize)> = vec![];
+    let mut branches: Vec<(usize, u32, String)> = vec![];
+
+    let (stmts, files, pre_diags) = preprocess(name, assem, options);
+    diags.extend(pre_diags);
+    // Where the diagnostics about each statement start, to give them its notes at the end
+    let mut firsts = Vec::new();
+
+    for (idx, stmt) in stmts.iter().enumerate() {
+        firsts.push((diags.len(), idx));
+        let (line, curline) = (stmt.source.as_str(), stmt.line);
+
+        let (counter, adrs): (&mut u32, _) = match cur_section {
+            Section::Text => (&mut text_counter, &mut lbl_adr),
+            Section::Data => (&mut data_counter, &mut data_lbl_adr),
+            Section::KText => (&mut ktext_counter, &mut lbl_adr),
+            Section::KData => (&mut kdata_counter, &mut data_lbl_adr),
+        };
+
+        //println!("{}",curline);
+
+        // Comments are gone, constants replaced and macros expanded
+        let line_nc = rem_spaces(&stmt.text);
+        //println!("{}",line_nc);
+
+        if line_nc.is_empty() {
+            continue;
+        }
+
+        if line_nc[..1].ne(" ") && line_nc[..1].ne("\t") {
+            cur_label = None;
+        } else if cur_label.is_none() {
+            diags.push(Diagnostic::warning(curline, "Code indented without label."));
+        }
+
+        let line_nl = {
+            // A label is a single word before the first ':' outside quotes, so `.word 5:3`
+            // and `.asciiz "a:b"` have none
+            let pos_opt = find_unquoted(&line_nc, ':')
+                .filter(|&n| !line_nc[..n].contains(char::is_whitespace));
+            match pos_opt {
+                None => line_nc,
+                Some(pos) => {
+                    let lname = &line_nc[0..pos];
+
+                    if !is_label_name(lname) {
+                        diags.push(
+                            Diagnostic::error(
+                                curline,
+                                format!("Invalid label name \"{0}\" found.", lname),
+                            )
+                            .at(line, lname),
+                        );
+                    }
+
+                    let unique = {
+                        let mut fail = false;
+                        for (label, first) in &labels {
+                            if (*label).eq(&lname) {
+                                diags.push(
+                                    Diagnostic::error(
+                                        curline,
+                                        format!(
+                                            "Duplicate label {0} found, first was found on {1}.",
+                                            lname,
+                                            place(&stmts, &files, *first, idx)
+                                        ),
+                                    )
+                                    .at(line, lname),
+                                );
+                                fail = true;
+                                break;
+                            }
+                        }
+                        !fail
+                    };
+                    if unique {
+                        if lname.eq("START") {
+                            let mut upd_adr: HashMap<String, u32> = HashMap::new();
+                            {
+                                for (lbl, c) in &mut *adrs {
+                                    upd_adr.insert(lbl.into(), *c + 4);
+                                }
+                            }
+                            //for (lbl, c) in upd_adr {
+                            //    adrs.insert(lbl, c);
+                            //}
+                            *adrs = upd_adr;
+                        }
+                        labels.push((lname.into(), idx));
+                        adrs.insert(line_nc[0..pos].into(), *counter);
+                        cur_label = Some(line_nc[0..pos].into());
+                    }
+                    rem_spaces(&line_nc[pos + 1..])
+                }
+            }
+        };
+
+        if line_nl.is_empty() {
+            continue;
+        }
+        if line_nl[..1].eq(".") {
+            let (directive, dir_data) = match line_nl.find(char::is_whitespace) {
+                Some(n) => (&line_nl[0..n], &line_nl[n..]),
+                None => (&line_nl[..], ""),
+            };
+            //todo!("Directinges");
+            if directive.eq(".asciiz") || directive.eq(".ascii") {
+                let mut byte_vec: Vec<u8> = Vec::new();
+                let mut rest = dir_data.trim();
+                let mut fail = None;
+                // A list of strings, each terminated for .asciiz
+                while !rest.is_empty() {
+                    match parse_string(rest) {
+                        Ok((bytes, r)) => {
+                            byte_vec.extend(bytes);
+                            if directive.eq(".asciiz") {
+                                byte_vec.push(0);
+                            }
+                            rest = r.trim_start();
+                            if let Some(r) = rest.strip_prefix(',') {
+                                rest = r.trim_start();
+                                if rest.is_empty() {
+                                    fail = Some("Expected a string after ','".to_string());
+                                }
+                            } else if !rest.is_empty() {
+                                fail = Some(format!("Expected ',' before \"{}\"", rest));
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            fail = Some(e);
+                            break;
+                        }
+                    }
+                }
+                if dir_data.trim().is_empty() {
+                    fail = Some("Expected a string".to_string());
+                }
+                if let Some(e) = fail {
+                    diags.push(
+                        Diagnostic::error(curline, format!("{} in {}.", e, directive))
+                            .at(line, rest),
+                    );
+                    continue;
+                }
+                advance(counter, byte_vec.len() as u32, curline, &mut diags);
+                lines.push((Line::Data(byte_vec, vec![], 1), idx, cur_section));
+            } else if directive.eq(".globl") || directive.eq(".global") {
+                let names = split_items(dir_data);
+                if names.is_empty() {
+                    diags.push(Diagnostic::error(
+                        curline,
+                        format!("Expected a label after {}.", directive),
+                    ));
+                }
+                for name in names {
+                    if is_label_name(name) {
+                        globals.push((name.to_string(), idx));
+                    } else {
+                        diags.push(
+                            Diagnostic::error(
+                                curline,
+                                format!("Invalid label name \"{0}\" found.", name),
+                            )
+                            .at(line, name),
+                        );
+                    }
+                }
+            } else if directive.eq(".extern") {
+                // `.extern name size` reserves a global in the extern area, like MARS.
+                // Without a size it names a global that another file defines.
+                let items = split_items(dir_data);
+                let size = match items.get(1) {
+                    None => None,
+                    Some(size) => match parse_num(fold(size)) {
+                        Ok(n) if n >= 0 && items.len() == 2 => Some(n as u32),
+                        _ => {
+                            diags.push(
+                                Diagnostic::error(
+                                    curline,
+                                    format!("Invalid size \"{}\" for .extern.", size),
+                                )
+                                .at(line, size),
+                            );
+                            continue;
+                        }
+                    },
+                };
+                let name = match items.first() {
+                    Some(name) if is_label_name(name) => name.to_string(),
+                    _ => {
+                        diags.push(Diagnostic::error(
+                            curline,
+                            "Expected a label after .extern.",
+                        ));
+                        continue;
+                    }
+                };
+                match size {
+                    Some(size) => {
+                        if let Some((_, first)) = labels.iter().find(|l| l.0 == name) {
+                            diags.push(
+                                Diagnostic::error(
+                                    curline,
+                                    format!(
+                                        "Duplicate label {0} found, first was found on {1}.",
+                                        name,
+                                        place(&stmts, &files, *first, idx)
+                                    ),
+                                )
+                                .at(line, &name),
+                            );
+                            continue;
+                        }
+                        data_lbl_adr.insert(name.clone(), extern_counter);
+                        labels.push((name.clone(), idx));
+                        globals.push((name, idx));
+                        advance(&mut extern_counter, (size + 3) & !3, curline, &mut diags);
+                    }
+                    None => externs.push((name, idx)),
+                }
+            } else if let Some(section) = match directive {
+                ".text" => Some(Section::Text),
+                ".data" => Some(Section::Data),
+                ".ktext" => Some(Section::KText),
+                ".kdata" => Some(Section::KData),
+                _ => None,
+            } {
+                cur_section = section;
+                auto_align = true;
+                // An address moves the section on to it, as in `.ktext 0x80000180`
+                let arg = rem_spaces(dir_data);
+                if arg.is_empty() {
+                    continue;
+                }
+                let counter = match section {
+                    Section::Text => &mut text_counter,
+                    Section::Data => &mut data_counter,
+                    Section::KText => &mut ktext_counter,
+                    Section::KData => &mut kdata_counter,
+                };
+                let end = options.memory.segment_end(*counter);
+                match parse_num(fold(&arg)) {
+                    Ok(adr) if adr as u32 != *counter && adr as u32 as u64 >= end => diags.push(
+                        Diagnostic::error(
+                            curline,
+                            format!(
+                                "Address {0} is past the end of the segment for {1}, {2:#010x}.",
+                                arg, directive, end
+                            ),
+                        )
+                        .at(line, &arg),
+                    ),
+                    Ok(adr) if adr as u32 >= *counter => {
+                        let pad = adr as u32 - *counter;
+                        if pad > 0 {
+                            lines.push((
+                                Line::Data(vec![0; pad as usize], vec![], 1),
+                                idx,
+                                section,
+                            ));
+                        }
+                        *counter = adr as u32;
+                    }
+                    Ok(_) => diags.push(
+                        Diagnostic::error(
+                            curline,
+                            format!(
+                                "Address {0} is before the end of {1} so far, {2:#010x}.",
+                                arg, directive, *counter
+                            ),
+                        )
+                        .at(line, &arg),
+                    ),
+                    Err(_) => diags.push(
+                        Diagnostic::error(
+                            curline,
+                            format!("Invalid address \"{0}\" for {1}.", arg, directive),
+                        )
+                        .at(line, &arg),
+                    ),
+                }
+            } else if directive.eq(".align") || data_size(directive).is_some() {
+                let align = match data_size(directive) {
+                    Some(size) => {
+                        if auto_align {
+                            size
+                        } else {
+                            1
+                        }
+                    }
+                    None => match parse_num(fold(&rem_spaces(dir_data))) {
+                        Ok(0) => {
+                            // Like MARS, .align 0 turns automatic alignment off
+                            auto_align = false;
+                            1
+                        }
+                        Ok(n @ 1..=15) => 1 << n,
+                        _ => {
+                            diags.push(Diagnostic::error(
+                                curline,
+                                format!("Invalid alignment \"{}\".", rem_spaces(dir_data)),
+                            ));
+                            continue;
+                        }
+                    },
+                };
+                let pad = (align - *counter % align) % align;
+                if pad > 0 {
+                    lines.push((
+                        Line::Data(vec![0; pad as usize], vec![], 1),
+                        idx,
+                        cur_section,
+                    ));
+                    advance(counter, pad, curline, &mut diags);
+                    // A label on the same line names the aligned value, not the padding
+                    if let Some(lname) = &cur_label {
+                        adrs.insert(lname.clone(), *counter);
+                    }
+                }
+                if directive.ne(".align") {
+                    match get_data(directive, dir_data, room(*counter, &options.memory)) {
+                        Ok((bytes, fixups)) => {
+                            advance(counter, bytes.len() as u32, curline, &mut diags);
+                            let size = data_size(directive).unwrap_or(1);
+                            lines.push((Line::Data(bytes, fixups, size), idx, cur_section));
+                        }
+                        Err((item, e)) => {
+                            diags.push(Diagnostic::error(curline, e).at(line, &item));
+                        }
+                    }
+                }
+            } else if directive.eq(".set") && ["reorder", "noreorder"].contains(&dir_data.trim()) {
+                reorder = dir_data.trim() == "reorder";
+            } else if directive.eq(".space") {
+                match parse_num(fold(&rem_spaces(dir_data))) {
+                    Ok(n) if n as u64 > room(*counter, &options.memory) => diags.push(
+                        Diagnostic::error(
+                            curline,
+                            ".space runs past the end of its segment.".to_string(),
+                        )
+                        .at(line, dir_data.trim()),
+                    ),
+                    Ok(n) if n >= 0 => {
+                        advance(counter, n as u32, curline, &mut diags);
+                        lines.push((Line::Data(vec![0; n as usize], vec![], 1), idx, cur_section));
+                    }
+                    _ => diags.push(Diagnostic::error(
+                        curline,
+                        format!("Invalid size \"{}\" for .space.", rem_spaces(dir_data)),
+                    )),
+                }
+            } else {
+                diags.push(
+                    Diagnostic::warning(
+                        curline,
+                        format!("Unknown directive {} ignored.", directive),
+                    )
+                    .at(line, directive),
+                );
+            }
+            continue;
+        }
+
+        //println!("{}",line_nl);
+
+        let toks = tokenize(&line_nl);
+        let code: &InstrCode = find_code(&toks, isa);
+        // Basic instructions come first, then the first pseudo-op whose example fits. Labels fit
+        // 32-bit immediates as well, but the forms that take them as labels keep relocations.
+        let pseudo = match pseudos.get(&toks[0]) {
+            Some(ops) => ops
+                .iter()
+                .filter(|op| op.fits(&toks))
+                .min_by_key(|op| op.label_immediates(&toks)),
+            None => None,
+        };
+        let known = code.code != -1 && code.valid_in(isa);
+        let basic = known && fits(&get_pattern(code.operands()), &toks[1..]);
+        // A register of the wrong class, such as $f0 in add, would otherwise pass as its number
+        let misplaced = match basic || pseudo.is_some() {
+            true => None,
+            false => known
+                .then(|| get_pattern(code.operands()))
+                .into_iter()
+                .chain(
+                    pseudos
+                        .get(&toks[0])
+                        .into_iter()
+                        .flatten()
+                        .map(|op| op.pattern().to_vec()),
+                )
+                .find_map(|pattern| wrong_class(&pattern, &toks[1..])),
+        };
+
+        // Conditional branches become their relaxed forms once they are found not to reach
+        let relaxable = known && (basic || pseudo.is_none()) && relaxations.contains_key(&toks[0]);
+        if relaxable {
+            branches.push((idx, *counter, toks[toks.len() - 1].clone()));
+        }
+        let relaxed_op = relaxed
+            .get(&idx)
+            .filter(|_| relaxable)
+            .map(|far| &relaxations[&toks[0]][*far as usize]);
+
+        let branch = if let Some((reg, class)) = misplaced {
+            let (_, classes) = register(reg).unwrap_or((0, &[]));
+            diags.push(
+                Diagnostic::error(
+                    curline,
+                    format!(
+                        "{0} is a {1} register, but {2} takes a {3} register here.",
+                        reg,
+                        classes.first().map_or("", |c| c.name()),
+                        toks[0],
+                        class.name()
+                    ),
+                )
+                .at(line, reg),
+            );
+            false
+        } else if let Some(op) = relaxed_op {
+            advance(counter, 4 * op.len(options), curline, &mut diags);
+            lines.push((Line::Pseudo(op, toks), idx, cur_section));
+            true
+        } else if known && (basic || pseudo.is_none()) {
+            match get_arguments(&line_nl[toks[0].len()..]) {
+                Ok(adata) => lines.push((Line::Instr(code, adata), idx, cur_section)),
+                Err(e) => diags.push(Diagnostic::error(curline, e)),
+            }
+
+            advance(counter, 4, curline, &mut diags);
+            code.has_delay_slot()
+        } else if let Some(op) = pseudo {
+            advance(counter, 4 * op.len(options), curline, &mut diags);
+
+            lines.push((Line::Pseudo(op, toks), idx, cur_section));
+            op.ends_in_branch()
+        } else if code.code != -1 {
+            diags.push(Diagnostic::error(curline, code.isa_error(isa)).at(line, &toks[0]));
+            false
+        } else if pseudos.contains_key(&toks[0]) {
+            diags.push(
+                Diagnostic::error(
+                    curline,
+                    format!("Operands do not fit any form of {}.", toks[0]),
+                )
+                .at(line, &toks[0]),
+            );
+            false
+        } else {
+            diags.push(
+                Diagnostic::error(curline, format!("Invalid instruction \"{}\".", toks[0]))
+                    .at(line, &toks[0]),
+            );
+            false
+        };
+        if branch && delayed && reorder {
+            lines.push((Line::DelaySlot, idx, cur_section));
+            advance(counter, 4, curline, &mut diags);
+        }
+    }
+    add_notes(&mut diags, &firsts, &stmts, &files);
+
+    lbl_adr.extend(data_lbl_adr);
+
+    Pass1 {
+        lines,
+        stmts,
+        files,
+        symbols: lbl_adr,
+        symbol_stmts: labels.into_iter().collect(),
+        globals,
+        externs,
+        branches,
+        end: Counters {
+            text: text_counter,
+            data: data_counter,
+            extern_: extern_counter,
+            ktext: ktext_counter,
+            kdata: kdata_counter,
+        },
+        diags,
+    }
+}
+
+/// Adds to `relaxed` the branches of `unit` that cannot reach their targets, and marks those
+/// whose targets are outside the region of the `j` that replaces them. Returns whether anything
+/// changed. Branches stay relaxed once they are, so that this settles.
+fn relax(unit: &Pass1, relaxed: &mut HashMap<usize, bool>, delayed: bool) -> bool {
+    let mut changed = false;
+    for (idx, adr, target) in &unit.branches {
+        // Targets defined in other files are not known yet, and are left to pass2
+        let target = match Expr::parse(target).and_then(|e| e.value(&unit.symbols, *adr)) {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        // The region of the jump is that of its delay slot
+        let slot = adr.wrapping_add(if delayed { 12 } else { 8 });
+        let far = (target ^ slot) & 0xf000_0000 != 0;
+        let words = (target.wrapping_sub(adr.wrapping_add(4)) as i32) >> 2;
+        let reaches = (-0x8000..0x8000).contains(&words);
+        match relaxed.get(idx) {
+            Some(true) => {}
+            Some(false) if !far => {}
+            None if reaches || target % 4 != 0 => {}
+            _ => {
+                relaxed.insert(*idx, far);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+pub(crate) enum Encoding {
+    // o, s, t, d, a, f
+    Register(i8, i8, i8, i8, i8, i8),
+    Immediate(i8, i8, i8, i16),
+    Jump(i8, i32),
+}
+
+pub(crate) fn get_bin(enc: Encoding) -> Vec<u8> {
+    match enc {
+        Encoding::Register(o, s, t, d, a, f) => vec![
+            ((o << 2) + (s >> 3)) as u8,
+            ((s << 5) + t) as u8,
+            ((d << 3) + (a >> 2)) as u8,
+            ((a << 6) + f) as u8,
+        ],
+        Encoding::Immediate(o, s, t, i) => {
+            //println!("{}", o);
+            //println!("{}", o<<2);
+            vec![
+                ((o << 2) + (s >> 3)) as u8,
+                ((s << 5) + t) as u8,
+                (i >> 8) as u8,
+                (i) as u8,
+            ]
+        }
+        Encoding::Jump(o, i) => vec![
+            ((o << 2) + ((i >> 24) & 3) as i8) as u8,
+            (i >> 16) as u8,
+            (i >> 8) as u8,
+            (i) as u8,
+        ],
+    }
+}
+
+/// Size of each value emitted by a data directive, which is also its natural alignment.
+fn data_size(directive: &str) -> Option<u32> {
+    match directive {
+        ".byte" => Some(1),
+        ".half" => Some(2),
+        ".word" | ".float" => Some(4),
+        ".double" => Some(8),
+        _ => None,
+    }
+}
+
+type DataBytes = (Vec<u8>, Vec<(usize, String)>);
+
+/// The bytes left in the segment from `counter` on
+fn room(counter: u32, mem: &MemoryMap) -> u64 {
+    mem.segment_end(counter) - counter as u64
+}
+
+/// Moves a section counter on by `n` bytes, with an error on `curline` if that takes it past
+/// the end of the address space
+fn advance(counter: &mut u32, n: u32, curline: u32, diags: &mut Vec<Diagnostic>) {
+    match counter.checked_add(n) {
+        Some(next) => *counter = next,
+        None => diags.push(Diagnostic::error(
+            curline,
+            "Segment runs past the end of the address space.",
+        )),
+    }
+}
+
+/// Encodes the values of a .byte/.half/.word/.float/.double directive. Each value may be followed
+/// by `:count` to repeat it. Values that use labels are left as zero and returned with their
+/// offsets so pass2 can fill them in. On failure returns the value at fault, or the directive
+/// if it has none, along with the reason. `room` bounds the total size.
+fn get_data(directive: &str, dir_data: &str, room: u64) -> Result<DataBytes, (String, String)> {
+    let size = data_size(directive).unwrap_or(1) as usize;
+    let mut bytes = Vec::new();
+    let mut fixups = Vec::new();
+
+    for item in split_items(dir_data) {
+        let fail = |e: String| (item.to_string(), e);
+        let (value, count) = match find_unquoted(item, ':') {
+            None => (item, 1),
+            Some(n) => match parse_num(&item[n + 1..]) {
+                Ok(count) if count > 0 => (&item[..n], count as usize),
+                _ => {
+                    return Err(fail(format!(
+                        "Invalid repeat count \"{}\".",
+                        &item[n + 1..]
+                    )))
+                }
+            },
+        };
+
+        let mut label = None;
+        let one: Vec<u8> = match directive {
+            ".float" => match value.parse::<f32>() {
+                Ok(f) if f.is_finite() => f.to_be_bytes().to_vec(),
+                Ok(_) => return Err(fail(format!("Value {} does not fit in .float.", value))),
+                Err(_) => return Err(fail(format!("Invalid float \"{}\".", value))),
+            },
+            ".double" => match value.parse::<f64>() {
+                Ok(f) if f.is_finite() => f.to_be_bytes().to_vec(),
+                Ok(_) => return Err(fail(format!("Value {} does not fit in .double.", value))),
+                Err(_) => return Err(fail(format!("Invalid double \"{}\".", value))),
+            },
+            _ => match parse_num(fold(value)) {
+                Ok(n) => {
+                    // Either the signed or the unsigned range is fine
+                    let bits = 8 * size as u32;
+                    if bits < 32 && (n < -(1 << (bits - 1)) || n >= (1 << bits)) {
+                        return Err(fail(format!(
+                            "Value {} does not fit in {}.",
+                            value, directive
+                        )));
+                    }
+                    n.to_be_bytes()[4 - size..].to_vec()
+                }
+                Err(_) if is_expr(value) => {
+                    label = Some(value.to_string());
+                    vec![0; size]
+                }
+                Err(_) => {
+                    return Err(fail(format!(
+                        "Invalid value \"{}\" in {}.",
+                        value, directive
+                    )))
+                }
+            },
+        };
+
+        if (bytes.len() + count * one.len()) as u64 > room {
+            return Err(fail(format!(
+                "{} runs past the end of its segment.",
+                directive
+            )));
+        }
+        for _ in 0..count {
+            if let Some(l) = &label {
+                fixups.push((bytes.len(), l.clone()));
+            }
+            bytes.extend(&one);
+        }
+    }
+    if bytes.is_empty() {
+        return Err((
+            directive.to_string(),
+            format!("{} needs at least one value.", directive),
+        ));
+    }
+    Ok((bytes, fixups))
+}
+
+/// The relocation for a basic instruction at `adr` that uses a label: the jump field of
+/// `j`/`jal`, a branch offset, or an immediate from `%hi` or `%lo`. Expressions that are not a
+/// label plus a constant have none.
+fn label_reloc(
+    instr: &InstrCode,
+    args: &Args<Arg>,
+    lbl_adr: &HashMap<String, u32>,
+    adr: u32,
+) -> Option<Relocation> {
+    let expr = args.items().into_iter().find_map(|a| match a {
+        Arg::Expr(e) => Expr::parse(e).ok(),
+        _ => None,
+    })?;
+    let (kind, expr) = match (expr, &instr.syntax) {
+        (Expr::Part(Part::Hi, e), _) => (RelocKind::Hi16, *e),
+        (Expr::Part(Part::Lo, e), _) => (RelocKind::Lo16, *e),
+        (Expr::Part(..), _) => return None,
+        (e, Syntax::Jump) => (RelocKind::Mips26, e),
+        (
+            e,
+            Syntax::Branch
+            | Syntax::BranchZ
+            | Syntax::RegImmBranch
+            | Syntax::FpBranch
+            | Syntax::FpBranchR6,
+        ) => (RelocKind::Pc16, e),
+        (e, Syntax::CompactBranchZ) => (RelocKind::Pc21, e),
+        (e, Syntax::CompactJump) => (RelocKind::Pc26, e),
+        _ => return None,
+    };
+    let symbol = expr.base_symbol()?;
+    Some(Relocation {
+        address: adr,
+        kind,
+        symbol: symbol.to_string(),
+        target: *lbl_adr.get(symbol)?,
+        value: expr.value(lbl_adr, adr).ok()?,
+    })
+}
+
+/// The code of each [`Section`], then what pass2 found out about it.
+pub(crate) type Pass2 = (
+    [Vec<u8>; 4],
+    Vec<SourceLine>,
+    Vec<Statement>,
+    Vec<Relocation>,
+    Vec<Diagnostic>,
+);
+
+pub(crate) fn pass2(
+    lines: Vec<(Line, usize, Section)>,
+    stmts: &[Stmt],
+    files: &[SourceFile],
+    lbl_adr: &HashMap<String, u32>,
+    start: Counters,
+    file_ids: &[usize],
+    options: &Options,
+) -> Pass2 {
+    let isa = options.isa;
+    let mut code: [Vec<u8>; 4] = Default::default();
+    let starts = [start.text, start.data, start.ktext, start.kdata];
+    let mut source_map = Vec::new();
+    let mut statements = Vec::new();
+    let mut relocations = Vec::new();
+    let mut diags = Vec::new();
+    let mut firsts = Vec::new();
+
+    for (i, idx, sect) in &lines {
+        firsts.push((diags.len(), *idx));
+        let stmt = &stmts[*idx];
+        let (ln, src) = (&stmt.line, stmt.source.as_str());
+        let machine_code = &mut code[*sect as usize];
+        // Past the end of the address space pass1 has already failed, so the address only
+        // has to not panic
+        let counter = (machine_code.len() as u32).wrapping_add(starts[*sect as usize]);
+        let before = machine_code.len();
+        let mut expansion = Vec::new();
+        let mut unit = 4;
+        match i {
+            Line::Instr(instr, args) => {
+                //println!("{}:{}", counter, instr.name);
+                source_map.push(SourceLine {
+                    address: counter,
+                    file: file_ids[stmt.site.0],
+                    line: stmt.site.1,
+                });
+                let enc = get_enc(instr, args.clone(), lbl_adr, *ln, counter, src);
+                /*
+                match enc {
+                    Encoding::Register(o, s, t, d, a, f) => {println!("{},{},{},{},{},{}", o,s,t,d,a,f);}
+                    Encoding::Immediate(o, s, t, i) => {println!("{},{},{},{}",o, s,t,i);}
+                    Encoding::Jump(o, i) => {println!("{},{}", o, i);}
+                }
+                 */
+                relocations.extend(label_reloc(instr, args, lbl_adr, counter));
+                match enc {
+                    Ok(enc) => machine_code.extend(get_bin(enc)),
+                    Err(d) => {
+                        diags.push(d);
+                        // Keep later addresses right
+                        machine_code.extend([0; 4]);
+                    }
+                }
+            }
+            Line::Pseudo(op, toks) => {
+                let basics = match op.expand(toks, lbl_adr, *ln, counter, src, options) {
+                    Ok(basics) => basics,
+                    Err(d) => {
+                        diags.push(d);
+                        vec![]
+                    }
+                };
+                if basics.is_empty() {
+                    machine_code.extend(vec![0; 4 * op.len(options) as usize]);
+                }
+                for (n, (basic, part)) in basics.iter().enumerate() {
+                    let adr = counter + 4 * n as u32;
+                    source_map.push(SourceLine {
+                        address: adr,
+                        file: file_ids[stmt.site.0],
+                        line: stmt.site.1,
+                    });
+                    if let Some((kind, symbol, value)) = part {
+                        relocations.push(Relocation {
+                            address: adr,
+                            kind: *kind,
+                            symbol: symbol.clone(),
+                            target: lbl_adr[symbol],
+                            value: *value,
+                        });
+                    }
+                    let line_args = basic.split_once(' ').map_or("", |b| b.1);
+                    let instr = find_code(&tokenize(basic), isa);
+                    let enc = get_arguments(line_args)
+                        .map_err(|e| Diagnostic::error(*ln, e))
+                        .and_then(|args| {
+                            if instr.code == -1 || instr.valid_in(isa) {
+                                return Ok(args);
+                            }
+                            let mut e = instr.isa_error(isa);
+                            if op.name != instr.name {
+                                e += &format!(" It is used by {}.", op.name);
+                            }
+                            Err(Diagnostic::error(*ln, e).at(src, &toks[0]))
+                        })
+                        .and_then(|args| {
+                            relocations.extend(label_reloc(instr, &args, lbl_adr, adr));
+                            get_enc(instr, args, lbl_adr, *ln, adr, src)
+                        });
+                    match enc {
+                        Ok(enc) => machine_code.extend(get_bin(enc)),
+                        Err(d) => {
+                            diags.push(d);
+                            machine_code.extend([0; 4]);
+                        }
+                    }
+                }
+                expansion = basics.into_iter().map(|b| b.0).collect();
+            }
+            Line::Data(data, fixups, size) => {
+                unit = *size;
+                let start = machine_code.len();
+                for i in data {
+                    machine_code.push(*i);
+                }
+                let size = *size as usize;
+                for (off, value) in fixups {
+                    let at = counter + *off as u32;
+                    match Expr::parse(value).and_then(|e| Ok((e.eval(lbl_adr, at)?, e))) {
+                        Ok((n, _)) if n < -(1 << (8 * size - 1)) || n >= 1 << (8 * size) => diags
+                            .push(
+                                Diagnostic::error(
+                                    *ln,
+                                    format!(
+                                        "Value {} ({}) does not fit in {}.",
+                                        value,
+                                        n,
+                                        match size {
+                                            1 => ".byte",
+                                            2 => ".half",
+                                            _ => ".word",
+                                        }
+                                    ),
+                                )
+                                .at(src, value),
+                            ),
+                        Ok((n, e)) => {
+                            let bytes = (n as u32).to_be_bytes();
+                            machine_code[start + off..start + off + size]
+                                .copy_from_slice(&bytes[4 - size..]);
+                            if let Some(symbol) = e.base_symbol().filter(|_| size == 4) {
+                                relocations.push(Relocation {
+                                    address: at,
+                                    kind: RelocKind::Mips32,
+                                    symbol: symbol.to_string(),
+                                    target: lbl_adr[symbol],
+                                    value: n as u32,
+                                });
+                            }
+                        }
+                        Err(e) => {
+                            diags.push(Diagnostic::error(*ln, format!("{}.", e)).at(src, value))
+                        }
+                    }
+                }
+            }
+            Line::DelaySlot => {
+                source_map.push(SourceLine {
+                    address: counter,
+                    file: file_ids[stmt.site.0],
+                    line: stmt.site.1,
+                });
+                machine_code.extend([0; 4]);
+                expansion.push("nop".to_string());
+            }
+        }
+        // Listings show the instructions of a macro under its call
+        if expansion.is_empty() && !stmt.notes.is_empty() && matches!(i, Line::Instr(..)) {
+            expansion.push(rem_spaces(split_label(&stmt.text).1));
+        }
+        statements.push(Statement {
+            file: file_ids[stmt.site.0],
+            line: stmt.site.1,
+            address: counter,
+            bytes: machine_code[before..].to_vec(),
+            unit,
+            expansion,
+        });
+    }
+
+    add_notes(&mut diags, &firsts, stmts, files);
+
+    /*
+    for (i, l) in lbl_adr {
+        println!("{} {}", i, l);
+    }
+     */
+
+    (code, source_map, statements, relocations, diags)
+}
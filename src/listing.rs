@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::slice::Iter;
+
+use crate::codes::{split_label, strip_comment, tokenize};
+use crate::expr::Expr;
+use crate::{Program, Statement};
+
+/// File and line each symbol is used on, by index into `program.symbols`. A use is any label
+/// named in an operand, so labels in data directives and expressions count as well as those in
+/// instructions.
+fn uses(program: &Program, sources: &[&str]) -> BTreeMap<usize, Vec<(usize, u32)>> {
+    let mut uses: BTreeMap<usize, Vec<(usize, u32)>> = BTreeMap::new();
+    let included = program.includes.iter().map(|i| (i.file, i.source.as_str()));
+    for (file, source) in sources.iter().copied().enumerate().chain(included) {
+        for (n, line) in source.lines().enumerate() {
+            let code = strip_comment(line);
+            // Skip past a label definition
+            let toks = tokenize(split_label(code).1);
+            // Declarations are not uses
+            if toks
+                .first()
+                .is_some_and(|t| [".globl", ".global", ".extern"].contains(&t.as_str()))
+            {
+                continue;
+            }
+            let names = toks.iter().skip(1).flat_map(|tok| match Expr::parse(tok) {
+                Ok(e) => e.symbols().into_iter().map(String::from).collect(),
+                Err(_) => vec![tok.clone()],
+            });
+            for name in names {
+                if let Some(sym) = program.lookup(file, &name) {
+                    let lines = uses.entry(sym).or_default();
+                    if lines.last() != Some(&(file, n as u32 + 1)) {
+                        lines.push((file, n as u32 + 1));
+                    }
+                }
+            }
+        }
+    }
+    uses
+}
+
+impl Program {
+    /// The file given to the assembler that `file` is, or was included from.
+    fn unit(&self, mut file: usize) -> usize {
+        while let Some(include) = self.includes.iter().find(|i| i.file == file) {
+            file = include.parent;
+        }
+        file
+    }
+
+    /// The symbol `name` means in `file`: a label of the same unit, otherwise a global one.
+    fn lookup(&self, file: usize, name: &str) -> Option<usize> {
+        let syms = &self.symbols;
+        let unit = self.unit(file);
+        syms.iter()
+            .position(|s| s.name == name && self.unit(s.file) == unit)
+            .or_else(|| syms.iter().position(|s| s.name == name && s.global))
+    }
+
+    /// A source position as shown in the cross-reference: the line, with the file in front when
+    /// there are several.
+    fn place(&self, file: usize, line: u32) -> String {
+        if self.files.len() > 1 {
+            format!("{}:{}", self.files[file], line)
+        } else {
+            line.to_string()
+        }
+    }
+
+    /// Lists the lines of `source`, which is `file`, taking the statements on them from
+    /// `statements`. The files it includes are listed after their `.include` lines.
+    fn list_file(
+        &self,
+        file: usize,
+        source: &str,
+        statements: &mut Peekable<Iter<Statement>>,
+        out: &mut String,
+    ) {
+        if self.files.len() > 1 {
+            *out += &format!("File {}\n", self.files[file]);
+        }
+        for (n, src) in source.lines().enumerate() {
+            let line = n as u32 + 1;
+            let mut rows: Vec<(u32, String, String)> = Vec::new();
+            while let Some(st) = statements.next_if(|st| st.file == file && st.line == line) {
+                if st.expansion.is_empty() {
+                    for (i, chunk) in st.bytes.chunks(4).enumerate() {
+                        rows.push((st.address + 4 * i as u32, hex::encode(chunk), String::new()));
+                    }
+                } else {
+                    // The source goes on a row of its own, unless the line's branch has it
+                    if rows.is_empty() {
+                        rows.push((st.address, String::new(), String::new()));
+                    }
+                    for (i, (basic, word)) in
+                        st.expansion.iter().zip(st.bytes.chunks(4)).enumerate()
+                    {
+                        rows.push((
+                            st.address + 4 * i as u32,
+                            hex::encode(word),
+                            format!("    {}", basic),
+                        ));
+                    }
+                }
+            }
+
+            let first = match rows.first() {
+                None => format!("{:>4}                        {}", line, src),
+                Some((adr, code, _)) => {
+                    format!("{:>4}  {:#010x}  {:<8}  {}", line, adr, code, src)
+                }
+            };
+            *out += first.trim_end();
+            out.push('\n');
+            for (adr, code, text) in rows.iter().skip(1) {
+                let row = format!("      {:#010x}  {:<8}  {}", adr, code, text);
+                *out += row.trim_end();
+                out.push('\n');
+            }
+
+            for include in &self.includes {
+                if include.parent == file && include.line == line {
+                    self.list_file(include.file, &include.source, statements, out);
+                    // Back to the rest of this file
+                    if n + 1 < source.lines().count() {
+                        *out += &format!("File {}\n", self.files[file]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists every line of `sources`, one per file given to the assembler, next to its address
+    /// and the words it assembled to, with the basic instructions of each pseudo-op under it and
+    /// included files after their `.include`. A symbol table and a cross-reference of where each
+    /// label is defined and used come after.
+    pub fn listing(&self, sources: &[&str]) -> String {
+        let mut out = String::from("Line  Address     Code      Source\n");
+        let mut statements = self.statements.iter().peekable();
+        for (file, source) in sources.iter().enumerate() {
+            self.list_file(file, source, &mut statements, &mut out);
+        }
+
+        let mut symbols: Vec<usize> = (0..self.symbols.len()).collect();
+        symbols.sort_by_key(|i| (self.symbols[*i].address, &self.symbols[*i].name));
+        let width = self
+            .symbols
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+
+        out += "\nSymbol table\n";
+        out += "  Address     Name\n";
+        for i in &symbols {
+            let sym = &self.symbols[*i];
+            let scope = if sym.global {
+                "global".to_string()
+            } else if self.files.len() > 1 {
+                self.files[sym.file].clone()
+            } else {
+                String::new()
+            };
+            let row = format!(
+                "  {:#010x}  {:<width$}  {}",
+                sym.address,
+                sym.name,
+                scope,
+                width = width
+            );
+            out += row.trim_end();
+            out.push('\n');
+        }
+
+        let uses = uses(self, sources);
+        out += "\nCross-reference\n";
+        out += &format!("  {:<width$}  Defined  Used\n", "Name", width = width);
+        symbols.sort_by_key(|i| (&self.symbols[*i].name, self.symbols[*i].file));
+        for i in symbols {
+            let sym = &self.symbols[i];
+            let defined = if sym.line == 0 {
+                String::new()
+            } else {
+                self.place(sym.file, sym.line)
+            };
+            let used: Vec<String> = uses
+                .get(&i)
+                .into_iter()
+                .flatten()
+                .map(|(file, line)| self.place(*file, *line))
+                .collect();
+            let row = format!(
+                "  {:<width$}  {:<7}  {}",
+                sym.name,
+                defined,
+                used.join(", "),
+                width = width
+            );
+            out += row.trim_end();
+            out.push('\n');
+        }
+        out
+    }
+}
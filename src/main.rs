@@ -1,356 +1,235 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use assembler_rust::{
+    assemble_files, disassemble, read_bo, read_ho, Cpu, Diagnostic, ElfKind, Endian, Isa,
+    MemoryMap, Options, Program, Stop, ISAS, PRESETS,
+};
 use to_binary::BinaryString;
 
-use crate::codes::{get_arguments, get_enc, Arg, Args, Syntax};
-use crate::tables::{get_code, get_ops, InstrCode};
-
-mod codes;
-mod tables;
-
 extern crate rev_slice;
 
 // I don't know much about licenses, feel free to use this but you probably shouldn't.
 
-enum Line<'a> {
-    Instr(&'a InstrCode<'a>, Args<Arg>),
-    Label(String),
-    Data(Vec<u8>),
-}
-
-#[derive(Clone, Copy)]
-enum Section {
-    Text,
-    Data,
-}
-
-pub fn rem_spaces<S: Into<String>>(inp_s: S) -> String {
-    let inp = inp_s.into();
-
-    if inp.len() == 0 {
-        return String::from("");
-    }
-    let mut begin: usize = 0;
-    let mut end: usize = inp.len();
-    while inp[begin..begin + (1 as usize)].eq(" ") || inp[begin..begin + (1 as usize)].eq("\t") {
-        begin += 1;
-        if begin == end {
-            return String::from("");
-        }
-    }
-    while inp[end - 1..end].eq(" ") || inp[end - 1..end].eq("\t") {
-        end -= 1;
+/// Splits the arguments into flags with their values and the files to assemble.
+fn get_cmd_args(args: Vec<String>) -> (HashMap<String, String>, Vec<String>) {
+    let mut arg_type: String = "file".into();
+    let mut argmap = HashMap::new();
+    let mut files = Vec::new();
+    for arg in args {
+        arg_type = if arg == "-o" {
+            "outfile".into()
+        } else if arg == "-p" {
+            "pseudo".into()
+        } else if arg == "-m" {
+            "memory".into()
+        } else if arg == "-n" {
+            "steps".into()
+        } else if arg == "-l" {
+            "listing".into()
+        } else if arg == "-f" {
+            "format".into()
+        } else if arg == "--isa" {
+            "isa".into()
+        } else if arg == "-I" {
+            "include".into()
+        } else if arg == "-D" {
+            "define".into()
+        } else if arg == "--delayed-branches" {
+            // A switch, with no value
+            argmap.insert("delayed".into(), String::new());
+            "file".into()
+        } else if arg_type == "file" {
+            files.push(arg);
+            "file".into()
+        } else if arg_type == "include" || arg_type == "define" {
+            // These can be given more than once, so their values are kept one per line
+            argmap
+                .entry(arg_type)
+                .and_modify(|v: &mut String| *v += &format!("\n{}", arg))
+                .or_insert(arg);
+            "file".into()
+        } else {
+            argmap.insert(arg_type, arg);
+            "file".into()
+        };
     }
-    //let sub : &'a str = &inp[begin..end];
-    //let ret : String = String::from(sub);
-    return String::from(&inp[begin..end]);
+    (argmap, files)
 }
 
-fn pass1(
-    assem: &String,
-    start_text_opt: Option<u32>,
-) -> (Vec<(Line, u32, Section)>, HashMap<String, u32>, u32, u32) {
-    let mut lines: Vec<(Line, u32, Section)> = vec![];
-    let mut labels: Vec<(String, u32)> = vec![];
-
-    let mut lbl_adr: HashMap<String, u32> = HashMap::new();
-    let mut data_lbl_adr: HashMap<String, u32> = HashMap::new();
-
-    let mut cur_label: Option<String> = None;
-    let mut cur_section = Section::Text;
-
-    let start_text = match start_text_opt {
-        None => 0x1000,
-        Some(n) => n,
-    };
-    let mut text_counter: u32 = start_text;
-    let mut data_counter: u32 = start_text;
-
-    let mut curline = 0;
-    for line in assem.lines() {
-        curline += 1;
-
-        let (counter, adrs): (&mut u32, _) = match cur_section {
-            Section::Text => (&mut text_counter, &mut lbl_adr),
-            Section::Data => (&mut data_counter, &mut data_lbl_adr),
+/// Prints diagnostics and their notes to stderr, quoting the lines they point at. `sources` are
+/// the names and texts of the files being assembled; other files are read as needed. Returns
+/// whether any of them were errors.
+fn report(diags: &[Diagnostic], sources: &[(&str, &str)]) -> bool {
+    let mut files: HashMap<&str, String> = HashMap::new();
+    for d in diags
+        .iter()
+        .flat_map(|d| std::iter::once(d).chain(&d.notes))
+    {
+        let text = match sources.iter().find(|s| s.0 == d.file) {
+            Some(s) => s.1,
+            None => files
+                .entry(&d.file)
+                .or_insert_with(|| std::fs::read_to_string(&d.file).unwrap_or_default()),
         };
-
-        //println!("{}",curline);
-
-        let line_nc_dirty = {
-            // Removes comments, which start with #
-            let pos_opt: Option<usize> = line.find('#');
-            match pos_opt {
-                Some(n) => &line[0..n],
-                None => line,
-            }
+        let line = match d.line {
+            0 => "",
+            n => text.lines().nth(n as usize - 1).unwrap_or(""),
         };
-        let line_nc = rem_spaces(line_nc_dirty);
-        //println!("{}",line_nc);
+        eprintln!("{}", d.render(line));
+    }
+    diags.iter().any(|d| d.is_error())
+}
 
-        if line_nc.len() == 0 {
-            continue;
+/// Finds the memory map named by `-m`, which is either a preset or a config file.
+fn get_memory(arg: &str) -> MemoryMap {
+    if let Some(map) = MemoryMap::preset(arg) {
+        return map;
+    }
+    let config = match std::fs::read_to_string(arg) {
+        Ok(config) => config,
+        Err(_) => {
+            let names: Vec<&str> = PRESETS.iter().map(|p| p.0).collect();
+            eprintln!(
+                "error: \"{0}\" is neither a memory preset ({1}) nor a readable file.",
+                arg,
+                names.join(", ")
+            );
+            std::process::exit(1);
         }
-
-        if line_nc[..1].ne(" ") && line_nc[..1].ne("\t") {
-            cur_label = None;
-        } else if cur_label == None {
-            println!("Code indented without label\nLine {0}: {1}", curline, line);
+    };
+    match MemoryMap::parse(&config) {
+        Ok(map) => map,
+        Err(mut d) => {
+            d.file = arg.to_string();
+            report(&[d], &[(arg, &config)]);
+            std::process::exit(1);
         }
+    }
+}
 
-        let line_nl = {
-            let pos_opt: Option<usize> = line_nc.find(':');
-            match pos_opt {
-                None => line_nc,
-                Some(pos) => {
-                    let lname = &line_nc[0..pos];
-
-                    if let Some(_) = lname.find(" ") {
-                        println!(
-                            "Invalid label name \"{0}\" found.\nLine {1}: {2}",
-                            lname, curline, line
-                        );
-                    }
-
-                    if {
-                        let mut fail = false;
-                        for (label, lline) in &labels {
-                            if (*label).eq(&lname) {
-                                println!("Duplicate label {0} found.\nFirst was found at {1} and second was found at {2}.", lname, lline, curline);
-                                fail = true;
-                                break;
-                            }
-                        }
-                        !fail
-                    } {
-                        if lname.eq("START") {
-                            let mut upd_adr: HashMap<String, u32> = HashMap::new();
-                            {
-                                for (lbl, c) in &mut *adrs {
-                                    upd_adr.insert(lbl.into(), *c + 4);
-                                }
-                            }
-                            //for (lbl, c) in upd_adr {
-                            //    adrs.insert(lbl, c);
-                            //}
-                            *adrs = upd_adr;
-                        }
-                        lines.push((Line::Label(lname.into()), curline, cur_section));
-                        labels.push((lname.into(), curline));
-                        adrs.insert(line_nc[0..pos].into(), *counter);
-                        cur_label = Some(line_nc[0..pos].into());
-                    }
-                    rem_spaces(&line_nc[pos + (1 as usize)..])
-                }
-            }
-        };
-
-        if line_nl.len() == 0 {
-            continue;
+/// Runs an assembled program, then prints the registers it finished with. Exits with the
+/// program's exit code, or 1 if it raised an exception.
+fn run(program: &Program, sources: &[(&str, &str)], max_steps: Option<u64>) {
+    let mut cpu = Cpu::new(program);
+    let mut code = 0;
+    let result = cpu.run(
+        max_steps,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+    );
+    match result {
+        Ok(Stop::Exit(n)) => {
+            code = n;
+            println!("\n-- program is finished running ({}) --", n);
         }
-        if line_nl[..1].eq(".") {
-            let pos_opt = line_nl.find(" ");
-            //todo!("Directinges");
-            match pos_opt {
-                Some(n) => {
-                    let directive = &line_nl[0..n];
-                    let dir_data = &line_nl[n..];
-                    if directive.eq(".asciiz") || directive.eq(".ascii") {
-                        let begin_opt = dir_data.find("\"");
-                        let begin = match begin_opt {
-                            Some(n) => n as isize,
-                            None => -1,
-                        };
-                        let end_opt = dir_data[begin as usize + 1 as usize..].find("\"");
-                        let end: isize = match end_opt {
-                            Some(n) => (n as isize) + begin + (1 as isize),
-                            None => -1,
-                        };
-                        if begin == -1 || end == -1 {
-                            println!("Directive .ascii(z) is missing beginning or ending parentheses\nLine {0}: {1}", curline, line);
-                            continue;
-                        }
-                        //TODO: get this to output bytes in the right order
-                        let ascii =
-                            dir_data[begin as usize + (1 as usize)..end as usize].as_bytes();
-                        let mut byte_vec: Vec<u8> = Vec::new();
-                        for i in ascii {
-                            //print!("{},",i);
-                            byte_vec.push(*i);
-                        }
-                        //println!();
-                        if directive.eq(".asciiz") {
-                            byte_vec.push(0);
-                        }
-                        *counter += byte_vec.len() as u32;
-                        lines.push((Line::Data(byte_vec), curline, cur_section));
-                    } else if directive.eq(".data") {
-                        cur_section = Section::Data;
-                    } else if directive.eq(".data") {
-                        cur_section = Section::Text;
-                    }
-                }
-                None => {}
-            }
-            continue;
+        Ok(Stop::DroppedOff) => {
+            println!("\n-- program is finished running (dropped off bottom) --");
         }
-
-        //println!("{}",line_nl);
-
-        let code: &InstrCode = get_code(&line_nl);
-        if code.code == -1 {
+        Ok(Stop::StepLimit) => {
             println!(
-                "Invalid instruction code found.\nLine {0}: {1}",
-                curline, line
+                "\n-- program stopped after {} steps --",
+                max_steps.unwrap_or(0)
             );
         }
-
-        let line_args: String = {
-            let pos_opt: Option<usize> = line.find(' ');
-
-            match pos_opt {
-                None => {
-                    if let Syntax::Syscall = code.syntax {
-                        String::from(line_nl)
-                    } else {
-                        println!(
-                            "No arguments found when some are expected\nLine {0}: {1}",
-                            curline, line
-                        );
-                        String::from(&line_nl[(code.name.len())..])
-                    }
-                }
-                Some(pos) => rem_spaces(String::from(&line_nl[pos..])),
-            }
-        };
-
-        //println!("{}",line_args);
-
-        let adata: Args<Arg> = get_arguments(line_args);
-
-        lines.push((Line::Instr(code, adata), curline, cur_section));
-
-        *counter += 4;
-    }
-
-    for (lbl, c) in data_lbl_adr {
-        lbl_adr.insert(lbl, text_counter + c);
+        Err(e) => {
+            let (file, line) = program
+                .source_map
+                .iter()
+                .find(|s| s.address == cpu.pc)
+                .map_or((0, 0), |s| (s.file, s.line));
+            let mut d = Diagnostic::error(
+                line,
+                format!("Runtime exception at {:#010x}: {}.", cpu.pc, e),
+            );
+            d.file = program.files[file].clone();
+            report(&[d], sources);
+            code = 1;
+        }
     }
-
-    return (lines, lbl_adr, start_text, text_counter);
-}
-
-pub enum Encoding {
-    // o, s, t, d, a, f
-    Register(i8, i8, i8, i8, i8, i8),
-    Immediate(i8, i8, i8, i16),
-    Jump(i8, i32),
+    print!("{}", cpu.dump_registers());
+    std::process::exit(code);
 }
 
-fn get_bin(enc: Encoding) -> Vec<u8> {
-    match enc {
-        Encoding::Register(o, s, t, d, a, f) => {
-            let mut b: Vec<u8> = Vec::new();
-            b.push(((o << 2) + (s >> 3)) as u8);
-            b.push(((s << 5) + t) as u8);
-            b.push(((d << 3) + (a >> 2)) as u8);
-            b.push(((a << 6) + f) as u8);
-            b
-        }
-        Encoding::Immediate(o, s, t, i) => {
-            //println!("{}", o);
-            //println!("{}", o<<2);
-            let mut b: Vec<u8> = Vec::new();
-            b.push(((o << 2) + (s >> 3)) as u8);
-            b.push(((s << 5) + t) as u8);
-            b.push((i >> 8) as u8);
-            b.push((i) as u8);
-            b
-        }
-        Encoding::Jump(o, i) => {
-            let mut b: Vec<u8> = Vec::new();
-            b.push(((o << 2) + ((i >> 24) as i8)) as u8);
-            b.push((i >> 16) as u8);
-            b.push((i >> 8) as u8);
-            b.push((i) as u8);
-            b
+/// Reads the ELF format named by `-f`, with the extension the file gets.
+fn get_format(arg: &str) -> (ElfKind, Endian, &'static str) {
+    match arg {
+        "exec" => (ElfKind::Executable, Endian::Big, "elf"),
+        "exec-el" => (ElfKind::Executable, Endian::Little, "elf"),
+        "obj" => (ElfKind::Object, Endian::Big, "o"),
+        "obj-el" => (ElfKind::Object, Endian::Little, "o"),
+        _ => {
+            eprintln!(
+                "error: Unknown output format \"{0}\", expected one of exec, exec-el, obj, obj-el.",
+                arg
+            );
+            std::process::exit(1);
         }
     }
 }
 
-fn pass2(
-    lines: Vec<(Line, u32, Section)>,
-    lbl_adr: &HashMap<String, u32>,
-    start_text: u32,
-    text_counter: u32,
-) -> Vec<u8> {
-    let mut text_code = Vec::new();
-    let mut data_code = Vec::new();
-
-    for (i, ln, sect) in &lines {
-        let (machine_code, counter): (&mut Vec<u8>, _) = match sect {
-            Section::Text => {
-                let t: u32 = text_code.len() as u32 + start_text;
-                (&mut text_code, t)
-            }
-            Section::Data => {
-                let t: u32 = data_code.len() as u32 + text_counter;
-                (&mut data_code, t)
-            }
-        };
-        match i {
-            Line::Instr(instr, args) => {
-                //println!("{}:{}", counter, instr.name);
-                let enc = get_enc(instr, args.clone(), &lbl_adr, *ln, counter);
-                /*
-                match enc {
-                    Encoding::Register(o, s, t, d, a, f) => {println!("{},{},{},{},{},{}", o,s,t,d,a,f);}
-                    Encoding::Immediate(o, s, t, i) => {println!("{},{},{},{}",o, s,t,i);}
-                    Encoding::Jump(o, i) => {println!("{},{}", o, i);}
-                }
-                 */
-                let data = get_bin(enc);
-                for i in data {
-                    machine_code.push(i)
-                }
-            }
-            Line::Data(data) => {
-                for i in data {
-                    machine_code.push(*i);
-                }
-            }
-            _ => {}
+/// Reads the instruction set revision named by `--isa`.
+fn get_isa(arg: &str) -> Isa {
+    match Isa::parse(arg) {
+        Some(isa) => isa,
+        None => {
+            let names: Vec<&str> = ISAS.iter().map(|i| i.0).collect();
+            eprintln!(
+                "error: Unknown ISA \"{0}\", expected one of {1}.",
+                arg,
+                names.join(", ")
+            );
+            std::process::exit(1);
         }
     }
+}
 
-    /*
-    for (i, l) in lbl_adr {
-        println!("{} {}", i, l);
-    }
-     */
-
-    for i in data_code {
-        text_code.push(i);
+/// Reads a `-D name=value` constant. Without a value the constant is 1.
+fn get_define(arg: &str) -> (String, String) {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+    let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid {
+        eprintln!("error: Invalid constant name \"{0}\" for -D.", name);
+        std::process::exit(1);
     }
-    text_code
+    (name.to_string(), value.to_string())
 }
 
-fn get_cmd_args(args : Vec<String>) -> HashMap<String, String> {
-    let mut arg_type : String = "file".into();
-    let mut argmap = HashMap::new();
-    for arg in args {
-        arg_type = if arg=="-o" {
-            "outfile".into()
+/// Disassembles a .ho, .bo or raw binary file, as told apart by its extension, and prints the
+/// result or writes it to `out`.
+fn disasm(file: &str, start: u32, isa: Isa, out: Option<&String>) {
+    let bytes = match std::fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            eprintln!("error: File \"{0}\" not found.", file);
+            std::process::exit(1);
         }
-        else if arg=="-p" {
-            "pseudo".into()
+    };
+    let text = String::from_utf8_lossy(&bytes);
+    let image = if file.ends_with(".ho") {
+        read_ho(&text)
+    } else if file.ends_with(".bo") {
+        read_bo(&text)
+    } else {
+        Ok(bytes.clone())
+    };
+    let image = match image {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("error: {} in {}", e, file);
+            std::process::exit(1);
         }
-        else {
-            argmap.insert(arg_type, arg);
-            "file".into()
-        };
+    };
+
+    let asm = disassemble(&image, start, &HashMap::new(), isa);
+    match out {
+        None => print!("{}", asm),
+        Some(out) => match std::fs::write(out, asm) {
+            Ok(_) => println!("Results written to {}", out),
+            Err(e) => println!("Could not write disassembly to file, {}", e),
+        },
     }
-    argmap
 }
 
 // Tested on own code as well as samples from:
@@ -361,110 +240,149 @@ fn get_cmd_args(args : Vec<String>) -> HashMap<String, String> {
 fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
 
-    let args: HashMap<String, String> = get_cmd_args(std::env::args().collect());
-    if let None=args.get("file") {
+    let mut argv: Vec<String> = std::env::args().collect();
+    let run_mode = argv.get(1).is_some_and(|a| a == "run");
+    let disasm_mode = argv.get(1).is_some_and(|a| a == "disasm");
+    if run_mode || disasm_mode {
+        argv.remove(1);
+    }
+    let (args, files) = get_cmd_args(argv.split_off(1));
+    if files.is_empty() {
         println!("No parameters given, needs at least 1");
-        println!("Usage:    assembler_rust file [-o outfile] [-p pseudos]");
+        println!(
+            "Usage:    assembler_rust file... [-o outfile] [-p pseudos] [-m memory] [-l listing] [-f format] [--isa isa] [--delayed-branches] [-I dir] [-D name=value]"
+        );
+        println!(
+            "          assembler_rust run file... [-p pseudos] [-m memory] [-n steps] [--isa isa] [--delayed-branches] [-I dir] [-D name=value]"
+        );
+        println!("          assembler_rust disasm file [-o outfile] [-m memory] [--isa isa]");
         return;
     }
-    let fdata = std::fs::read_to_string(&args.get("file").unwrap());
-
-    let _pseudos = get_ops(args.get("pseudo"));
-
-    match fdata {
-        Ok(data) => {
-            let (lines, lbls, start, text) = pass1(&data, Some(0x400000));
-
-            /*
-            for (line, ln, sect) in &lines {
-                match line {
-                    Line::Instr(code, args) => {println!("Instruction {0} with {1}", code.name, args)}
-                    Line::Label(name) => {println!("Label `{0}`", name)}
-                    Line::Data(data) => {
-                        print!("Data ");
-                        for i in data {
-                            print!("{}, ",i);
-                        }
-                        println!();
-                    }
+    let isa = args.get("isa").map_or_else(Isa::default, |i| get_isa(i));
+    if disasm_mode {
+        let memory = args
+            .get("memory")
+            .map_or_else(MemoryMap::default, |m| get_memory(m));
+        disasm(&files[0], memory.text, isa, args.get("outfile"));
+        return;
+    }
+    let format = args.get("format").map(|f| get_format(f));
+    let mut texts = Vec::new();
+    for file in &files {
+        match std::fs::read_to_string(file) {
+            Ok(src) => texts.push(src),
+            Err(_) => {
+                eprintln!("error: File \"{0}\" not found.", file);
+                std::process::exit(1);
+            }
+        }
+    }
+    let sources: Vec<(&str, &str)> = files
+        .iter()
+        .zip(&texts)
+        .map(|(f, t)| (f.as_str(), t.as_str()))
+        .collect();
+    let file = &files[0];
+
+    let options = Options {
+        file: file.clone(),
+        pseudo_ops: args.get("pseudo").cloned(),
+        memory: args
+            .get("memory")
+            .map_or_else(MemoryMap::default, |m| get_memory(m)),
+        isa,
+        delayed_branches: args.contains_key("delayed"),
+        include_dirs: args
+            .get("include")
+            .map_or_else(Vec::new, |dirs| dirs.lines().map(PathBuf::from).collect()),
+        defines: args
+            .get("define")
+            .map_or_else(Vec::new, |defs| defs.lines().map(get_define).collect()),
+    };
+    let mut elf = None;
+    let data = match assemble_files(&sources, &options) {
+        Ok(program) => {
+            report(&program.warnings, &sources);
+            if let Some(lst) = args.get("listing") {
+                let texts: Vec<&str> = sources.iter().map(|s| s.1).collect();
+                if let Err(e) = std::fs::write(lst, program.listing(&texts)) {
+                    println!("Could not write listing to file, {}", e);
                 }
             }
-             */
-
-            let data = pass2(lines, &lbls, start, text);
-
-            let hex = hex::encode(data);
-
-            /*
-            let mut i = 0;
-            while i < hex.len() - 8 {
-                println!("{}", &hex[i..i+8]);
-                i += 8;
+            if run_mode {
+                let steps = args.get("steps").and_then(|n| n.parse().ok());
+                run(&program, &sources, steps);
             }
-            println!("{}", &hex[i..]);
-             */
-
-            let bin_res = BinaryString::from_hex(&hex);
-
-            match bin_res {
-                Ok(bin) => {
-                    let out = String::from({
-                        if let Some(x)=args.get("outfile") {
-                            x
-                        } else {
-                            let dot_find: Option<usize> = args["file"].rfind(".");
-                            let slash_find: Option<usize> = args["file"].rfind("/");
-                            let ln = args["file"].len();
-                            match dot_find {
-                                None => &args["file"],
-                                Some(n) => match slash_find {
-                                    None => &args["file"][..ln - n],
-                                    Some(n2) => {
-                                        if n > n2 {
-                                            &args["file"]
-                                        } else {
-                                            &args["file"][..ln - n]
-                                        }
-                                    }
-                                },
-                            }
-                        }
-                    });
-
-                    let mut hex_lined = "".to_string();
-                    let mut i = 0;
-                    while i < hex.len() - 8 {
-                        hex_lined = hex_lined + &hex[i..i + 8] + "\n";
-                        i += 8;
-                    }
-                    hex_lined = hex_lined + &hex[i..];
+            if let Some((kind, endian, ext)) = format {
+                elf = Some((program.elf(kind, endian), ext));
+            }
+            program.bytes()
+        }
+        Err(diags) => {
+            report(&diags, &sources);
+            std::process::exit(1);
+        }
+    };
 
-                    match std::fs::write(format!("{}{}", out, ".ho".to_string()), hex_lined) {
-                        Ok(_) => {
-                            match std::fs::write(
-                                format!("{}{}", out, ".bo".to_string()),
-                                bin.to_string(),
-                            ) {
-                                Ok(_) => {
-                                    println!("Results written to {0}.bo and {0}.ho", out);
-                                }
-                                Err(e) => {
-                                    println!("Could not write binary to file, {}", e)
+    let hex = hex::encode(data);
+
+    let bin_res = BinaryString::from_hex(&hex);
+
+    match bin_res {
+        Ok(bin) => {
+            let out = String::from({
+                if let Some(x) = args.get("outfile") {
+                    x
+                } else {
+                    let dot_find: Option<usize> = file.rfind('.');
+                    let slash_find: Option<usize> = file.rfind('/');
+                    let ln = file.len();
+                    match dot_find {
+                        None => file,
+                        Some(n) => match slash_find {
+                            None => &file[..ln - n],
+                            Some(n2) => {
+                                if n > n2 {
+                                    file
+                                } else {
+                                    &file[..ln - n]
                                 }
                             }
-                        }
-                        Err(e) => {
-                            println!("Could not write hex to file, {}", e)
-                        }
+                        },
                     }
                 }
-                Err(_) => {
-                    println!("Invalid hex???");
+            });
+
+            // One word per line, and the bytes past the last whole word on a line of their own
+            let hex_lined = hex
+                .as_bytes()
+                .chunks(8)
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            match std::fs::write(format!("{}{}", out, ".ho"), hex_lined) {
+                Ok(_) => match std::fs::write(format!("{}{}", out, ".bo"), bin.to_string()) {
+                    Ok(_) => {
+                        println!("Results written to {0}.bo and {0}.ho", out);
+                    }
+                    Err(e) => {
+                        println!("Could not write binary to file, {}", e)
+                    }
+                },
+                Err(e) => {
+                    println!("Could not write hex to file, {}", e)
+                }
+            }
+            if let Some((bytes, ext)) = elf {
+                match std::fs::write(format!("{}.{}", out, ext), bytes) {
+                    Ok(_) => println!("ELF written to {}.{}", out, ext),
+                    Err(e) => println!("Could not write ELF to file, {}", e),
                 }
             }
         }
         Err(_) => {
-            println!("File \"{0}\" not found.", &args["file"])
+            println!("Invalid hex???");
         }
     }
     //println!("\"{0}\"", arg_nospace);
@@ -0,0 +1,198 @@
+use crate::codes::{parse_num, strip_comment};
+use crate::diagnostic::Diagnostic;
+
+/// Where each segment of a program lives, and the initial `$gp` and `$sp`. The presets match the
+/// memory configurations of MARS.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryMap {
+    pub text: u32,
+    pub data: u32,
+    /// Start of the `.extern` area, which `$gp` points into.
+    pub extern_: u32,
+    pub gp: u32,
+    pub sp: u32,
+    /// Start of the heap that sbrk hands out.
+    pub heap: u32,
+    pub ktext: u32,
+    /// Address of the exception handler in kernel text.
+    pub exception_handler: u32,
+    pub kdata: u32,
+    pub mmio: u32,
+}
+
+impl Default for MemoryMap {
+    fn default() -> MemoryMap {
+        MemoryMap::mars_default()
+    }
+}
+
+/// Names accepted by [`MemoryMap::preset`], with the MARS name each stands for.
+pub const PRESETS: [(&str, &str); 3] = [
+    ("default", "Default"),
+    ("compact-data-at-0", "Compact, Data at Address 0"),
+    ("compact-text-at-0", "Compact, Text at Address 0"),
+];
+
+impl MemoryMap {
+    /// MARS "Default": text at 0x00400000, data at 0x10010000.
+    pub fn mars_default() -> MemoryMap {
+        MemoryMap {
+            text: 0x0040_0000,
+            data: 0x1001_0000,
+            extern_: 0x1000_0000,
+            gp: 0x1000_8000,
+            sp: 0x7fff_effc,
+            heap: 0x1004_0000,
+            ktext: 0x8000_0000,
+            exception_handler: 0x8000_0180,
+            kdata: 0x9000_0000,
+            mmio: 0xffff_0000,
+        }
+    }
+
+    /// MARS "Compact, Data at Address 0", which fits in 32K.
+    pub fn compact_data_at_0() -> MemoryMap {
+        MemoryMap {
+            text: 0x3000,
+            data: 0x0000,
+            extern_: 0x1000,
+            gp: 0x1800,
+            sp: 0x2ffc,
+            heap: 0x2000,
+            ktext: 0x4000,
+            exception_handler: 0x4180,
+            kdata: 0x5000,
+            mmio: 0x7f00,
+        }
+    }
+
+    /// MARS "Compact, Text at Address 0", which fits in 32K.
+    pub fn compact_text_at_0() -> MemoryMap {
+        MemoryMap {
+            text: 0x0000,
+            data: 0x2000,
+            sp: 0x3ffc,
+            heap: 0x3000,
+            ..MemoryMap::compact_data_at_0()
+        }
+    }
+
+    /// Whether every address fits in 15 bits, as with the compact presets, so that a label is
+    /// reached as a 16-bit offset from `$zero`.
+    pub fn is_compact(&self) -> bool {
+        [
+            self.text,
+            self.data,
+            self.extern_,
+            self.gp,
+            self.sp,
+            self.heap,
+            self.ktext,
+            self.exception_handler,
+            self.kdata,
+            self.mmio,
+        ]
+        .iter()
+        .all(|adr| *adr <= 0x7fff)
+    }
+
+    /// Where the segment holding `adr` ends: at the next segment or the MMIO area above it, or
+    /// at the end of the address space. The `.extern` area is part of the data segment.
+    pub(crate) fn segment_end(&self, adr: u32) -> u64 {
+        [self.text, self.data, self.ktext, self.kdata, self.mmio]
+            .into_iter()
+            .filter(|start| *start > adr)
+            .min()
+            .map_or(1 << 32, u64::from)
+    }
+
+    /// Looks up a preset by one of the names in [`PRESETS`], or by its MARS name.
+    pub fn preset(name: &str) -> Option<MemoryMap> {
+        let idx = PRESETS
+            .iter()
+            .position(|(short, mars)| name == *short || name.eq_ignore_ascii_case(mars))?;
+        Some(match idx {
+            0 => MemoryMap::mars_default(),
+            1 => MemoryMap::compact_data_at_0(),
+            _ => MemoryMap::compact_text_at_0(),
+        })
+    }
+
+    /// Reads a memory configuration file. Each line is `key = value`, where the key is
+    /// `preset` (which must come first) or one of the field names, and `#` starts a comment.
+    /// Fields not given keep their MARS default.
+    ///
+    /// ```text
+    /// preset = compact-text-at-0
+    /// data = 0x1000
+    /// ```
+    pub fn parse(config: &str) -> Result<MemoryMap, Diagnostic> {
+        let mut map = MemoryMap::mars_default();
+        let mut curline = 0;
+        for line in config.lines() {
+            curline += 1;
+            let code = strip_comment(line).trim();
+            if code.is_empty() {
+                continue;
+            }
+            let (key, value) = match code.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => {
+                    return Err(Diagnostic::error(curline, "Expected \"key = value\"."));
+                }
+            };
+            if key == "preset" {
+                map = MemoryMap::preset(value).ok_or_else(|| {
+                    let names: Vec<&str> = PRESETS.iter().map(|p| p.0).collect();
+                    Diagnostic::error(
+                        curline,
+                        format!(
+                            "Unknown memory preset \"{}\", expected one of {}.",
+                            value,
+                            names.join(", ")
+                        ),
+                    )
+                    .at(line, value)
+                })?;
+                continue;
+            }
+            let adr = match parse_num(value) {
+                Ok(n) => n as u32,
+                Err(_) => {
+                    return Err(Diagnostic::error(
+                        curline,
+                        format!("Invalid address \"{}\".", value),
+                    )
+                    .at(line, value));
+                }
+            };
+            let field = match key {
+                "text" => &mut map.text,
+                "data" => &mut map.data,
+                "extern" => &mut map.extern_,
+                "gp" => &mut map.gp,
+                "sp" => &mut map.sp,
+                "heap" => &mut map.heap,
+                "ktext" => &mut map.ktext,
+                "exception_handler" => &mut map.exception_handler,
+                "kdata" => &mut map.kdata,
+                "mmio" => &mut map.mmio,
+                _ => {
+                    return Err(
+                        Diagnostic::error(curline, format!("Unknown key \"{}\".", key))
+                            .at(line, key),
+                    );
+                }
+            };
+            if adr % 4 != 0 {
+                return Err(Diagnostic::error(
+                    curline,
+                    format!("Address {:#x} for {} is not word aligned.", adr, key),
+                )
+                .at(line, value));
+            }
+            *field = adr;
+        }
+        Ok(map)
+    }
+}
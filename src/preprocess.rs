@@ -0,0 +1,759 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::codes::{
+    find_unquoted, fold, is_label_name, parse_num, parse_string, split_items, split_label,
+    split_operands, strip_comment,
+};
+use crate::diagnostic::Diagnostic;
+use crate::expr::Expr;
+use crate::Options;
+
+/// How a constant was defined.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// `.eqv NAME text`, whose text replaces the name, as in MARS.
+    Eqv,
+    /// `.equ NAME, value`, a number that cannot change.
+    Equ,
+    /// `.set NAME, value`, a number that a later `.set` can change.
+    Set,
+}
+
+struct Constant {
+    kind: Kind,
+    /// With the constants it used replaced, apart from those not yet defined at the time.
+    value: String,
+    /// 0 for a constant given with `-D`.
+    line: u32,
+}
+
+/// The constants of one file.
+#[derive(Default)]
+struct Constants {
+    defs: HashMap<String, Constant>,
+}
+
+/// Calls `f` on each word of `text` outside quotes that could name a constant, and puts what it
+/// returns in place of the word. Numbers, registers and the `hi` of `%hi` are not names.
+fn replace_names(text: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut out = String::new();
+    let (mut quote, mut escaped) = (None, false);
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if is_word(c) {
+            let len = text[i..].find(|c| !is_word(c)).unwrap_or(text.len() - i);
+            let word = &text[i..i + len];
+            let before = text[..i].chars().next_back();
+            let name = !word.starts_with(|c: char| c.is_ascii_digit())
+                && before != Some('$')
+                && !(before == Some('%') && ["hi", "lo", "gp_rel"].contains(&word));
+            match if name { f(word) } else { None } {
+                Some(r) => out.push_str(&r),
+                None => out.push_str(word),
+            }
+            i += len;
+            continue;
+        }
+        out.push(c);
+        i += c.len_utf8();
+    }
+    out
+}
+
+impl Constants {
+    /// `text` with every constant in it replaced by its value. A numeric constant's value is
+    /// worked out when it can be, and kept in parentheses when it uses labels.
+    fn expand(&self, text: &str) -> String {
+        replace_names(text, |name| {
+            let c = self.defs.get(name)?;
+            let value = self.expand(&c.value);
+            Some(match c.kind {
+                Kind::Eqv => value,
+                Kind::Equ | Kind::Set => fold(&format!("({})", value)),
+            })
+        })
+    }
+
+    /// The constants leading from `text` back to `name`, if its value would depend on itself.
+    fn cycle(&self, text: &str, name: &str) -> Option<Vec<String>> {
+        let mut path = None;
+        replace_names(text, |word| {
+            if path.is_none() {
+                if word == name {
+                    path = Some(vec![name.to_string()]);
+                } else if let Some(c) = self.defs.get(word) {
+                    path = self
+                        .cycle(&c.value, name)
+                        .map(|p| [vec![word.to_string()], p].concat());
+                }
+            }
+            None
+        });
+        path
+    }
+
+    /// Defines the constant given by the arguments `args` of `directive`, which is `.eqv`,
+    /// `.equ` or `.set`. On failure returns the reason along with the text at fault.
+    fn define(
+        &mut self,
+        kind: Kind,
+        directive: &str,
+        args: &str,
+        line: u32,
+        labels: &HashMap<String, u32>,
+    ) -> Result<(), (String, String)> {
+        let args = args.trim();
+        let parts = match kind {
+            Kind::Eqv => args.split_once(char::is_whitespace),
+            Kind::Equ | Kind::Set => args.split_once(','),
+        };
+        let (name, value) = match parts {
+            Some((name, value)) if !value.trim().is_empty() => (name.trim(), value.trim()),
+            _ => {
+                let e = format!("Expected a name and a value after {}.", directive);
+                return Err((e, String::new()));
+            }
+        };
+        let fail = |e: String| Err((e, name.to_string()));
+        if !is_label_name(name) {
+            return fail(format!("Invalid constant name \"{}\".", name));
+        }
+        if let Some(old) = self.defs.get(name) {
+            if old.line == 0 {
+                return fail(format!("Constant {} is already defined with -D.", name));
+            }
+            if kind != Kind::Set || old.kind != Kind::Set {
+                return fail(format!(
+                    "Constant {} is already defined on line {}.",
+                    name, old.line
+                ));
+            }
+        }
+        if let Some(l) = labels.get(name) {
+            return fail(format!(
+                "{} is already defined as a label on line {}.",
+                name, l
+            ));
+        }
+
+        // A .set may use the value it replaces, as in `.set n, n + 1`
+        if !self.defs.contains_key(name) {
+            if let Some(path) = self.cycle(value, name) {
+                return fail(format!(
+                    "Constant {0} is defined in terms of itself: {0} -> {1}.",
+                    name,
+                    path.join(" -> ")
+                ));
+            }
+        }
+        let value = self.expand(value);
+        if kind != Kind::Eqv && Expr::parse(&value).is_err() {
+            return Err((
+                format!("Invalid value \"{}\" for {}.", value, directive),
+                value,
+            ));
+        }
+        self.defs
+            .insert(name.to_string(), Constant { kind, value, line });
+        Ok(())
+    }
+}
+
+/// A macro: the lines between `.macro` and `.end_macro`.
+#[derive(Clone)]
+struct Macro {
+    /// The parameters, without their `%`.
+    params: Vec<String>,
+    /// The number and text of each line of the body, as written.
+    body: Vec<(u32, String)>,
+    /// Labels the body defines, which each expansion renames so that they are its own.
+    labels: Vec<String>,
+    /// The file defining it, and the line of its `.macro`.
+    file: usize,
+    line: u32,
+}
+
+/// Puts the arguments of a macro call in place of the parameters, written `%name`, in `text`.
+fn replace_params(text: &str, params: &[String], args: &[&str]) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(n) = find_unquoted(rest, '%') {
+        out.push_str(&rest[..n]);
+        let after = &rest[n + 1..];
+        let len = after
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(after.len());
+        match params.iter().position(|p| *p == after[..len]) {
+            Some(i) => out.push_str(args[i]),
+            None => out.push_str(&rest[n..n + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    out + rest
+}
+
+/// A file read by preprocessing: the one being assembled, then each one it includes, in the order
+/// their `.include`s were reached.
+pub(crate) struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// For an included file, the file and line of its `.include`.
+    pub parent: Option<(usize, u32)>,
+}
+
+/// A statement after preprocessing, along with where it came from.
+pub(crate) struct Stmt {
+    /// The statement, with its comment removed, constants replaced and macros expanded.
+    pub text: String,
+    /// The file it was written in, as an index into the files preprocessing read.
+    pub file: usize,
+    /// The line it was written on, which for a line of a macro is in the definition.
+    pub line: u32,
+    /// The text of that line, to point diagnostics at.
+    pub source: String,
+    /// The file and line listings show the statement under: the outermost macro call, or else
+    /// `file` and `line`.
+    pub site: (usize, u32),
+    /// For a line of a macro, notes pointing at each call it came through, innermost first.
+    pub notes: Vec<Diagnostic>,
+}
+
+/// Gives each diagnostic the file and notes of the statement it is about. `firsts` has the index
+/// of the first diagnostic about each statement, in order, along with the statement.
+pub(crate) fn add_notes(
+    diags: &mut [Diagnostic],
+    firsts: &[(usize, usize)],
+    stmts: &[Stmt],
+    files: &[SourceFile],
+) {
+    for (i, (first, stmt)) in firsts.iter().enumerate() {
+        let end = firsts.get(i + 1).map_or(diags.len(), |f| f.0);
+        let stmt = &stmts[*stmt];
+        for d in &mut diags[*first..end] {
+            d.file.clone_from(&files[stmt.file].name);
+            d.notes.clone_from(&stmt.notes);
+        }
+    }
+}
+
+/// Where the lines being preprocessed come from: a file, or a macro call.
+#[derive(Clone, Default)]
+struct Origin {
+    /// The file and line of the outermost call, if any.
+    site: Option<(usize, u32)>,
+    notes: Vec<Diagnostic>,
+    /// The macros being expanded, by name and number of arguments, to catch recursion.
+    calls: Vec<(String, usize)>,
+}
+
+/// An `.if`, `.ifdef` or `.ifndef` whose `.endif` has not been reached.
+struct Cond {
+    /// Whether the lines are being assembled, which needs the enclosing conditions to be too.
+    active: bool,
+    /// Whether the enclosing conditions are active.
+    outer: bool,
+    /// Whether the condition held, so the `.else` part is skipped.
+    taken: bool,
+    has_else: bool,
+    file: usize,
+    line: u32,
+    source: String,
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    consts: Constants,
+    labels: HashMap<String, u32>,
+    /// Macros by name, each name with one macro per number of parameters.
+    macros: HashMap<String, Vec<Macro>>,
+    /// The macro whose body is being read, with its name.
+    defining: Option<(String, Macro)>,
+    /// Expansions so far, to number the labels of each.
+    expansions: u32,
+    conds: Vec<Cond>,
+    files: Vec<SourceFile>,
+    /// The files being read, innermost last, with their canonical paths to catch cycles.
+    open: Vec<(usize, Option<PathBuf>)>,
+    include_dirs: Vec<PathBuf>,
+    stmts: Vec<Stmt>,
+    diags: Vec<Diagnostic>,
+}
+
+impl Preprocessor {
+    /// `d`, which is about `file`, with the file's name.
+    fn in_file(&self, d: Diagnostic, file: usize) -> Diagnostic {
+        Diagnostic {
+            file: self.files[file].name.clone(),
+            ..d
+        }
+    }
+
+    fn error(&mut self, d: Diagnostic, file: usize, origin: &Origin) {
+        let d = Diagnostic {
+            notes: origin.notes.clone(),
+            ..self.in_file(d, file)
+        };
+        self.diags.push(d);
+    }
+
+    fn active(&self) -> bool {
+        self.conds.last().is_none_or(|c| c.active)
+    }
+
+    /// Preprocesses each line of `file`, then reports the conditions it leaves open.
+    fn file(&mut self, file: usize, origin: &Origin) {
+        let depth = self.conds.len();
+        let text = self.files[file].text.clone();
+        for (n, line) in text.lines().enumerate() {
+            self.line(line, file, n as u32 + 1, line, origin);
+        }
+        for c in self.conds.split_off(depth.min(self.conds.len())) {
+            let directive = c.source.split_whitespace().next().unwrap_or("");
+            let d = Diagnostic::error(c.line, "Missing .endif for this condition.")
+                .at(&c.source, directive);
+            self.error(d, c.file, origin);
+        }
+    }
+
+    /// Whether the condition of an `.if`, `.ifdef` or `.ifndef` with the arguments `args` holds.
+    /// On failure returns the reason along with the text at fault.
+    fn condition(&self, directive: &str, args: &str) -> Result<bool, (String, String)> {
+        let args = args.trim();
+        if directive != ".if" {
+            if !is_label_name(args) {
+                let e = format!("Expected a constant name after {}.", directive);
+                return Err((e, args.to_string()));
+            }
+            return Ok(self.consts.defs.contains_key(args) == (directive == ".ifdef"));
+        }
+        match parse_num(fold(self.consts.expand(args).trim())) {
+            Ok(n) => Ok(n != 0),
+            Err(_) => Err((
+                format!("The condition \"{}\" of .if is not a constant.", args),
+                args.to_string(),
+            )),
+        }
+    }
+
+    /// Handles `directive` if it is one of the conditional ones, returning whether it was.
+    fn conditional(
+        &mut self,
+        directive: &str,
+        args: &str,
+        file: usize,
+        line: u32,
+        source: &str,
+        origin: &Origin,
+    ) -> bool {
+        let result = match directive {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let outer = self.active();
+                // Conditions in skipped lines are not looked at, only matched with their .endif
+                let taken = match outer {
+                    true => self.condition(directive, args),
+                    false => Ok(false),
+                };
+                self.conds.push(Cond {
+                    active: outer && taken == Ok(true),
+                    outer,
+                    taken: taken == Ok(true),
+                    has_else: false,
+                    file,
+                    line,
+                    source: source.to_string(),
+                });
+                taken.map(|_| ())
+            }
+            ".else" => match self.conds.last_mut() {
+                Some(c) if !c.has_else => {
+                    c.has_else = true;
+                    c.active = c.outer && !c.taken;
+                    Ok(())
+                }
+                Some(c) => {
+                    let e = format!("The condition on line {} already has an .else.", c.line);
+                    Err((e, ".else".to_string()))
+                }
+                None => Err((".else without .if.".to_string(), ".else".to_string())),
+            },
+            ".endif" => match self.conds.pop() {
+                Some(_) => Ok(()),
+                None => Err((".endif without .if.".to_string(), ".endif".to_string())),
+            },
+            _ => return false,
+        };
+        if let Err((e, at)) = result {
+            self.error(Diagnostic::error(line, e).at(source, &at), file, origin);
+        }
+        true
+    }
+
+    /// Reads the file named by `args`, the rest of an `.include` line, and preprocesses it in
+    /// place of the line. Returns the reason and the text at fault if it cannot.
+    fn include(
+        &mut self,
+        args: &str,
+        file: usize,
+        line: u32,
+        origin: &Origin,
+    ) -> Result<(), (String, String)> {
+        let args = args.trim();
+        let name = match parse_string(args) {
+            Ok((name, rest)) if rest.trim().is_empty() => {
+                String::from_utf8_lossy(&name).into_owned()
+            }
+            _ => {
+                let e = "Expected a quoted file name after .include.".to_string();
+                return Err((e, args.to_string()));
+            }
+        };
+
+        // First next to the file with the .include, then in each include directory
+        let here = Path::new(&self.files[file].name)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let dirs: Vec<PathBuf> = [here]
+            .into_iter()
+            .chain(self.include_dirs.clone())
+            .collect();
+        let path = match dirs.iter().map(|d| d.join(&name)).find(|p| p.is_file()) {
+            Some(path) => path,
+            None => {
+                let searched: Vec<String> = dirs
+                    .iter()
+                    .map(|d| match d.to_str() {
+                        Some("") => ".".to_string(),
+                        _ => d.display().to_string(),
+                    })
+                    .collect();
+                let e = format!("File \"{}\" not found in {}.", name, searched.join(", "));
+                return Err((e, args.to_string()));
+            }
+        };
+        let canonical = path.canonicalize().ok();
+        if let Some(pos) = self
+            .open
+            .iter()
+            .position(|o| o.1.is_some() && o.1 == canonical)
+        {
+            let chain: Vec<&str> = self.open[pos..]
+                .iter()
+                .map(|o| self.files[o.0].name.as_str())
+                .collect();
+            let e = format!(
+                "Cyclic .include: {} -> {}.",
+                chain.join(" -> "),
+                path.display()
+            );
+            return Err((e, args.to_string()));
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                let e = format!("Could not read \"{}\": {}.", path.display(), e);
+                return Err((e, args.to_string()));
+            }
+        };
+
+        let included = self.files.len();
+        self.files.push(SourceFile {
+            name: path.display().to_string(),
+            text,
+            parent: Some((file, line)),
+        });
+        self.open.push((included, canonical));
+        self.file(included, origin);
+        self.open.pop();
+        Ok(())
+    }
+
+    /// Starts reading the body of the macro that `args`, the rest of a `.macro` line, names.
+    fn define_macro(&mut self, args: &str, file: usize, line: u32) -> Result<(), (String, String)> {
+        let args = args.trim();
+        let len = args
+            .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+            .unwrap_or(args.len());
+        let (name, params) = args.split_at(len);
+        if !is_label_name(name) {
+            return Err((
+                format!("Invalid macro name \"{}\".", name),
+                name.to_string(),
+            ));
+        }
+        let params = params.trim();
+        let params = match params.strip_prefix('(') {
+            Some(p) => match p.strip_suffix(')') {
+                Some(p) => p,
+                None => {
+                    let e = format!("Missing ending parenthesis in macro {}.", name);
+                    return Err((e, String::new()));
+                }
+            },
+            None => params,
+        };
+        let mut names: Vec<String> = Vec::new();
+        for param in split_items(params) {
+            let p = param.strip_prefix('%').unwrap_or("");
+            if p.is_empty() || !p.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                let e = format!("Invalid macro parameter \"{}\", expected %name.", param);
+                return Err((e, param.to_string()));
+            }
+            if names.iter().any(|n| n == p) {
+                let e = format!("Parameter {} of macro {} appears twice.", param, name);
+                return Err((e, param.to_string()));
+            }
+            names.push(p.to_string());
+        }
+        if let Some(m) = self
+            .macros
+            .get(name)
+            .into_iter()
+            .flatten()
+            .find(|m| m.params.len() == names.len())
+        {
+            return Err((
+                format!(
+                    "Macro {} with {} parameters is already defined on line {}.",
+                    name,
+                    names.len(),
+                    m.line
+                ),
+                name.to_string(),
+            ));
+        }
+        let m = Macro {
+            params: names,
+            body: Vec::new(),
+            labels: Vec::new(),
+            file,
+            line,
+        };
+        self.defining = Some((name.to_string(), m));
+        Ok(())
+    }
+
+    /// Expands a call of macro `name` with `args`, on `line` of `file` with the text `source`.
+    fn call(
+        &mut self,
+        name: &str,
+        args: &str,
+        file: usize,
+        line: u32,
+        source: &str,
+        origin: &Origin,
+    ) {
+        // Either name(a, b) or name a, b
+        let args = args.trim();
+        let inner = args
+            .strip_prefix('(')
+            .and_then(|a| a.strip_suffix(')'))
+            .filter(|a| split_operands(&format!("({})", a)).len() == 1);
+        let args: Vec<&str> = match inner.unwrap_or(args) {
+            "" => Vec::new(),
+            a => split_operands(a).into_iter().map(str::trim).collect(),
+        };
+        let versions = &self.macros[name];
+        let m = match versions.iter().find(|m| m.params.len() == args.len()) {
+            Some(m) => m.clone(),
+            None => {
+                let mut counts: Vec<usize> = versions.iter().map(|m| m.params.len()).collect();
+                counts.sort();
+                let counts: Vec<String> = counts.iter().map(usize::to_string).collect();
+                let noun = if counts == ["1"] {
+                    "argument"
+                } else {
+                    "arguments"
+                };
+                let e = format!(
+                    "Macro {} takes {} {}, not {}.",
+                    name,
+                    counts.join(" or "),
+                    noun,
+                    args.len()
+                );
+                self.error(Diagnostic::error(line, e).at(source, name), file, origin);
+                return;
+            }
+        };
+        let key = (name.to_string(), args.len());
+        if origin.calls.contains(&key) {
+            let e = format!("Macro {} calls itself.", name);
+            self.error(Diagnostic::error(line, e).at(source, name), file, origin);
+            return;
+        }
+
+        let note =
+            Diagnostic::note(line, format!("In macro {}, called here.", name)).at(source, name);
+        let inner = Origin {
+            site: Some(origin.site.unwrap_or((file, line))),
+            notes: [vec![self.in_file(note, file)], origin.notes.clone()].concat(),
+            calls: [origin.calls.clone(), vec![key]].concat(),
+        };
+        let n = self.expansions;
+        self.expansions += 1;
+        for (body_line, source) in &m.body {
+            let text = replace_names(source, |w| {
+                m.labels
+                    .iter()
+                    .any(|l| l == w)
+                    .then(|| format!("{}_M{}", w, n))
+            });
+            let text = replace_params(&text, &m.params, &args);
+            self.line(&text, m.file, *body_line, source, &inner);
+        }
+    }
+
+    /// Preprocesses `text`, which is line `line` of `file` or comes from it. `source` is that
+    /// line as written.
+    fn line(&mut self, text: &str, file: usize, line: u32, source: &str, origin: &Origin) {
+        let code = strip_comment(text);
+        let (label, rest) = split_label(code);
+        let stmt = rest.trim();
+        let (directive, args) = stmt.split_at(stmt.find(char::is_whitespace).unwrap_or(stmt.len()));
+
+        if let Some((name, mut m)) = self.defining.take() {
+            match directive {
+                ".end_macro" => self.macros.entry(name).or_default().push(m),
+                ".macro" => {
+                    let e = format!("Macro {} has no .end_macro before this .macro.", name);
+                    self.error(
+                        Diagnostic::error(line, e).at(source, ".macro"),
+                        file,
+                        origin,
+                    );
+                    self.defining = Some((name, m));
+                }
+                _ => {
+                    if let Some(label) = label {
+                        m.labels.push(label.to_string());
+                    }
+                    m.body.push((line, source.to_string()));
+                    self.defining = Some((name, m));
+                }
+            }
+            return;
+        }
+        if self.conditional(directive, args, file, line, source, origin) || !self.active() {
+            return;
+        }
+
+        let prefix = &code[..code.len() - rest.len()];
+        if let Some(label) = label {
+            if let Some(c) = self.consts.defs.get(label) {
+                let e = format!(
+                    "{} is already defined as a constant on line {}.",
+                    label, c.line
+                );
+                self.error(Diagnostic::error(line, e).at(source, label), file, origin);
+            }
+            self.labels.entry(label.to_string()).or_insert(line);
+        }
+
+        let kind = match directive {
+            ".eqv" => Some(Kind::Eqv),
+            ".equ" => Some(Kind::Equ),
+            // Otherwise .set reorder and the like
+            ".set" if args.contains(',') => Some(Kind::Set),
+            _ => None,
+        };
+        let result = match kind {
+            Some(kind) => self
+                .consts
+                .define(kind, directive, args, line, &self.labels),
+            None if directive == ".macro" => self.define_macro(args, file, line),
+            None if directive == ".end_macro" => {
+                Err((".end_macro without .macro.".to_string(), String::new()))
+            }
+            None if directive == ".include" => {
+                if label.is_some() {
+                    self.push(prefix.to_string(), file, line, source, origin);
+                }
+                self.include(args, file, line, origin)
+            }
+            None => {
+                let rest = self.consts.expand(rest);
+                let stmt = rest.trim_start();
+                let len = stmt
+                    .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+                    .unwrap_or(stmt.len());
+                let call = self.macros.contains_key(&stmt[..len]);
+                // A label on a call names the start of what the macro expands to
+                let text = if call {
+                    prefix.to_string()
+                } else {
+                    prefix.to_string() + &rest
+                };
+                if !text.trim().is_empty() {
+                    self.push(text, file, line, source, origin);
+                }
+                if call {
+                    self.call(&stmt[..len], &stmt[len..], file, line, source, origin);
+                }
+                Ok(())
+            }
+        };
+        if let Err((e, at)) = result {
+            self.error(Diagnostic::error(line, e).at(source, &at), file, origin);
+        }
+    }
+
+    fn push(&mut self, text: String, file: usize, line: u32, source: &str, origin: &Origin) {
+        self.stmts.push(Stmt {
+            text,
+            file,
+            line,
+            source: source.to_string(),
+            site: origin.site.unwrap_or((file, line)),
+            notes: origin.notes.clone(),
+        });
+    }
+}
+
+/// Defines the constants and macros of `source`, the text of the file `name`, and replaces them
+/// where they are used. Brings in the files it includes, and drops the lines its conditions
+/// skip. Returns the statements, the files read, starting with `name`, and the diagnostics.
+pub(crate) fn preprocess(
+    name: &str,
+    source: &str,
+    options: &Options,
+) -> (Vec<Stmt>, Vec<SourceFile>, Vec<Diagnostic>) {
+    let mut p = Preprocessor {
+        include_dirs: options.include_dirs.clone(),
+        ..Preprocessor::default()
+    };
+    for (name, value) in &options.defines {
+        let c = Constant {
+            kind: Kind::Eqv,
+            value: value.clone(),
+            line: 0,
+        };
+        p.consts.defs.insert(name.clone(), c);
+    }
+    p.files.push(SourceFile {
+        name: name.to_string(),
+        text: source.to_string(),
+        parent: None,
+    });
+    p.open.push((0, Path::new(name).canonicalize().ok()));
+    p.file(0, &Origin::default());
+    if let Some((name, m)) = p.defining.take() {
+        let e = format!("Macro {} has no .end_macro.", name);
+        let source = p.files[m.file].text.lines().nth(m.line as usize - 1);
+        let d = Diagnostic::error(m.line, e).at(source.unwrap_or(""), &name);
+        p.diags.push(p.in_file(d, m.file));
+    }
+    (p.stmts, p.files, p.diags)
+}
@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, Write};
+
+use crate::codes::Syntax;
+use crate::isa::Isa;
+use crate::memory::MemoryMap;
+use crate::tables::{decode, REGS};
+use crate::Program;
+
+const PAGE: u32 = 0x1000;
+
+// Coprocessor 0 registers
+const BAD_VADDR: usize = 8;
+const STATUS: usize = 12;
+const CAUSE: usize = 13;
+const EPC: usize = 14;
+/// Exception level bit of Status, set while handling an exception.
+const EXL: u32 = 2;
+/// Branch delay bit of Cause, set when the instruction at fault was in a delay slot.
+const BD: u32 = 1 << 31;
+
+/// Byte-addressable big-endian memory, the same byte order the assembler writes. Pages are made
+/// on the first write, and memory that was never written reads as zero.
+#[derive(Clone, Default)]
+pub struct Memory {
+    pages: HashMap<u32, Box<[u8; PAGE as usize]>>,
+}
+
+impl Memory {
+    pub fn load_byte(&self, adr: u32) -> u8 {
+        match self.pages.get(&(adr / PAGE)) {
+            Some(page) => page[(adr % PAGE) as usize],
+            None => 0,
+        }
+    }
+
+    pub fn store_byte(&mut self, adr: u32, value: u8) {
+        let page = self
+            .pages
+            .entry(adr / PAGE)
+            .or_insert_with(|| Box::new([0; PAGE as usize]));
+        page[(adr % PAGE) as usize] = value;
+    }
+
+    /// Reads `size` bytes starting at `adr` as one big-endian value.
+    pub fn load(&self, adr: u32, size: u32) -> u32 {
+        (0..size).fold(0, |v, i| {
+            (v << 8) | self.load_byte(adr.wrapping_add(i)) as u32
+        })
+    }
+
+    /// Writes the low `size` bytes of `value` big-endian starting at `adr`.
+    pub fn store(&mut self, adr: u32, size: u32, value: u32) {
+        for i in 0..size {
+            let shift = 8 * (size - 1 - i);
+            self.store_byte(adr.wrapping_add(i), (value >> shift) as u8);
+        }
+    }
+
+    pub fn store_bytes(&mut self, adr: u32, bytes: &[u8]) {
+        for (i, b) in bytes.iter().enumerate() {
+            self.store_byte(adr.wrapping_add(i as u32), *b);
+        }
+    }
+}
+
+/// Something that stops the processor in the middle of an instruction. The program counter is
+/// left at the instruction that raised it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Exception {
+    /// A `syscall` that the simulator does not provide.
+    Syscall(u32),
+    Breakpoint(u32),
+    Trap(u32),
+    Overflow,
+    /// Misaligned load, or a fetch from outside the text segment.
+    AddressLoad(u32),
+    AddressStore(u32),
+    ReservedInstruction(u32),
+    /// A syscall given arguments it cannot work with, such as bad input for a read.
+    BadSyscall(u32),
+}
+
+impl Exception {
+    /// The ExcCode that goes into Cause, or `None` for [`Exception::BadSyscall`], which only
+    /// the simulator raises.
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            Exception::AddressLoad(_) => Some(4),
+            Exception::AddressStore(_) => Some(5),
+            Exception::Syscall(_) => Some(8),
+            Exception::Breakpoint(_) => Some(9),
+            Exception::ReservedInstruction(_) => Some(10),
+            Exception::Overflow => Some(12),
+            Exception::Trap(_) => Some(13),
+            Exception::BadSyscall(_) => None,
+        }
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exception::Syscall(n) => write!(f, "Syscall {} is not supported", n),
+            Exception::Breakpoint(code) => write!(f, "Break instruction executed, code {}", code),
+            Exception::Trap(code) => write!(f, "Trap {} executed", code),
+            Exception::Overflow => write!(f, "Arithmetic overflow"),
+            Exception::AddressLoad(adr) => {
+                write!(f, "Address error on load or fetch at {:#010x}", adr)
+            }
+            Exception::AddressStore(adr) => write!(f, "Address error on store at {:#010x}", adr),
+            Exception::ReservedInstruction(word) => {
+                write!(f, "Reserved instruction {:#010x}", word)
+            }
+            Exception::BadSyscall(n) => write!(f, "Invalid arguments or input for syscall {}", n),
+        }
+    }
+}
+
+/// Why a run ended without an exception.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    /// The program made an exit syscall with this code.
+    Exit(i32),
+    /// Execution ran past the last instruction.
+    DroppedOff,
+    /// The step limit was reached.
+    StepLimit,
+}
+
+/// The processor: general purpose registers, HI/LO, the program counter, the FPU registers and
+/// condition flags, the coprocessor 0 registers, and memory laid out by the program's memory
+/// map.
+pub struct Cpu {
+    pub regs: [u32; 32],
+    pub fpr: [u32; 32],
+    /// FPU condition flags 0 to 7, one bit each.
+    pub fcc: u8,
+    /// Coprocessor 0, of which BadVAddr (8), Status (12), Cause (13) and EPC (14) are used.
+    pub cp0: [u32; 32],
+    pub hi: u32,
+    pub lo: u32,
+    pub pc: u32,
+    pub mem: Memory,
+    pub memory: MemoryMap,
+    /// The instruction set the program was assembled for, which decides how words decode.
+    pub isa: Isa,
+    /// Whether the instruction after a branch or jump runs before it lands.
+    pub delayed_branches: bool,
+    /// Where a taken branch goes once its delay slot has run.
+    delay: Option<u32>,
+    text_start: u32,
+    text_end: u32,
+    ktext_start: u32,
+    ktext_end: u32,
+    ll_bit: bool,
+    /// Next address sbrk hands out.
+    pub(crate) brk: u32,
+    /// Files opened with syscall 13, by descriptor.
+    pub(crate) files: HashMap<u32, File>,
+}
+
+impl Cpu {
+    /// Loads a program, with `$gp` and `$sp` set from its memory map and the PC at the first
+    /// instruction.
+    pub fn new(program: &Program) -> Cpu {
+        let mut mem = Memory::default();
+        mem.store_bytes(program.text_start, &program.text);
+        mem.store_bytes(program.data_start, &program.data);
+        mem.store_bytes(program.ktext_start, &program.ktext);
+        mem.store_bytes(program.kdata_start, &program.kdata);
+        let mut regs = [0; 32];
+        // Interrupt mask all set and interrupts enabled, in user mode, as MARS starts out
+        let mut cp0 = [0; 32];
+        cp0[STATUS] = 0x0000_ff11;
+        regs[28] = program.memory.gp;
+        regs[29] = program.memory.sp;
+        Cpu {
+            regs,
+            fpr: [0; 32],
+            fcc: 0,
+            cp0,
+            hi: 0,
+            lo: 0,
+            pc: program.text_start,
+            mem,
+            memory: program.memory,
+            isa: program.isa,
+            delayed_branches: program.delayed_branches,
+            delay: None,
+            text_start: program.text_start,
+            text_end: program.text_start + program.text.len() as u32,
+            ktext_start: program.ktext_start,
+            ktext_end: program.ktext_start + program.ktext.len() as u32,
+            ll_bit: false,
+            brk: program.memory.heap,
+            files: HashMap::new(),
+        }
+    }
+
+    fn set(&mut self, r: u32, value: u32) {
+        if r != 0 {
+            self.regs[r as usize] = value;
+        }
+    }
+
+    fn load(&self, adr: u32, size: u32) -> Result<u32, Exception> {
+        if !adr.is_multiple_of(size) {
+            return Err(Exception::AddressLoad(adr));
+        }
+        Ok(self.mem.load(adr, size))
+    }
+
+    fn store(&mut self, adr: u32, size: u32, value: u32) -> Result<(), Exception> {
+        if !adr.is_multiple_of(size) {
+            return Err(Exception::AddressStore(adr));
+        }
+        self.mem.store(adr, size, value);
+        Ok(())
+    }
+
+    /// Executes the instruction at the PC. A `syscall` comes back as [`Exception::Syscall`]
+    /// holding `$v0`, for the caller to carry out before moving the PC on.
+    pub fn step(&mut self) -> Result<(), Exception> {
+        let in_text = (self.text_start..self.text_end).contains(&self.pc)
+            || (self.ktext_start..self.ktext_end).contains(&self.pc);
+        if !in_text || !self.pc.is_multiple_of(4) {
+            return Err(Exception::AddressLoad(self.pc));
+        }
+        let word = self.mem.load(self.pc, 4);
+        let instr = decode(word, self.isa).ok_or(Exception::ReservedInstruction(word))?;
+
+        let rs = (word >> 21) & 31;
+        let rt = (word >> 16) & 31;
+        let rd = (word >> 11) & 31;
+        let sa = (word >> 6) & 31;
+        let imm = word as u16 as u32;
+        let simm = word as i16 as i32 as u32;
+        let (s, t) = (self.regs[rs as usize], self.regs[rt as usize]);
+        // Release 6 ll and sc have a 9-bit offset
+        let adr = match instr.syntax {
+            Syntax::AtomicR6 => s.wrapping_add((((word << 16) as i32) >> 23) as u32),
+            _ => s.wrapping_add(simm),
+        };
+        let next = self.pc.wrapping_add(4);
+        let branch = next.wrapping_add(simm << 2);
+        // Returns skip the delay slot, which has already run
+        let link = match self.delayed_branches {
+            true => next.wrapping_add(4),
+            false => next,
+        };
+        let mut pc = next;
+
+        match instr.name {
+            _ if matches!(instr.syntax, Syntax::MulDivR6) => {
+                let (a, b) = (s as i32, t as i32);
+                let v = match instr.name {
+                    "mul" => Some(a.wrapping_mul(b) as u32),
+                    "muh" => Some(((a as i64 * b as i64) >> 32) as u32),
+                    "mulu" => Some(s.wrapping_mul(t)),
+                    "muhu" => Some(((s as u64 * t as u64) >> 32) as u32),
+                    "div" => (t != 0).then(|| a.wrapping_div(b) as u32),
+                    "mod" => (t != 0).then(|| a.wrapping_rem(b) as u32),
+                    "divu" => s.checked_div(t),
+                    _ => s.checked_rem(t),
+                };
+                // As with div, dividing by zero leaves the destination alone
+                if let Some(v) = v {
+                    self.set(rd, v);
+                }
+            }
+            "add" => {
+                let v = (s as i32)
+                    .checked_add(t as i32)
+                    .ok_or(Exception::Overflow)?;
+                self.set(rd, v as u32);
+            }
+            "addu" => self.set(rd, s.wrapping_add(t)),
+            "addi" => {
+                let v = (s as i32)
+                    .checked_add(simm as i32)
+                    .ok_or(Exception::Overflow)?;
+                self.set(rt, v as u32);
+            }
+            "addiu" => self.set(rt, s.wrapping_add(simm)),
+            "sub" => {
+                let v = (s as i32)
+                    .checked_sub(t as i32)
+                    .ok_or(Exception::Overflow)?;
+                self.set(rd, v as u32);
+            }
+            "subu" => self.set(rd, s.wrapping_sub(t)),
+            "and" => self.set(rd, s & t),
+            "andi" => self.set(rt, s & imm),
+            "or" => self.set(rd, s | t),
+            "ori" => self.set(rt, s | imm),
+            "xor" => self.set(rd, s ^ t),
+            "xori" => self.set(rt, s ^ imm),
+            "nor" => self.set(rd, !(s | t)),
+            "slt" => self.set(rd, ((s as i32) < (t as i32)) as u32),
+            "sltu" => self.set(rd, (s < t) as u32),
+            "slti" => self.set(rt, ((s as i32) < (simm as i32)) as u32),
+            "sltiu" => self.set(rt, (s < simm) as u32),
+            "sll" => self.set(rd, t << sa),
+            "srl" => self.set(rd, t >> sa),
+            "sra" => self.set(rd, ((t as i32) >> sa) as u32),
+            "sllv" => self.set(rd, t << (s & 31)),
+            "srlv" => self.set(rd, t >> (s & 31)),
+            "srav" => self.set(rd, ((t as i32) >> (s & 31)) as u32),
+            "rotr" => self.set(rd, t.rotate_right(sa)),
+            "rotrv" => self.set(rd, t.rotate_right(s & 31)),
+            "ext" => {
+                let size = rd + 1;
+                self.set(rt, (s >> sa) & (u32::MAX >> (32 - size)));
+            }
+            "ins" => {
+                let mask = (u32::MAX >> (31 - rd)) & (u32::MAX << sa);
+                self.set(rt, (t & !mask) | ((s << sa) & mask));
+            }
+            "wsbh" => self.set(rd, ((t & 0x00ff_00ff) << 8) | ((t >> 8) & 0x00ff_00ff)),
+            "seb" => self.set(rd, t as i8 as i32 as u32),
+            "seh" => self.set(rd, t as i16 as i32 as u32),
+            "clz" => self.set(rd, s.leading_zeros()),
+            "clo" => self.set(rd, s.leading_ones()),
+            "movn" => {
+                if t != 0 {
+                    self.set(rd, s);
+                }
+            }
+            "movz" => {
+                if t == 0 {
+                    self.set(rd, s);
+                }
+            }
+            "lui" => self.set(rt, imm << 16),
+            "lhi" => self.set(rt, (imm << 16) | (t & 0xffff)),
+            "llo" => self.set(rt, (t & 0xffff_0000) | imm),
+            "mult" | "mul" => {
+                let v = (s as i32 as i64).wrapping_mul(t as i32 as i64) as u64;
+                self.hi = (v >> 32) as u32;
+                self.lo = v as u32;
+                if instr.name == "mul" {
+                    self.set(rd, v as u32);
+                }
+            }
+            "multu" => {
+                let v = s as u64 * t as u64;
+                self.hi = (v >> 32) as u32;
+                self.lo = v as u32;
+            }
+            // Like MARS, dividing by zero leaves HI and LO alone
+            "div" => {
+                if t != 0 {
+                    self.lo = (s as i32).wrapping_div(t as i32) as u32;
+                    self.hi = (s as i32).wrapping_rem(t as i32) as u32;
+                }
+            }
+            "divu" => {
+                if let (Some(q), Some(r)) = (s.checked_div(t), s.checked_rem(t)) {
+                    self.lo = q;
+                    self.hi = r;
+                }
+            }
+            "madd" | "maddu" | "msub" | "msubu" => {
+                let acc = ((self.hi as u64) << 32) | self.lo as u64;
+                let v = if instr.name.ends_with('u') {
+                    s as u64 * t as u64
+                } else {
+                    (s as i32 as i64).wrapping_mul(t as i32 as i64) as u64
+                };
+                let v = if instr.name.starts_with("madd") {
+                    acc.wrapping_add(v)
+                } else {
+                    acc.wrapping_sub(v)
+                };
+                self.hi = (v >> 32) as u32;
+                self.lo = v as u32;
+            }
+            "mfhi" => self.set(rd, self.hi),
+            "mflo" => self.set(rd, self.lo),
+            "mthi" => self.hi = s,
+            "mtlo" => self.lo = s,
+            "mfc0" => self.set(rt, self.cp0[rd as usize]),
+            "mtc0" => self.cp0[rd as usize] = t,
+            "eret" => {
+                self.cp0[STATUS] &= !EXL;
+                pc = self.cp0[EPC];
+            }
+            "mfc1" => self.set(rt, self.fpr[rd as usize]),
+            "mtc1" => self.fpr[rd as usize] = t,
+            "beq" => {
+                if s == t {
+                    pc = branch;
+                }
+            }
+            "bne" => {
+                if s != t {
+                    pc = branch;
+                }
+            }
+            "blez" => {
+                if s as i32 <= 0 {
+                    pc = branch;
+                }
+            }
+            "bgtz" => {
+                if s as i32 > 0 {
+                    pc = branch;
+                }
+            }
+            "bgez" => {
+                if s as i32 >= 0 {
+                    pc = branch;
+                }
+            }
+            "bltz" => {
+                if (s as i32) < 0 {
+                    pc = branch;
+                }
+            }
+            "j" | "jal" => {
+                if instr.name == "jal" {
+                    self.set(31, link);
+                }
+                pc = (next & 0xf000_0000) | ((word & 0x03ff_ffff) << 2);
+            }
+            "bc" | "balc" => {
+                if instr.name == "balc" {
+                    self.set(31, next);
+                }
+                pc = next.wrapping_add((((word << 6) as i32) >> 4) as u32);
+            }
+            "beqzc" | "bnezc" => {
+                if (s == 0) == (instr.name == "beqzc") {
+                    pc = next.wrapping_add((((word << 11) as i32) >> 9) as u32);
+                }
+            }
+            "jr" => pc = s,
+            "jalr" => {
+                self.set(rd, link);
+                pc = s;
+            }
+            "lb" => self.set(rt, self.load(adr, 1)? as i8 as i32 as u32),
+            "lbu" => self.set(rt, self.load(adr, 1)?),
+            "lh" => self.set(rt, self.load(adr, 2)? as i16 as i32 as u32),
+            "lhu" => self.set(rt, self.load(adr, 2)?),
+            "lw" => self.set(rt, self.load(adr, 4)?),
+            "sb" => self.store(adr, 1, t)?,
+            "sh" => self.store(adr, 2, t)?,
+            "sw" => self.store(adr, 4, t)?,
+            "lwc1" => self.fpr[rt as usize] = self.load(adr, 4)?,
+            "swc1" => self.store(adr, 4, self.fpr[rt as usize])?,
+            "ldc1" | "sdc1" => {
+                if !rt.is_multiple_of(2) {
+                    return Err(Exception::ReservedInstruction(word));
+                }
+                if adr % 8 != 0 {
+                    return Err(if instr.name == "ldc1" {
+                        Exception::AddressLoad(adr)
+                    } else {
+                        Exception::AddressStore(adr)
+                    });
+                }
+                // The even register holds the low word
+                let (lo, hi) = (rt as usize, rt as usize + 1);
+                if instr.name == "ldc1" {
+                    self.fpr[hi] = self.load(adr, 4)?;
+                    self.fpr[lo] = self.load(adr + 4, 4)?;
+                } else {
+                    self.store(adr, 4, self.fpr[hi])?;
+                    self.store(adr + 4, 4, self.fpr[lo])?;
+                }
+            }
+            // Unaligned word access, big-endian
+            "lwl" | "lwr" | "swl" | "swr" => {
+                let base = adr & !3;
+                let k = adr & 3;
+                let w = self.mem.load(base, 4);
+                match instr.name {
+                    "lwl" => {
+                        let keep = if k == 0 { 0 } else { u32::MAX >> (32 - 8 * k) };
+                        self.set(rt, (w << (8 * k)) | (t & keep));
+                    }
+                    "lwr" => {
+                        let keep = if k == 3 { 0 } else { u32::MAX << (8 * (k + 1)) };
+                        self.set(rt, (w >> (8 * (3 - k))) | (t & keep));
+                    }
+                    "swl" => {
+                        let keep = if k == 0 { 0 } else { u32::MAX << (32 - 8 * k) };
+                        self.mem.store(base, 4, (t >> (8 * k)) | (w & keep));
+                    }
+                    _ => {
+                        let keep = if k == 3 { 0 } else { u32::MAX >> (8 * (k + 1)) };
+                        self.mem.store(base, 4, (t << (8 * (3 - k))) | (w & keep));
+                    }
+                }
+            }
+            "ll" => {
+                self.set(rt, self.load(adr, 4)?);
+                self.ll_bit = true;
+            }
+            "sc" => {
+                if self.ll_bit {
+                    self.store(adr, 4, t)?;
+                }
+                self.set(rt, self.ll_bit as u32);
+                self.ll_bit = false;
+            }
+            "bc1t" | "bc1f" => {
+                if self.flag((word >> 18) & 7) == (instr.name == "bc1t") {
+                    pc = branch;
+                }
+            }
+            // Release 6 tests bit 0 of an FPU register, which its compares set to all ones or zero
+            "bc1eqz" | "bc1nez" => {
+                let ft = (word >> 16) & 31;
+                if (self.fpr[ft as usize] & 1 == 0) == (instr.name == "bc1eqz") {
+                    pc = branch;
+                }
+            }
+            "movf" | "movt" => {
+                if self.flag((word >> 18) & 7) == (instr.name == "movt") {
+                    self.set(rd, s);
+                }
+            }
+            name if name.contains('.') => self.fpu(instr, word)?,
+            "syscall" => return Err(Exception::Syscall(self.regs[2])),
+            "break" => return Err(Exception::Breakpoint((word >> 6) & 0xfffff)),
+            "trap" => return Err(Exception::Trap(word & 0x03ff_ffff)),
+            "teq" | "tne" | "tge" | "tgeu" | "tlt" | "tltu" | "teqi" | "tnei" | "tgei"
+            | "tgeiu" | "tlti" | "tltiu" => {
+                // The immediate forms compare with the sign-extended immediate, and have no code
+                let (t, code) = match instr.syntax {
+                    Syntax::TrapImm => (simm, 0),
+                    _ => (t, (word >> 6) & 0x3ff),
+                };
+                let taken = match instr.name {
+                    "teq" | "teqi" => s == t,
+                    "tne" | "tnei" => s != t,
+                    "tge" | "tgei" => s as i32 >= t as i32,
+                    "tgeu" | "tgeiu" => s >= t,
+                    "tlt" | "tlti" => (s as i32) < t as i32,
+                    _ => s < t,
+                };
+                if taken {
+                    return Err(Exception::Trap(code));
+                }
+            }
+            "sync" | "nop" => {}
+            _ => return Err(Exception::ReservedInstruction(word)),
+        }
+        // A taken branch lands once the instruction after it has run too
+        let target = (self.delayed_branches && instr.has_delay_slot() && pc != next).then_some(pc);
+        self.pc = match (self.delay, target) {
+            (Some(after_slot), _) => after_slot,
+            (None, Some(_)) => next,
+            (None, None) => pc,
+        };
+        self.delay = target;
+        Ok(())
+    }
+
+    /// Goes to the exception handler for `e` as the processor does: EPC gets the address of the
+    /// instruction at fault, or of the branch if it was in a delay slot, Cause the exception code
+    /// and the branch delay bit, BadVAddr the address of an address
+    /// error, and Status the exception level bit. Returns false and changes nothing if there is
+    /// no handler at `memory.exception_handler`, or if the exception came from the handler
+    /// itself.
+    pub fn raise(&mut self, e: Exception) -> bool {
+        let handler = self.memory.exception_handler;
+        let code = match e.code() {
+            Some(code) => code,
+            None => return false,
+        };
+        if !(self.ktext_start..self.ktext_end).contains(&handler) || self.cp0[STATUS] & EXL != 0 {
+            return false;
+        }
+        if let Exception::AddressLoad(adr) | Exception::AddressStore(adr) = e {
+            self.cp0[BAD_VADDR] = adr;
+        }
+        let in_slot = self.delay.take().is_some();
+        self.cp0[EPC] = if in_slot { self.pc - 4 } else { self.pc };
+        self.cp0[CAUSE] = (self.cp0[CAUSE] & !(BD | 0x7c)) | (in_slot as u32 * BD) | (code << 2);
+        self.cp0[STATUS] |= EXL;
+        self.pc = handler;
+        true
+    }
+
+    /// Runs until the program exits, falls off the end of the text segment, raises an exception
+    /// it has no handler for, or has executed `max_steps` instructions. Syscalls read from
+    /// `input` and print to `output`; only those the simulator does not provide go to the
+    /// handler.
+    pub fn run(
+        &mut self,
+        max_steps: Option<u64>,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<Stop, Exception> {
+        let mut steps = 0;
+        loop {
+            if self.pc == self.text_end
+                || self.pc == self.ktext_end && self.ktext_end > self.ktext_start
+            {
+                return Ok(Stop::DroppedOff);
+            }
+            if max_steps.is_some_and(|max| steps >= max) {
+                return Ok(Stop::StepLimit);
+            }
+            steps += 1;
+            let result = match self.step() {
+                Err(Exception::Syscall(_)) => {
+                    let stop = self.syscall(input, output);
+                    // Whatever the program printed should show up before any error or exit
+                    let _ = output.flush();
+                    match stop {
+                        Ok(Some(stop)) => return Ok(stop),
+                        Ok(None) => {
+                            self.pc = self.delay.take().unwrap_or(self.pc + 4);
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                result => result,
+            };
+            if let Err(e) = result {
+                if !self.raise(e) {
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// The registers as a table, one per line, the way MARS shows them.
+    pub fn dump_registers(&self) -> String {
+        let mut out = String::new();
+        for (n, name) in REGS.iter().enumerate() {
+            out += &format!("${:<5}{:>2}  0x{:08x}\n", name, n, self.regs[n]);
+        }
+        out += &format!("{:<9}0x{:08x}\n", "pc", self.pc);
+        out += &format!("{:<9}0x{:08x}\n", "hi", self.hi);
+        out += &format!("{:<9}0x{:08x}\n", "lo", self.lo);
+        out
+    }
+}
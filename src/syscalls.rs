@@ -0,0 +1,221 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, Read, Write};
+
+use crate::simulator::{Cpu, Exception, Stop};
+
+/// Formats a float the way MARS (that is, Java) prints it: at least one digit after the point,
+/// and scientific notation outside 10^-3..10^7.
+fn java_float(v: f64, text: String, sci: String) -> String {
+    if v.is_nan() {
+        return "NaN".into();
+    }
+    if v.is_infinite() {
+        return if v > 0.0 { "Infinity" } else { "-Infinity" }.into();
+    }
+    if v == 0.0 || (1e-3..1e7).contains(&v.abs()) {
+        if text.contains('.') {
+            text
+        } else {
+            text + ".0"
+        }
+    } else {
+        let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+        if mantissa.contains('.') {
+            format!("{}E{}", mantissa, exp)
+        } else {
+            format!("{}.0E{}", mantissa, exp)
+        }
+    }
+}
+
+/// Reads one line of input without its line ending. `None` at the end of input.
+fn read_line(input: &mut dyn BufRead) -> Option<String> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+impl Cpu {
+    /// Reads the NUL-terminated string at `adr`.
+    fn read_string(&self, adr: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut adr = adr;
+        loop {
+            let b = self.mem.load_byte(adr);
+            if b == 0 {
+                return bytes;
+            }
+            bytes.push(b);
+            adr = adr.wrapping_add(1);
+        }
+    }
+
+    fn double_arg(&self) -> f64 {
+        f64::from_bits(((self.fpr[13] as u64) << 32) | self.fpr[12] as u64)
+    }
+
+    /// Carries out the syscall the PC is at, using the MARS service number in `$v0`. Returns
+    /// how the program stopped if the service was an exit.
+    pub(crate) fn syscall(
+        &mut self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<Option<Stop>, Exception> {
+        let service = self.regs[2];
+        let bad = Exception::BadSyscall(service);
+        let (a0, a1, a2) = (self.regs[4], self.regs[5], self.regs[6]);
+        let failed = -1i32 as u32;
+
+        match service {
+            // print int
+            1 => write!(output, "{}", a0 as i32).map_err(|_| bad)?,
+            // print float
+            2 => {
+                let f = f32::from_bits(self.fpr[12]);
+                let text = java_float(f as f64, f.to_string(), format!("{:e}", f));
+                write!(output, "{}", text).map_err(|_| bad)?;
+            }
+            // print double
+            3 => {
+                let d = self.double_arg();
+                let text = java_float(d, d.to_string(), format!("{:e}", d));
+                write!(output, "{}", text).map_err(|_| bad)?;
+            }
+            // print string
+            4 => output.write_all(&self.read_string(a0)).map_err(|_| bad)?,
+            // read int
+            5 => {
+                let _ = output.flush();
+                let line = read_line(input).ok_or(bad)?;
+                let n: i32 = line.trim().parse().map_err(|_| bad)?;
+                self.regs[2] = n as u32;
+            }
+            // read float
+            6 => {
+                let _ = output.flush();
+                let line = read_line(input).ok_or(bad)?;
+                let f: f32 = line.trim().parse().map_err(|_| bad)?;
+                self.fpr[0] = f.to_bits();
+            }
+            // read double
+            7 => {
+                let _ = output.flush();
+                let line = read_line(input).ok_or(bad)?;
+                let d: f64 = line.trim().parse().map_err(|_| bad)?;
+                self.fpr[0] = d.to_bits() as u32;
+                self.fpr[1] = (d.to_bits() >> 32) as u32;
+            }
+            // read string: at most $a1 - 1 characters, newline included, then a NUL
+            8 => {
+                let _ = output.flush();
+                let max = a1 as i32;
+                if max >= 1 {
+                    let mut line = Vec::new();
+                    let _ = input.read_until(b'\n', &mut line);
+                    line.truncate(max as usize - 1);
+                    line.push(0);
+                    self.mem.store_bytes(a0, &line);
+                }
+            }
+            // sbrk, in whole words
+            9 => {
+                let amount = a0 as i32;
+                if amount < 0 {
+                    return Err(bad);
+                }
+                self.regs[2] = self.brk;
+                self.brk = self.brk.wrapping_add((amount as u32 + 3) & !3);
+            }
+            10 => return Ok(Some(Stop::Exit(0))),
+            // print char
+            11 => output.write_all(&[a0 as u8]).map_err(|_| bad)?,
+            // read char
+            12 => {
+                let _ = output.flush();
+                let mut c = [0];
+                input.read_exact(&mut c).map_err(|_| bad)?;
+                self.regs[2] = c[0] as u32;
+            }
+            // open: flags 0 read, 1 write, 9 append. $v0 is the descriptor or -1.
+            13 => {
+                let name = String::from_utf8_lossy(&self.read_string(a0)).into_owned();
+                let file = match a1 {
+                    0 => File::open(&name),
+                    1 => File::create(&name),
+                    9 => OpenOptions::new().append(true).create(true).open(&name),
+                    _ => Err(std::io::ErrorKind::InvalidInput.into()),
+                };
+                self.regs[2] = match file {
+                    Ok(file) => {
+                        let fd = (3..).find(|fd| !self.files.contains_key(fd)).unwrap();
+                        self.files.insert(fd, file);
+                        fd
+                    }
+                    Err(_) => failed,
+                };
+            }
+            // read: $a1 buffer, $a2 length. $v0 is the count read, 0 at the end, or -1. The
+            // length stops at the top of memory, and only as much as there is to read is held.
+            14 if (a2 as i32) < 0 => self.regs[2] = failed,
+            14 => {
+                let len = (a2 as u64).min((1 << 32) - a1 as u64);
+                let mut buf = Vec::new();
+                let read = match a0 {
+                    0 => {
+                        let _ = output.flush();
+                        let filled = input.fill_buf().map(|avail| {
+                            buf.extend(&avail[..avail.len().min(len as usize)]);
+                        });
+                        input.consume(buf.len());
+                        filled
+                    }
+                    fd => match self.files.get_mut(&fd) {
+                        Some(file) => file.take(len).read_to_end(&mut buf).map(|_| ()),
+                        None => Err(std::io::ErrorKind::NotFound.into()),
+                    },
+                };
+                self.regs[2] = match read {
+                    Ok(()) => {
+                        self.mem.store_bytes(a1, &buf);
+                        buf.len() as u32
+                    }
+                    Err(_) => failed,
+                };
+            }
+            // write: $a1 buffer, $a2 length. $v0 is the count written or -1. Like read, the
+            // length stops at the top of memory, and the bytes go out a block at a time.
+            15 if (a2 as i32) < 0 => self.regs[2] = failed,
+            15 => {
+                let len = (a2 as u64).min((1 << 32) - a1 as u64) as u32;
+                let mut stderr = std::io::stderr();
+                let out: Option<&mut dyn Write> = match a0 {
+                    1 => Some(output),
+                    2 => Some(&mut stderr),
+                    fd => self.files.get_mut(&fd).map(|f| f as &mut dyn Write),
+                };
+                let written = match out {
+                    Some(out) => (0..len).step_by(4096).try_for_each(|start| {
+                        let block: Vec<u8> = (start..len.min(start.saturating_add(4096)))
+                            .map(|i| self.mem.load_byte(a1.wrapping_add(i)))
+                            .collect();
+                        out.write_all(&block)
+                    }),
+                    None => Err(std::io::ErrorKind::NotFound.into()),
+                };
+                self.regs[2] = match written {
+                    Ok(()) => len,
+                    Err(_) => failed,
+                };
+            }
+            // close
+            16 => {
+                self.files.remove(&a0);
+            }
+            17 => return Ok(Some(Stop::Exit(a0 as i32))),
+            n => return Err(Exception::Syscall(n)),
+        }
+        Ok(None)
+    }
+}
@@ -1,9 +1,11 @@
-use crate::codes::Arg;
-use crate::{rem_spaces, Args, Syntax};
+use crate::codes::{fits, get_pattern, is_expr, is_label_name, parse_num, OpKind, Syntax};
+use crate::diagnostic::Diagnostic;
+use crate::expr::Expr;
+use crate::isa::Isa;
+use crate::{rem_spaces, Options, RelocKind};
 use std::collections::HashMap;
-use std::str::Split;
 
-static REGS: [&str; 32] = [
+pub(crate) static REGS: [&str; 32] = [
     "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
     "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
     "ra",
@@ -15,7 +17,7 @@ pub struct InstrCode<'a> {
     pub(crate) code: i8,
 }
 
-static CODES: [InstrCode; 68] = [
+static CODES: [InstrCode; 184] = [
     InstrCode {
         name: "null",
         syntax: Syntax::Syscall,
@@ -48,7 +50,7 @@ static CODES: [InstrCode; 68] = [
     },
     InstrCode {
         name: "andi",
-        syntax: Syntax::ArithLogI,
+        syntax: Syntax::LogicI,
         code: 12,
     },
     InstrCode {
@@ -83,9 +85,15 @@ static CODES: [InstrCode; 68] = [
     },
     InstrCode {
         name: "ori",
-        syntax: Syntax::ArithLogI,
+        syntax: Syntax::LogicI,
         code: 13,
     },
+    // Before sll, so the zero word disassembles as nop
+    InstrCode {
+        name: "nop",
+        syntax: Syntax::Syscall,
+        code: 0,
+    },
     InstrCode {
         name: "sll",
         syntax: Syntax::Shift,
@@ -133,7 +141,7 @@ static CODES: [InstrCode; 68] = [
     },
     InstrCode {
         name: "xori",
-        syntax: Syntax::ArithLogI,
+        syntax: Syntax::LogicI,
         code: 14,
     },
     InstrCode {
@@ -154,7 +162,7 @@ static CODES: [InstrCode; 68] = [
     InstrCode {
         name: "sltu",
         syntax: Syntax::ArithLog,
-        code: 41,
+        code: 43,
     },
     InstrCode {
         name: "slti",
@@ -164,7 +172,7 @@ static CODES: [InstrCode; 68] = [
     InstrCode {
         name: "sltiu",
         syntax: Syntax::ArithLogI,
-        code: 9,
+        code: 11,
     },
     InstrCode {
         name: "beq",
@@ -291,6 +299,11 @@ static CODES: [InstrCode; 68] = [
         syntax: Syntax::RegImmBranch,
         code: 1,
     },
+    InstrCode {
+        name: "bltz",
+        syntax: Syntax::RegImmBranch,
+        code: 0,
+    },
     InstrCode {
         name: "mfc1",
         syntax: Syntax::CoProc1Move,
@@ -301,6 +314,21 @@ static CODES: [InstrCode; 68] = [
         syntax: Syntax::CoProc1Move,
         code: 4,
     },
+    InstrCode {
+        name: "mfc0",
+        syntax: Syntax::CoProc0Move,
+        code: 0,
+    },
+    InstrCode {
+        name: "mtc0",
+        syntax: Syntax::CoProc0Move,
+        code: 4,
+    },
+    InstrCode {
+        name: "eret",
+        syntax: Syntax::ExcReturn,
+        code: 24,
+    },
     InstrCode {
         name: "break",
         syntax: Syntax::Break,
@@ -309,7 +337,7 @@ static CODES: [InstrCode; 68] = [
     InstrCode {
         name: "lwr",
         syntax: Syntax::LoadStore,
-        code: 34,
+        code: 38,
     },
     InstrCode {
         name: "swr",
@@ -319,7 +347,7 @@ static CODES: [InstrCode; 68] = [
     InstrCode {
         name: "lwl",
         syntax: Syntax::LoadStore,
-        code: 38,
+        code: 34,
     },
     InstrCode {
         name: "swl",
@@ -349,95 +377,1235 @@ static CODES: [InstrCode; 68] = [
     InstrCode {
         name: "sc",
         syntax: Syntax::AtomicLoadStore,
-        code: 38,
+        code: 56,
     },
     InstrCode {
         name: "ll",
         syntax: Syntax::AtomicLoadStore,
+        code: 48,
+    },
+    InstrCode {
+        name: "add.s",
+        syntax: Syntax::FpArith,
+        code: 0,
+    },
+    InstrCode {
+        name: "add.d",
+        syntax: Syntax::FpArith,
+        code: 0,
+    },
+    InstrCode {
+        name: "sub.s",
+        syntax: Syntax::FpArith,
+        code: 1,
+    },
+    InstrCode {
+        name: "sub.d",
+        syntax: Syntax::FpArith,
+        code: 1,
+    },
+    InstrCode {
+        name: "mul.s",
+        syntax: Syntax::FpArith,
+        code: 2,
+    },
+    InstrCode {
+        name: "mul.d",
+        syntax: Syntax::FpArith,
+        code: 2,
+    },
+    InstrCode {
+        name: "div.s",
+        syntax: Syntax::FpArith,
+        code: 3,
+    },
+    InstrCode {
+        name: "div.d",
+        syntax: Syntax::FpArith,
+        code: 3,
+    },
+    InstrCode {
+        name: "sqrt.s",
+        syntax: Syntax::FpUnary,
+        code: 4,
+    },
+    InstrCode {
+        name: "sqrt.d",
+        syntax: Syntax::FpUnary,
+        code: 4,
+    },
+    InstrCode {
+        name: "abs.s",
+        syntax: Syntax::FpUnary,
+        code: 5,
+    },
+    InstrCode {
+        name: "abs.d",
+        syntax: Syntax::FpUnary,
+        code: 5,
+    },
+    InstrCode {
+        name: "mov.s",
+        syntax: Syntax::FpUnary,
+        code: 6,
+    },
+    InstrCode {
+        name: "mov.d",
+        syntax: Syntax::FpUnary,
+        code: 6,
+    },
+    InstrCode {
+        name: "neg.s",
+        syntax: Syntax::FpUnary,
+        code: 7,
+    },
+    InstrCode {
+        name: "neg.d",
+        syntax: Syntax::FpUnary,
+        code: 7,
+    },
+    InstrCode {
+        name: "round.w.s",
+        syntax: Syntax::FpUnary,
+        code: 12,
+    },
+    InstrCode {
+        name: "round.w.d",
+        syntax: Syntax::FpUnary,
+        code: 12,
+    },
+    InstrCode {
+        name: "trunc.w.s",
+        syntax: Syntax::FpUnary,
+        code: 13,
+    },
+    InstrCode {
+        name: "trunc.w.d",
+        syntax: Syntax::FpUnary,
+        code: 13,
+    },
+    InstrCode {
+        name: "ceil.w.s",
+        syntax: Syntax::FpUnary,
+        code: 14,
+    },
+    InstrCode {
+        name: "ceil.w.d",
+        syntax: Syntax::FpUnary,
+        code: 14,
+    },
+    InstrCode {
+        name: "floor.w.s",
+        syntax: Syntax::FpUnary,
+        code: 15,
+    },
+    InstrCode {
+        name: "floor.w.d",
+        syntax: Syntax::FpUnary,
+        code: 15,
+    },
+    InstrCode {
+        name: "cvt.s.d",
+        syntax: Syntax::FpUnary,
+        code: 32,
+    },
+    InstrCode {
+        name: "cvt.s.w",
+        syntax: Syntax::FpUnary,
+        code: 32,
+    },
+    InstrCode {
+        name: "cvt.d.s",
+        syntax: Syntax::FpUnary,
+        code: 33,
+    },
+    InstrCode {
+        name: "cvt.d.w",
+        syntax: Syntax::FpUnary,
+        code: 33,
+    },
+    InstrCode {
+        name: "cvt.w.s",
+        syntax: Syntax::FpUnary,
+        code: 36,
+    },
+    InstrCode {
+        name: "cvt.w.d",
+        syntax: Syntax::FpUnary,
+        code: 36,
+    },
+    InstrCode {
+        name: "c.eq.s",
+        syntax: Syntax::FpCompare,
+        code: 50,
+    },
+    InstrCode {
+        name: "c.eq.d",
+        syntax: Syntax::FpCompare,
+        code: 50,
+    },
+    InstrCode {
+        name: "c.lt.s",
+        syntax: Syntax::FpCompare,
+        code: 60,
+    },
+    InstrCode {
+        name: "c.lt.d",
+        syntax: Syntax::FpCompare,
+        code: 60,
+    },
+    InstrCode {
+        name: "c.le.s",
+        syntax: Syntax::FpCompare,
+        code: 62,
+    },
+    InstrCode {
+        name: "c.le.d",
+        syntax: Syntax::FpCompare,
+        code: 62,
+    },
+    InstrCode {
+        name: "bc1t",
+        syntax: Syntax::FpBranch,
+        code: 8,
+    },
+    InstrCode {
+        name: "bc1f",
+        syntax: Syntax::FpBranch,
+        code: 8,
+    },
+    InstrCode {
+        name: "movf.s",
+        syntax: Syntax::FpMoveCond,
+        code: 17,
+    },
+    InstrCode {
+        name: "movf.d",
+        syntax: Syntax::FpMoveCond,
+        code: 17,
+    },
+    InstrCode {
+        name: "movt.s",
+        syntax: Syntax::FpMoveCond,
+        code: 17,
+    },
+    InstrCode {
+        name: "movt.d",
+        syntax: Syntax::FpMoveCond,
+        code: 17,
+    },
+    InstrCode {
+        name: "movz.s",
+        syntax: Syntax::FpMoveZero,
+        code: 18,
+    },
+    InstrCode {
+        name: "movz.d",
+        syntax: Syntax::FpMoveZero,
+        code: 18,
+    },
+    InstrCode {
+        name: "movn.s",
+        syntax: Syntax::FpMoveZero,
+        code: 19,
+    },
+    InstrCode {
+        name: "movn.d",
+        syntax: Syntax::FpMoveZero,
+        code: 19,
+    },
+    InstrCode {
+        name: "movf",
+        syntax: Syntax::MoveCond,
+        code: 1,
+    },
+    InstrCode {
+        name: "movt",
+        syntax: Syntax::MoveCond,
+        code: 1,
+    },
+    InstrCode {
+        name: "ext",
+        syntax: Syntax::BitField,
+        code: 0,
+    },
+    InstrCode {
+        name: "ins",
+        syntax: Syntax::BitField,
+        code: 4,
+    },
+    InstrCode {
+        name: "wsbh",
+        syntax: Syntax::ByteSwap,
+        code: 2,
+    },
+    InstrCode {
+        name: "seb",
+        syntax: Syntax::ByteSwap,
+        code: 16,
+    },
+    InstrCode {
+        name: "seh",
+        syntax: Syntax::ByteSwap,
+        code: 24,
+    },
+    InstrCode {
+        name: "rotr",
+        syntax: Syntax::Rotate,
+        code: 2,
+    },
+    InstrCode {
+        name: "rotrv",
+        syntax: Syntax::RotateV,
+        code: 6,
+    },
+    InstrCode {
+        name: "clz",
+        syntax: Syntax::CountBits,
+        code: 32,
+    },
+    InstrCode {
+        name: "clo",
+        syntax: Syntax::CountBits,
+        code: 33,
+    },
+    InstrCode {
+        name: "madd",
+        syntax: Syntax::MulAcc,
+        code: 0,
+    },
+    InstrCode {
+        name: "maddu",
+        syntax: Syntax::MulAcc,
+        code: 1,
+    },
+    InstrCode {
+        name: "msub",
+        syntax: Syntax::MulAcc,
+        code: 4,
+    },
+    InstrCode {
+        name: "msubu",
+        syntax: Syntax::MulAcc,
+        code: 5,
+    },
+    InstrCode {
+        name: "movn",
+        syntax: Syntax::ArithLog,
+        code: 11,
+    },
+    InstrCode {
+        name: "movz",
+        syntax: Syntax::ArithLog,
+        code: 10,
+    },
+    InstrCode {
+        name: "tge",
+        syntax: Syntax::TrapReg,
+        code: 48,
+    },
+    InstrCode {
+        name: "tgeu",
+        syntax: Syntax::TrapReg,
+        code: 49,
+    },
+    InstrCode {
+        name: "tlt",
+        syntax: Syntax::TrapReg,
+        code: 50,
+    },
+    InstrCode {
+        name: "tltu",
+        syntax: Syntax::TrapReg,
+        code: 51,
+    },
+    InstrCode {
+        name: "teq",
+        syntax: Syntax::TrapReg,
+        code: 52,
+    },
+    InstrCode {
+        name: "tne",
+        syntax: Syntax::TrapReg,
+        code: 54,
+    },
+    InstrCode {
+        name: "tgei",
+        syntax: Syntax::TrapImm,
+        code: 8,
+    },
+    InstrCode {
+        name: "tgeiu",
+        syntax: Syntax::TrapImm,
+        code: 9,
+    },
+    InstrCode {
+        name: "tlti",
+        syntax: Syntax::TrapImm,
+        code: 10,
+    },
+    InstrCode {
+        name: "tltiu",
+        syntax: Syntax::TrapImm,
+        code: 11,
+    },
+    InstrCode {
+        name: "teqi",
+        syntax: Syntax::TrapImm,
+        code: 12,
+    },
+    InstrCode {
+        name: "tnei",
+        syntax: Syntax::TrapImm,
+        code: 14,
+    },
+    InstrCode {
+        name: "sync",
+        syntax: Syntax::Sync,
+        code: 15,
+    },
+    InstrCode {
+        name: "mul",
+        syntax: Syntax::MulDivR6,
+        code: 24,
+    },
+    InstrCode {
+        name: "muh",
+        syntax: Syntax::MulDivR6,
+        code: 24,
+    },
+    InstrCode {
+        name: "mulu",
+        syntax: Syntax::MulDivR6,
+        code: 25,
+    },
+    InstrCode {
+        name: "muhu",
+        syntax: Syntax::MulDivR6,
+        code: 25,
+    },
+    InstrCode {
+        name: "div",
+        syntax: Syntax::MulDivR6,
+        code: 26,
+    },
+    InstrCode {
+        name: "mod",
+        syntax: Syntax::MulDivR6,
+        code: 26,
+    },
+    InstrCode {
+        name: "divu",
+        syntax: Syntax::MulDivR6,
+        code: 27,
+    },
+    InstrCode {
+        name: "modu",
+        syntax: Syntax::MulDivR6,
+        code: 27,
+    },
+    InstrCode {
+        name: "clz",
+        syntax: Syntax::CountBitsR6,
+        code: 16,
+    },
+    InstrCode {
+        name: "clo",
+        syntax: Syntax::CountBitsR6,
+        code: 17,
+    },
+    InstrCode {
+        name: "ll",
+        syntax: Syntax::AtomicR6,
+        code: 54,
+    },
+    InstrCode {
+        name: "sc",
+        syntax: Syntax::AtomicR6,
+        code: 38,
+    },
+    InstrCode {
+        name: "jr",
+        syntax: Syntax::JumpR,
+        code: 9,
+    },
+    InstrCode {
+        name: "bc",
+        syntax: Syntax::CompactJump,
+        code: 50,
+    },
+    InstrCode {
+        name: "balc",
+        syntax: Syntax::CompactJump,
+        code: 58,
+    },
+    InstrCode {
+        name: "beqzc",
+        syntax: Syntax::CompactBranchZ,
         code: 54,
     },
+    InstrCode {
+        name: "bnezc",
+        syntax: Syntax::CompactBranchZ,
+        code: 62,
+    },
+    InstrCode {
+        name: "cmp.af.s",
+        syntax: Syntax::FpCompareR6,
+        code: 0,
+    },
+    InstrCode {
+        name: "cmp.af.d",
+        syntax: Syntax::FpCompareR6,
+        code: 0,
+    },
+    InstrCode {
+        name: "cmp.un.s",
+        syntax: Syntax::FpCompareR6,
+        code: 1,
+    },
+    InstrCode {
+        name: "cmp.un.d",
+        syntax: Syntax::FpCompareR6,
+        code: 1,
+    },
+    InstrCode {
+        name: "cmp.eq.s",
+        syntax: Syntax::FpCompareR6,
+        code: 2,
+    },
+    InstrCode {
+        name: "cmp.eq.d",
+        syntax: Syntax::FpCompareR6,
+        code: 2,
+    },
+    InstrCode {
+        name: "cmp.ueq.s",
+        syntax: Syntax::FpCompareR6,
+        code: 3,
+    },
+    InstrCode {
+        name: "cmp.ueq.d",
+        syntax: Syntax::FpCompareR6,
+        code: 3,
+    },
+    InstrCode {
+        name: "cmp.lt.s",
+        syntax: Syntax::FpCompareR6,
+        code: 4,
+    },
+    InstrCode {
+        name: "cmp.lt.d",
+        syntax: Syntax::FpCompareR6,
+        code: 4,
+    },
+    InstrCode {
+        name: "cmp.ult.s",
+        syntax: Syntax::FpCompareR6,
+        code: 5,
+    },
+    InstrCode {
+        name: "cmp.ult.d",
+        syntax: Syntax::FpCompareR6,
+        code: 5,
+    },
+    InstrCode {
+        name: "cmp.le.s",
+        syntax: Syntax::FpCompareR6,
+        code: 6,
+    },
+    InstrCode {
+        name: "cmp.le.d",
+        syntax: Syntax::FpCompareR6,
+        code: 6,
+    },
+    InstrCode {
+        name: "cmp.ule.s",
+        syntax: Syntax::FpCompareR6,
+        code: 7,
+    },
+    InstrCode {
+        name: "cmp.ule.d",
+        syntax: Syntax::FpCompareR6,
+        code: 7,
+    },
+    InstrCode {
+        name: "bc1eqz",
+        syntax: Syntax::FpBranchR6,
+        code: 9,
+    },
+    InstrCode {
+        name: "bc1nez",
+        syntax: Syntax::FpBranchR6,
+        code: 13,
+    },
 ];
 
 pub fn get_code<S: Into<String>>(line: S) -> &'static InstrCode<'static> {
     let line_str = line.into();
-    let parts: Vec<String> = line_str.split(" ").map(String::from).collect();
+    let name = line_str.split_whitespace().next().unwrap_or("");
     for code in &CODES {
-        if parts[0] == code.name {
+        if name == code.name {
             return code;
         }
     }
-    return &CODES[0];
+    &CODES[0]
 }
 
-pub fn as_register<S: Into<String>>(arg: S) -> Result<i8, ()> {
-    let mut i: i8 = 0;
+impl InstrCode<'_> {
+    /// The fmt field of a coprocessor 1 instruction, from the last suffix of its name: 16 for
+    /// `.s`, 17 for `.d` and 20 for `.w`. 0 for other instructions.
+    pub fn fmt(&self) -> u32 {
+        match self.name.rsplit_once('.') {
+            Some((_, "s")) => 16,
+            Some((_, "d")) => 17,
+            Some((_, "w")) => 20,
+            _ => 0,
+        }
+    }
 
-    let name = &(arg.into())[1..];
-    while i < 32 {
-        if REGS[i as usize] == name {
-            return Ok(i);
+    /// The sa field of the Release 6 multiplies and divides: 3 for those giving the high word
+    /// or the remainder, 2 for the others.
+    pub fn r6_sa(&self) -> u32 {
+        if self.name.starts_with("muh") || self.name.starts_with("mod") {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// The first revision with this instruction, and the first one without it if it was
+    /// removed or re-encoded.
+    pub fn revisions(&self) -> (Isa, Option<Isa>) {
+        // Release 6 writes jr as jalr linking to $zero
+        if self.name == "jr" {
+            return match self.code {
+                9 => (Isa::Mips32r6, None),
+                _ => (Isa::Mips1, Some(Isa::Mips32r6)),
+            };
+        }
+        let since = match self.syntax {
+            Syntax::MulDivR6
+            | Syntax::CountBitsR6
+            | Syntax::AtomicR6
+            | Syntax::CompactJump
+            | Syntax::CompactBranchZ
+            | Syntax::FpCompareR6
+            | Syntax::FpBranchR6 => return (Isa::Mips32r6, None),
+            Syntax::BitField
+            | Syntax::ByteSwap
+            | Syntax::Rotate
+            | Syntax::RotateV
+            | Syntax::S2ArithLog
+            | Syntax::CountBits
+            | Syntax::MulAcc
+            | Syntax::MoveCond
+            | Syntax::FpMoveCond
+            | Syntax::FpMoveZero => Isa::Mips32r2,
+            // MIPS I returned from exceptions with rfe instead
+            Syntax::ExcReturn => Isa::Mips32r2,
+            Syntax::TrapReg | Syntax::TrapImm | Syntax::Sync | Syntax::AtomicLoadStore => {
+                Isa::Mips2
+            }
+            _ => match self.name.split('.').next() {
+                Some("movn" | "movz") => Isa::Mips32r2,
+                Some("ldc1" | "sdc1" | "sqrt" | "round" | "trunc" | "ceil" | "floor") => Isa::Mips2,
+                _ => Isa::Mips1,
+            },
+        };
+        let removed = match self.syntax {
+            Syntax::DivMult
+            | Syntax::MoveFrom
+            | Syntax::MoveTo
+            | Syntax::S2ArithLog
+            | Syntax::CountBits
+            | Syntax::MulAcc
+            | Syntax::MoveCond
+            | Syntax::AtomicLoadStore
+            | Syntax::TrapImm
+            | Syntax::FpCompare
+            | Syntax::FpBranch
+            | Syntax::FpMoveCond
+            | Syntax::FpMoveZero => true,
+            _ => matches!(
+                self.name,
+                "addi" | "movn" | "movz" | "lwl" | "lwr" | "swl" | "swr"
+            ),
+        };
+        (since, removed.then_some(Isa::Mips32r6))
+    }
+
+    /// Whether the instruction after this one runs before a taken branch or jump lands, when
+    /// branches are delayed. Compact branches have no delay slot.
+    pub fn has_delay_slot(&self) -> bool {
+        matches!(
+            self.syntax,
+            Syntax::Branch
+                | Syntax::BranchZ
+                | Syntax::RegImmBranch
+                | Syntax::Jump
+                | Syntax::JumpR
+                | Syntax::FpBranch
+                | Syntax::FpBranchR6
+        )
+    }
+
+    pub fn valid_in(&self, isa: Isa) -> bool {
+        let (since, until) = self.revisions();
+        since <= isa && until.is_none_or(|until| isa < until)
+    }
+
+    /// The example operands for this instruction. The coprocessor 1 loads and stores share the
+    /// syntax of `lw`, but move to and from an FPU register.
+    pub fn operands(&self) -> &'static str {
+        match self.syntax {
+            Syntax::LoadStore if self.name.ends_with("c1") => "$f1,-100($t2)",
+            _ => self.syntax.operands(),
+        }
+    }
+
+    /// The instruction Release 6 has in place of this one under another name: `cmp.cond.fmt`
+    /// for the compares into condition flags, and `bc1eqz`/`bc1nez` for the branches on them.
+    fn r6_successor(&self) -> Option<String> {
+        match self.syntax {
+            Syntax::FpCompare => Some(format!("cmp{}", &self.name[1..])),
+            Syntax::FpBranch if self.tf() == 1 => Some("bc1nez".into()),
+            Syntax::FpBranch => Some("bc1eqz".into()),
+            _ => None,
+        }
+    }
+
+    /// Why `isa` does not have this instruction.
+    pub fn isa_error(&self, isa: Isa) -> String {
+        let (since, until) = self.revisions();
+        let name = self.r6_successor().unwrap_or(self.name.to_string());
+        let other = CODES
+            .iter()
+            .find(|c| (c.name == self.name || c.name == name) && c.valid_in(isa));
+        match (until, other) {
+            _ if isa < since => format!(
+                "{0} needs {1} or later, but the ISA is {2}.",
+                self.name, since, isa
+            ),
+            (Some(until), Some(other)) => format!(
+                "{0} {1} was removed in {2}, which has {3} {4} instead.",
+                self.name,
+                self.operands(),
+                until,
+                other.name,
+                other.operands()
+            ),
+            (Some(until), None) => format!("{0} was removed in {1}.", self.name, until),
+            (None, _) => format!("{0} is not in {1}.", self.name, isa),
+        }
+    }
+
+    /// The true/false bit of instructions that test an FPU condition flag.
+    pub fn tf(&self) -> u32 {
+        (self.name.starts_with("movt") || self.name == "bc1t") as u32
+    }
+
+    /// Whether `word` is an encoding of this instruction. Where the code sits depends on the
+    /// format: the funct field for SPECIAL, SPECIAL2 and SPECIAL3 instructions, rt for REGIMM, sa
+    /// for the byte swaps, rs for COP0 and COP1 moves and COP1 branches, fmt and funct for other
+    /// COP1 instructions, and the opcode otherwise.
+    pub fn matches(&self, word: u32) -> bool {
+        let op = word >> 26;
+        let rs = (word >> 21) & 31;
+        let rt = (word >> 16) & 31;
+        let rd = (word >> 11) & 31;
+        let sa = (word >> 6) & 31;
+        let funct = word & 63;
+        let code = self.code as u32;
+        if self.code < 0 {
+            return false;
+        }
+        match self.syntax {
+            // jalr links through $ra, and jr has $zero there in Release 6
+            Syntax::JumpR => op == 0 && funct == code && rd == 31 * (self.name == "jalr") as u32,
+            // nop is sll $zero, $zero, 0
+            Syntax::Syscall if code == 0 => word == 0,
+            Syntax::ArithLog
+            | Syntax::DivMult
+            | Syntax::MoveFrom
+            | Syntax::MoveTo
+            | Syntax::Syscall
+            | Syntax::Break
+            | Syntax::TrapReg
+            | Syntax::Sync => op == 0 && funct == code,
+            // The rotates are the shifts with a 1 in the field the shifts leave at 0
+            Syntax::Shift => op == 0 && funct == code && rs == 0,
+            Syntax::Rotate => op == 0 && funct == code && rs == 1,
+            Syntax::ShiftV => op == 0 && funct == code && sa == 0,
+            Syntax::RotateV => op == 0 && funct == code && sa == 1,
+            Syntax::S2ArithLog | Syntax::CountBits | Syntax::MulAcc => op == 28 && funct == code,
+            Syntax::BitField => op == 31 && funct == code,
+            Syntax::ByteSwap => op == 31 && funct == 32 && sa == code,
+            Syntax::RegImmBranch | Syntax::TrapImm => op == 1 && rt == code,
+            Syntax::MulDivR6 => op == 0 && funct == code && sa == self.r6_sa(),
+            Syntax::CountBitsR6 => op == 0 && funct == code && sa == 1,
+            Syntax::AtomicR6 => op == 31 && funct == code,
+            // With rs at 0 these are jic and jialc
+            Syntax::CompactBranchZ => op == code && rs != 0,
+            // Release 6 compares use the W and L formats for single and double
+            Syntax::FpCompareR6 => op == 17 && rs == self.fmt() + 4 && funct == code,
+            Syntax::FpBranchR6 => op == 17 && rs == code,
+            Syntax::CoProc1Move => op == 17 && rs == code,
+            Syntax::CoProc0Move => op == 16 && rs == code,
+            Syntax::ExcReturn => op == 16 && rs == 16 && funct == code,
+            Syntax::FpArith | Syntax::FpUnary | Syntax::FpCompare | Syntax::FpMoveZero => {
+                op == 17 && rs == self.fmt() && funct == code
+            }
+            Syntax::FpMoveCond => {
+                op == 17 && rs == self.fmt() && funct == code && rt & 1 == self.tf()
+            }
+            Syntax::FpBranch => op == 17 && rs == code && rt & 1 == self.tf(),
+            Syntax::MoveCond => op == 0 && funct == code && rt & 1 == self.tf(),
+            Syntax::ArithLogI
+            | Syntax::LogicI
+            | Syntax::LoadI
+            | Syntax::Branch
+            | Syntax::BranchZ
+            | Syntax::LoadStore
+            | Syntax::Jump
+            | Syntax::Trap
+            | Syntax::AtomicLoadStore
+            | Syntax::CompactJump => op == code,
         }
-        i += 1;
     }
-    Err(())
 }
 
-pub fn get_ops<S : Into<String>>(
-    opfile: Option<S>,
-) -> HashMap<String, Box<dyn Fn(Args<Arg>) -> Vec<&'static InstrCode<'static>>>> {
-    let fname = match opfile {
-        None => "res/PseudoOps.txt".into(),
-        Some(s) => s.into(),
+/// Finds the instruction an encoded word belongs to in `isa`.
+pub fn decode(word: u32, isa: Isa) -> Option<&'static InstrCode<'static>> {
+    CODES.iter().find(|c| c.valid_in(isa) && c.matches(word))
+}
+
+/// Finds the instruction the statement `toks` calls for on `isa`. Where revisions encode an
+/// instruction differently, the one `isa` has comes first, and after that the first one whose
+/// operands fit. Instructions `isa` lacks are still found, so the caller can say why they are
+/// not allowed.
+pub fn find_code(toks: &[String], isa: Isa) -> &'static InstrCode<'static> {
+    let mut codes: Vec<&InstrCode> = CODES.iter().filter(|c| c.name == toks[0]).collect();
+    codes.sort_by_key(|c| !c.valid_in(isa));
+    codes
+        .iter()
+        .find(|c| fits(&get_pattern(c.operands()), &toks[1..]))
+        .or(codes.first())
+        .copied()
+        .unwrap_or(&CODES[0])
+}
+
+/// The register files an operand can name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegClass {
+    /// General purpose registers: `$t0`, `$8` or `$r8`.
+    Gpr,
+    /// FPU registers, `$f0` to `$f31`.
+    Fpr,
+    /// Coprocessor 0 registers, written by number as in MARS, such as `$12` for Status.
+    Cp0,
+}
+
+impl RegClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegClass::Gpr => "general purpose",
+            RegClass::Fpr => "floating point",
+            RegClass::Cp0 => "coprocessor 0",
+        }
+    }
+}
+
+/// The number of the register `tok` names, and the classes it can be of. A bare number such as
+/// `$8` is a general purpose register or a coprocessor 0 one, depending on where it is used.
+pub fn register(tok: &str) -> Option<(i8, &'static [RegClass])> {
+    let name = tok.strip_prefix('$')?;
+    let number = |digits: &str| match digits.parse::<u8>() {
+        Ok(n) if n < 32 && digits.bytes().all(|b| b.is_ascii_digit()) => Some(n as i8),
+        _ => None,
     };
+    if let Some(n) = number(name) {
+        return Some((n, &[RegClass::Gpr, RegClass::Cp0]));
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(number) {
+        return Some((n, &[RegClass::Fpr]));
+    }
+    if let Some(n) = name.strip_prefix('r').and_then(number) {
+        return Some((n, &[RegClass::Gpr]));
+    }
+    // $s8 is another name for $fp, which some code uses as a ninth saved register
+    let n = match name {
+        "s8" => 30,
+        _ => REGS.iter().position(|r| *r == name)?,
+    };
+    Some((n as i8, &[RegClass::Gpr]))
+}
 
-    let instr_table = HashMap::new();
+/// The number of the register `arg` names, of any class.
+pub fn as_register<S: Into<String>>(arg: S) -> Result<i8, ()> {
+    register(&arg.into()).map(|r| r.0).ok_or(())
+}
 
-    let total = std::fs::read_to_string(fname);
+/// A pseudo-instruction from PseudoOps.txt: the example statement it matches and the basic
+/// instructions it expands to.
+pub struct PseudoOp {
+    pub(crate) name: String,
+    pattern: Vec<OpKind>,
+    templates: Vec<String>,
+    // The templates after COMPACT, for when every address fits in 16 bits
+    compact: Vec<String>,
+}
 
-    match total {
-        Ok(tot) => {
-
-            for line in tot.lines() {
-                let line_nc_dirty: &str = {
-                    // Removes comments, which start with #
-                    let pos_opt: Option<usize> = line.find('#');
-                    match pos_opt {
-                        Some(n) => &line[0..n],
-                        None => line,
-                    }
-                };
-                let line_nc = rem_spaces(line_nc_dirty);
+impl PseudoOp {
+    pub fn fits(&self, toks: &[String]) -> bool {
+        fits(&self.pattern, &toks[1..])
+    }
 
-                if line_nc.len() == 0 {
-                    continue;
-                }
+    pub fn pattern(&self) -> &[OpKind] {
+        &self.pattern
+    }
 
-                //println!("{}", line);
+    /// How many operands of `toks` depend on labels yet stand for a 32-bit immediate.
+    pub fn label_immediates(&self, toks: &[String]) -> usize {
+        self.pattern
+            .iter()
+            .zip(&toks[1..])
+            .filter(|(k, t)| **k == OpKind::Imm32 && is_expr(t))
+            .count()
+    }
 
-                let instrs : Vec<_> = line_nc.split("\t").filter(|x| x!=&"").collect();
+    /// The templates that apply: the compact ones if there are any and the memory map is compact,
+    /// and DBNOP, a nop for a delay slot, only with delayed branches.
+    fn templates(&self, options: &Options) -> impl Iterator<Item = &String> {
+        let delayed = options.delayed_branches;
+        match self.compact.is_empty() || !options.memory.is_compact() {
+            true => &self.templates,
+            false => &self.compact,
+        }
+        .iter()
+        .filter(move |t| delayed || *t != "DBNOP")
+    }
 
-                /*
-                for i in instrs {
-                    print!("{{{}}}, ", i);
-                }
-                println!();
-                 */
+    /// Number of basic instructions this expands to.
+    pub fn len(&self, options: &Options) -> u32 {
+        self.templates(options).count() as u32
+    }
 
-                let pseudo = instrs[0];
-                let repls : Vec<String> = instrs[1..].iter().map(|x| {let y : &str = x.to_owned(); y.into()}).collect();
+    /// Whether the last instruction this expands to has a delay slot after it.
+    pub fn ends_in_branch(&self) -> bool {
+        self.templates
+            .last()
+            .is_some_and(|t| get_code(t.as_str()).has_delay_slot())
+    }
 
-                for i in &repls {
-                    let word = i.split(&String::from(" ")).collect::<Vec<&str>>()[0];//.map(|x| x.to_owned()).collect();
-                    if get_code(word).code == -1 && word != "DBNOP" && word != "COMPACT" {
-                        println!("{}", &word);
+    /// Fills in the templates for the statement `toks`, which starts at `adr`. `src` is the source
+    /// line, used to point diagnostics at the operand at fault.
+    pub fn expand(
+        &self,
+        toks: &[String],
+        lbl_adr: &HashMap<String, u32>,
+        line: u32,
+        adr: u32,
+        src: &str,
+        options: &Options,
+    ) -> Result<Vec<(String, Option<LabelPart>)>, Diagnostic> {
+        let delayed = options.delayed_branches;
+        let mut out = Vec::new();
+        for (i, template) in self.templates(options).enumerate() {
+            if template == "DBNOP" {
+                out.push(("nop".to_string(), None));
+                continue;
+            }
+            let mut instr = String::new();
+            let mut part = None;
+            let mut word = String::new();
+            for c in template.chars().chain(std::iter::once(' ')) {
+                if c.is_ascii_alphanumeric() {
+                    word.push(c);
+                    continue;
+                }
+                if !word.is_empty() {
+                    match substitute(&word, toks, lbl_adr, adr, adr + 4 * i as u32, delayed) {
+                        Ok(Some((s, p))) => {
+                            instr.push_str(&s);
+                            part = part.or(p);
+                        }
+                        Ok(None) => instr.push_str(&word),
+                        Err((tok, e)) => {
+                            return Err(Diagnostic::error(
+                                line,
+                                format!("{0} in {1}.", e, self.name),
+                            )
+                            .at(src, &tok));
+                        }
                     }
+                    word.clear();
                 }
+                instr.push(c);
             }
+            out.push((rem_spaces(instr), part));
         }
-        _ => {
-            println!("PseudoOps.txt not found.");
+        Ok(out)
+    }
+}
+
+fn hi(v: u32) -> String {
+    (v >> 16).to_string()
+}
+
+// High half for use with a sign-extended low half, so add 1 if bit 15 is set.
+fn hi_adj(v: u32) -> String {
+    (v.wrapping_add(0x8000) >> 16).to_string()
+}
+
+fn lo(v: u32, unsigned: bool) -> String {
+    if unsigned {
+        (v & 0xffff).to_string()
+    } else {
+        (v as i16).to_string()
+    }
+}
+
+/// Half of a label's address put into an expanded instruction: the kind of relocation it needs,
+/// the label, and the full value it came from.
+pub type LabelPart = (RelocKind, String, u32);
+
+/// A substituted word, with the label part it holds if any.
+type Substitution = (String, Option<LabelPart>);
+
+/// Substitutes one word of a template for the statement at `dot`, in the instruction at `adr`.
+/// Returns `None` for words that are copied as is, and on failure the token at fault along with
+/// the reason.
+fn substitute(
+    word: &str,
+    toks: &[String],
+    lbl_adr: &HashMap<String, u32>,
+    dot: u32,
+    adr: u32,
+    delayed: bool,
+) -> Result<Option<Substitution>, (String, String)> {
+    let tok = |n: usize| -> Result<&String, (String, String)> {
+        toks.get(n)
+            .ok_or((String::new(), format!("Missing token {}", n)))
+    };
+    let eval = |t: &String| -> Result<u32, (String, String)> {
+        Expr::parse(t)
+            .and_then(|e| e.value(lbl_adr, dot))
+            .map_err(|e| (t.clone(), e))
+    };
+    let val = |n: usize| -> Result<u32, (String, String)> {
+        let t = tok(n)?;
+        match parse_num(t) {
+            Ok(v) => Ok(v as u32),
+            Err(_) if Expr::parse(t).is_ok() => eval(t),
+            Err(_) => Err((t.clone(), format!("Number \"{}\" not valid", t))),
+        }
+    };
+    let lbl = |n: usize| eval(tok(n)?);
+    let reg = |n: usize| -> Result<i8, (String, String)> {
+        let t = tok(n)?;
+        as_register(t).map_err(|_| (t.clone(), format!("Register \"{}\" not found", t)))
+    };
+
+    // Relocations are against the label the address is relative to, when there is one
+    let part = |kind: RelocKind, t: &str, value: u32| {
+        let e = Expr::parse(t).ok()?;
+        Some((kind, e.base_symbol()?.to_string(), value))
+    };
+
+    if word == "LAB" {
+        let t = &toks[toks.len() - 1];
+        if is_label_name(t) {
+            return Ok(Some((t.clone(), None)));
         }
+        return Ok(Some((format!("0x{:08x}", eval(t)?), None)));
+    }
+    if word == "S32" {
+        return Ok(Some(((32 - val(toks.len() - 1)? as i32).to_string(), None)));
+    }
+    if let Some(offs) = word.strip_prefix("BROFF") {
+        // The second offset also skips the DBNOP in the delay slot
+        let off = match offs.as_bytes() {
+            [n, m] if n.is_ascii_digit() && m.is_ascii_digit() => {
+                (if delayed { m } else { n } - b'0') as u32
+            }
+            _ => return Ok(None),
+        };
+        // Branch targets are given to get_enc as addresses rather than offsets.
+        return Ok(Some((format!("0x{:08x}", adr + 4 + 4 * off), None)));
+    }
+    if word == "LHL" {
+        let v = lbl(2)?;
+        return Ok(Some((hi(v), part(RelocKind::Hi16, &toks[2], v))));
+    }
+    if word == "LHPN" {
+        let v = lbl(2)?.wrapping_add(val(4)?);
+        return Ok(Some((hi(v), part(RelocKind::Hi16, &toks[2], v))));
+    }
+
+    let (kind, rest) = match [
+        "VHL", "VH", "VL", "LHPA", "LLP", "LH", "LL", "RG", "NR", "OP",
+    ]
+    .iter()
+    .find(|k| word.starts_with(**k))
+    {
+        Some(k) => (*k, &word[k.len()..]),
+        None => return Ok(None),
     };
+    let mut rest = rest.as_bytes();
+    let mut n = 2;
+    if kind != "LHPA" && kind != "LLP" {
+        match rest {
+            [d, ..] if d.is_ascii_digit() => {
+                n = (d - b'0') as usize;
+                rest = &rest[1..];
+            }
+            _ => return Ok(None),
+        }
+    }
+    let mut plus = 0;
+    if let [b'P', d, ..] = rest {
+        if !d.is_ascii_digit() {
+            return Ok(None);
+        }
+        plus = (d - b'0') as u32;
+        rest = &rest[2..];
+    }
+    let unsigned = rest == b"U";
+    if !rest.is_empty() && !unsigned {
+        return Ok(None);
+    }
+
+    Ok(Some(match kind {
+        "RG" => (tok(n)?.clone(), None),
+        "NR" => (format!("${}", reg(n)? + 1), None),
+        "OP" => (tok(n)?.clone(), None),
+        "VHL" => (hi(val(n)?.wrapping_add(plus)), None),
+        "VH" => (hi_adj(val(n)?.wrapping_add(plus)), None),
+        "VL" => (lo(val(n)?.wrapping_add(plus), unsigned), None),
+        "LH" | "LL" | "LHPA" | "LLP" => {
+            let v = if kind == "LH" || kind == "LL" {
+                lbl(n)?.wrapping_add(plus)
+            } else {
+                lbl(2)?.wrapping_add(val(4)?).wrapping_add(plus)
+            };
+            let label = tok(if kind.len() == 2 { n } else { 2 })?;
+            if kind == "LH" || kind == "LHPA" {
+                (hi_adj(v), part(RelocKind::Hi16, label, v))
+            } else {
+                (lo(v, unsigned), part(RelocKind::Lo16, label, v))
+            }
+        }
+        _ => unreachable!(),
+    }))
+}
+
+pub fn get_ops<S: Into<String>>(
+    opfile: Option<S>,
+) -> (HashMap<String, Vec<PseudoOp>>, Vec<Diagnostic>) {
+    let (fname, total): (String, _) = match opfile {
+        None => (
+            "res/PseudoOps.txt".into(),
+            Ok(include_str!("../res/PseudoOps.txt").to_string()),
+        ),
+        Some(s) => {
+            let fname = s.into();
+            let total = std::fs::read_to_string(&fname);
+            (fname, total)
+        }
+    };
+
+    match total {
+        Ok(tot) => read_ops(&tot, &fname),
+        Err(e) => {
+            let mut diag = Diagnostic::error(0, format!("Could not read pseudo-ops: {}", e));
+            diag.file = fname;
+            (HashMap::new(), vec![diag])
+        }
+    }
+}
+
+/// What a conditional branch that cannot reach its target becomes: the inverse branch over a
+/// jump to the target, or over `jr $at` when the target is outside the jump's 256MB region. With
+/// delayed branches, the delay slot of the branch becomes the delay slot of the jump, and is
+/// also where the inverse branch lands, so it runs once either way.
+const RELAXATIONS: &str = "\
+beq $t1,$t2,label\tbne RG1, RG2, BROFF12\tDBNOP\tj LAB
+beq $t1,$t2,label\tbne RG1, RG2, BROFF34\tDBNOP\tlui $1, LH3\taddiu $1, $1, LL3\tjr $1
+bne $t1,$t2,label\tbeq RG1, RG2, BROFF12\tDBNOP\tj LAB
+bne $t1,$t2,label\tbeq RG1, RG2, BROFF34\tDBNOP\tlui $1, LH3\taddiu $1, $1, LL3\tjr $1
+blez $t1,label\tbgtz RG1, BROFF12\tDBNOP\tj LAB
+blez $t1,label\tbgtz RG1, BROFF34\tDBNOP\tlui $1, LH2\taddiu $1, $1, LL2\tjr $1
+bgtz $t1,label\tblez RG1, BROFF12\tDBNOP\tj LAB
+bgtz $t1,label\tblez RG1, BROFF34\tDBNOP\tlui $1, LH2\taddiu $1, $1, LL2\tjr $1
+bgez $t1,label\tbltz RG1, BROFF12\tDBNOP\tj LAB
+bgez $t1,label\tbltz RG1, BROFF34\tDBNOP\tlui $1, LH2\taddiu $1, $1, LL2\tjr $1
+bltz $t1,label\tbgez RG1, BROFF12\tDBNOP\tj LAB
+bltz $t1,label\tbgez RG1, BROFF34\tDBNOP\tlui $1, LH2\taddiu $1, $1, LL2\tjr $1
+";
+
+/// The relaxed forms of each conditional branch that has them, by name: first over a `j`, then
+/// over a `jr $at`.
+pub fn get_relaxations() -> HashMap<String, Vec<PseudoOp>> {
+    read_ops(RELAXATIONS, "relaxations").0
+}
+
+/// Reads the pseudo-ops in `text`, which comes from the file `fname`.
+fn read_ops(text: &str, fname: &str) -> (HashMap<String, Vec<PseudoOp>>, Vec<Diagnostic>) {
+    let mut instr_table: HashMap<String, Vec<PseudoOp>> = HashMap::new();
+    let mut diags = Vec::new();
+
+    for (ln, line) in text.lines().enumerate() {
+        // A pseudo-op must start in the first column
+        if line.is_empty() || line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let line_nc: &str = {
+            // Removes comments and descriptions, which start with #
+            let pos_opt: Option<usize> = line.find('#');
+            match pos_opt {
+                Some(n) => &line[0..n],
+                None => line,
+            }
+        };
+
+        let mut instrs = line_nc
+            .split('\t')
+            .map(rem_spaces)
+            .filter(|x| !x.is_empty());
+        let example = match instrs.next() {
+            Some(e) => e,
+            None => continue,
+        };
+        let (name, operands) = match example.find(' ') {
+            Some(n) => (&example[..n], &example[n..]),
+            None => (&example[..], ""),
+        };
+
+        // The templates after COMPACT are for 16-bit addresses
+        let templates: Vec<String> = instrs.by_ref().take_while(|x| x != "COMPACT").collect();
+        let compact: Vec<String> = instrs.collect();
+
+        for i in templates.iter().chain(&compact).filter(|x| *x != "DBNOP") {
+            let word = i.split(' ').next().unwrap_or("");
+            if get_code(word).code == -1 {
+                let mut diag = Diagnostic::warning(
+                    ln as u32 + 1,
+                    format!("Unknown instruction {} in pseudo-op {}.", word, example),
+                )
+                .at(line, word);
+                diag.file = fname.to_string();
+                diags.push(diag);
+            }
+        }
+
+        instr_table.entry(name.into()).or_default().push(PseudoOp {
+            name: name.into(),
+            pattern: get_pattern(operands),
+            templates,
+            compact,
+        });
+    }
 
-    instr_table
+    (instr_table, diags)
 }
@@ -0,0 +1,99 @@
+//! Load and store addressing forms, against the words MARS assembles them to.
+
+mod common;
+
+use common::words;
+
+#[test]
+fn base_and_offset() {
+    let source = "
+        lw $t0, 4($sp)
+        sw $ra, -4($sp)
+        lb $t0, -1($t1)
+        lhu $t0, 2($t1)
+        sb $zero, 0($a0)
+        lw $t0, ($t1)
+        sw $t0, ($sp)
+        lw $t0, 100
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x8fa8_0004,
+            0xafbf_fffc,
+            0x8128_ffff,
+            0x9528_0002,
+            0xa080_0000,
+            0x8d28_0000,
+            0xafa8_0000,
+            0x8c08_0064
+        ]
+    );
+}
+
+#[test]
+fn addresses_through_at() {
+    let source = "
+        lw $t0, 0x8000
+        lw $t0, 0x10010008
+        lw $t0, 0x10018000
+        lw $t0, 0x8000($t1)
+        sw $t0, 0x10010008($t1)
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x3401_8000,
+            0x8c28_0000,
+            0x3c01_1001,
+            0x8c28_0008,
+            0x3c01_1002,
+            0x8c28_8000,
+            0x3401_8000,
+            0x0029_0821,
+            0x8c28_0000,
+            0x3c01_1001,
+            0x0029_0821,
+            0xac28_0008
+        ]
+    );
+}
+
+#[test]
+fn labels() {
+    let source = "
+        .data
+        x: .word 0
+        .space 0x7ffc
+        y: .word 0
+        .text
+        lw $t0, x
+        lw $t0, y
+        sh $t0, x($t1)
+        lw $t0, x+4
+        sw $t0, x+4($t1)
+        lwc1 $f2, y
+        sdc1 $f4, x
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x3c01_1001,
+            0x8c28_0000,
+            0x3c01_1002,
+            0x8c28_8000,
+            0x3c01_1001,
+            0x0029_0821,
+            0xa428_0000,
+            0x3c01_1001,
+            0x8c28_0004,
+            0x3c01_1001,
+            0x0029_0821,
+            0xac28_0004,
+            0x3c01_1002,
+            0xc422_8000,
+            0x3c01_1001,
+            0xf424_0000
+        ]
+    );
+}
@@ -0,0 +1,88 @@
+//! The library interface: what `assemble` returns for a program and for a mistake.
+
+use assembler_rust::{assemble, Options, Severity, SourceLine, Statement};
+
+fn options() -> Options {
+    Options {
+        file: "prog.asm".into(),
+        ..Options::default()
+    }
+}
+
+#[test]
+fn program_fields() {
+    let source = "\
+.data
+msg: .word 7
+.text
+main: la $t0, msg
+  .foo
+jr $ra
+";
+    let program = assemble(source, &options()).unwrap();
+    assert_eq!(program.files, ["prog.asm"]);
+    assert_eq!(
+        (program.text_start, program.data_start),
+        (0x0040_0000, 0x1001_0000)
+    );
+    assert_eq!(program.data, [0, 0, 0, 7]);
+    assert_eq!(program.text.len(), 12);
+
+    let symbols: Vec<_> = program
+        .symbols
+        .iter()
+        .map(|s| (s.name.as_str(), s.address, s.line, s.global))
+        .collect();
+    assert_eq!(
+        symbols,
+        [
+            ("main", 0x0040_0000, 4, false),
+            ("msg", 0x1001_0000, 2, false)
+        ]
+    );
+
+    assert_eq!(
+        program.source_map[2],
+        SourceLine {
+            address: 0x0040_0008,
+            file: 0,
+            line: 6
+        }
+    );
+    let la = program.statements.iter().find(|s| s.line == 4).unwrap();
+    assert_eq!(
+        *la,
+        Statement {
+            file: 0,
+            line: 4,
+            address: 0x0040_0000,
+            bytes: vec![0x3c, 0x01, 0x10, 0x01, 0x34, 0x28, 0x00, 0x00],
+            unit: 4,
+            expansion: vec!["lui $1, 4097".into(), "ori $t0, $1, 0".into()],
+        }
+    );
+
+    // The unknown directive is only a warning, so the program is still assembled
+    assert_eq!(program.warnings.len(), 1);
+    let warning = &program.warnings[0];
+    assert_eq!(warning.severity, Severity::Warning);
+    assert_eq!((warning.file.as_str(), warning.line), ("prog.asm", 5));
+    assert_eq!(
+        &source.lines().nth(4).unwrap()[warning.cols.clone()],
+        ".foo"
+    );
+}
+
+#[test]
+fn diagnostic_fields() {
+    let source = "main: addi $t0, $t9x, 1\nj main\n";
+    let diags = assemble(source, &options()).unwrap_err();
+    assert_eq!(diags.len(), 1);
+    let diag = &diags[0];
+    assert_eq!(diag.severity, Severity::Error);
+    assert!(diag.is_error());
+    assert_eq!((diag.file.as_str(), diag.line), ("prog.asm", 1));
+    assert!(diag.message.contains("$t9x"), "{}", diag.message);
+    assert_eq!(&source[diag.cols.clone()], "$t9x");
+    assert!(diag.notes.is_empty());
+}
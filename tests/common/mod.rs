@@ -0,0 +1,50 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate compiles this module on its own and uses only some of it
+#![allow(dead_code)]
+
+use assembler_rust::{assemble, Diagnostic, Options, Program};
+
+/// Assembles `source` with `options`, failing the test with the diagnostics if it does not.
+pub fn program_with(source: &str, options: &Options) -> Program {
+    assemble(source, options).unwrap_or_else(|diags| {
+        let msgs: Vec<String> = diags.iter().map(|d| d.render("")).collect();
+        panic!("{}", msgs.join("\n"))
+    })
+}
+
+/// Assembles `source` with the default options.
+pub fn program(source: &str) -> Program {
+    program_with(source, &Options::default())
+}
+
+/// A segment as big-endian words.
+pub fn to_words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks(4)
+        .map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]]))
+        .collect()
+}
+
+/// Assembles `source` and returns its text segment as words.
+pub fn words(source: &str) -> Vec<u32> {
+    to_words(&program(source).text)
+}
+
+/// The diagnostics of `source`, which must not assemble with `options`.
+pub fn diagnostics_with(source: &str, options: &Options) -> Vec<Diagnostic> {
+    match assemble(source, options) {
+        Ok(_) => panic!("{} should not assemble", source),
+        Err(diags) => diags,
+    }
+}
+
+/// The diagnostics of `source`, which must not assemble.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    diagnostics_with(source, &Options::default())
+}
+
+/// The first error `source` gives.
+pub fn error(source: &str) -> String {
+    diagnostics(source)[0].message.clone()
+}
@@ -0,0 +1,88 @@
+//! `.eqv`, `.equ` and `.set` constants.
+
+mod common;
+
+use common::{error, program, words};
+
+#[test]
+fn eqv_replaces_text() {
+    let source = "
+        .eqv PRINT_INT 1
+        .eqv EXIT li $v0, 10
+        li $v0, PRINT_INT
+        EXIT
+    ";
+    assert_eq!(words(source), [0x2402_0001, 0x2402_000a]);
+    // Not inside strings
+    let program = program(".eqv A 5\n.data\n.ascii \"A\"");
+    assert_eq!(program.data, b"A");
+}
+
+#[test]
+fn numeric_constants() {
+    let source = "
+        .equ SIZE, 4 * 4
+        .equ HALF, SIZE / 2
+        .equ LATE, LATER + 1
+        .equ LATER, 7
+        .set n, 1
+        .set n, n + 1
+        li $a0, HALF
+        addi $t0, $zero, n
+        li $t1, LATE
+        addi $t3, $t2, HALF
+        .data
+        .space SIZE
+        .word SIZE - n
+    ";
+    assert_eq!(
+        words(source),
+        [0x2404_0008, 0x2008_0002, 0x2409_0008, 0x214b_0008]
+    );
+    assert_eq!(program(source).data[16..], [0, 0, 0, 14]);
+    // A constant offset goes into a load as is
+    assert_eq!(
+        words(".equ HALF, 8\nlw $t3, HALF($t2)\nsw $t3, -HALF($sp)"),
+        [0x8d4b_0008, 0xafab_fff8]
+    );
+}
+
+#[test]
+fn constants_of_labels() {
+    let source = "
+        .data
+        buf: .space 16
+        .equ END, buf + 16
+        .text
+        la $t0, END
+    ";
+    assert_eq!(words(source), [0x3c01_1001, 0x3428_0010]);
+}
+
+#[test]
+fn redefinitions_and_cycles() {
+    assert_eq!(
+        error(".eqv X 1\n.eqv X 2"),
+        "Constant X is already defined on line 1."
+    );
+    assert_eq!(
+        error(".equ Y, 3\n.set Y, 4"),
+        "Constant Y is already defined on line 1."
+    );
+    assert_eq!(
+        error("lab: jr $ra\n.equ lab, 3"),
+        "lab is already defined as a label on line 1."
+    );
+    assert_eq!(
+        error(".equ K, 1\nK: jr $ra"),
+        "K is already defined as a constant on line 1."
+    );
+    assert_eq!(
+        error(".equ A, B + 1\n.equ B, A * 2"),
+        "Constant B is defined in terms of itself: B -> A -> B."
+    );
+    assert_eq!(
+        error(".set n, n + 1"),
+        "Constant n is defined in terms of itself: n -> n."
+    );
+}
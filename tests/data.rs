@@ -0,0 +1,128 @@
+//! Data directives: how values are laid out and aligned, and the values they refuse.
+
+mod common;
+
+use assembler_rust::{MemoryMap, Options};
+use common::{diagnostics_with, error, program, program_with};
+
+fn address(source: &str, label: &str) -> u32 {
+    let program = program(source);
+    program
+        .symbols
+        .iter()
+        .find(|s| s.name == label)
+        .unwrap()
+        .address
+        - program.data_start
+}
+
+#[test]
+fn values_are_aligned_to_their_size() {
+    let source = "
+        .data
+        a: .byte 1
+        b: .half 2
+        c: .byte 3
+        d: .word 4
+        e: .byte 5
+        f: .double 1.5
+    ";
+    assert_eq!(
+        program(source).data,
+        [1, 0, 0, 2, 3, 0, 0, 0, 0, 0, 0, 4, 5, 0, 0, 0, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]
+    );
+    let addresses: Vec<u32> = ["a", "b", "c", "d", "e", "f"]
+        .iter()
+        .map(|l| address(source, l))
+        .collect();
+    assert_eq!(addresses, [0, 2, 4, 8, 12, 16]);
+}
+
+#[test]
+fn space_and_align() {
+    let source = "
+        .data
+        a: .space 3
+        b: .word 6
+        c: .byte 7
+        .align 3
+        d: .byte 8
+        .align 0
+        e: .half 9
+        f: .space 1
+        g: .byte 10
+    ";
+    assert_eq!(
+        program(source).data,
+        [0, 0, 0, 0, 0, 0, 0, 6, 7, 0, 0, 0, 0, 0, 0, 0, 8, 0, 9, 0, 10]
+    );
+    let addresses: Vec<u32> = ["a", "b", "c", "d", "e", "f", "g"]
+        .iter()
+        .map(|l| address(source, l))
+        .collect();
+    // .align 0 turns automatic alignment off, so the half stays on an odd address
+    assert_eq!(addresses, [0, 4, 8, 16, 17, 19, 20]);
+}
+
+#[test]
+fn directives_without_values() {
+    assert_eq!(error(".data\n.word"), ".word needs at least one value.");
+    assert_eq!(
+        error(".data\nx: .half   "),
+        ".half needs at least one value."
+    );
+    assert_eq!(
+        error(".data\n.byte # none"),
+        ".byte needs at least one value."
+    );
+}
+
+#[test]
+fn floats_out_of_range() {
+    assert_eq!(
+        error(".data\n.float 1e39"),
+        "Value 1e39 does not fit in .float."
+    );
+    assert_eq!(
+        error(".data\n.double 1e999"),
+        "Value 1e999 does not fit in .double."
+    );
+    assert_eq!(
+        error(".data\n.double -inf"),
+        "Value -inf does not fit in .double."
+    );
+    assert_eq!(program(".data\n.float -1e38\n.double 1e308").data.len(), 16);
+}
+
+#[test]
+fn sizes_past_the_end_of_the_segment() {
+    // The data segment runs up to kernel text at 0x80000000
+    assert_eq!(
+        error(".data\n.space 0x7fffffff"),
+        ".space runs past the end of its segment."
+    );
+    assert_eq!(
+        error(".data\n.word 1:0x7fffffff"),
+        ".word runs past the end of its segment."
+    );
+    // Kernel data at the top of memory runs up to the end of the address space
+    let options = Options {
+        memory: MemoryMap {
+            kdata: 0xffff_fff0,
+            ..MemoryMap::default()
+        },
+        ..Options::default()
+    };
+    assert_eq!(
+        diagnostics_with(".kdata\n.word 1:8", &options)[0].message,
+        ".word runs past the end of its segment."
+    );
+    assert_eq!(
+        diagnostics_with(".kdata\n.byte 1\n.space 16", &options)[0].message,
+        ".space runs past the end of its segment."
+    );
+    assert_eq!(
+        program_with(".kdata\n.word 1:3", &options).kdata,
+        [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1]
+    );
+}
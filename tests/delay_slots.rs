@@ -0,0 +1,135 @@
+//! Delayed branches, `.set reorder` and `.set noreorder`.
+
+mod common;
+
+use assembler_rust::{Cpu, Options, Program, Stop};
+use common::{program_with, to_words};
+
+fn program(source: &str, delayed_branches: bool) -> Program {
+    let options = Options {
+        delayed_branches,
+        ..Options::default()
+    };
+    program_with(source, &options)
+}
+
+fn words(source: &str, delayed_branches: bool) -> Vec<u32> {
+    to_words(&program(source, delayed_branches).text)
+}
+
+fn run(source: &str, delayed_branches: bool) -> Cpu {
+    let mut cpu = Cpu::new(&program(source, delayed_branches));
+    let stop = cpu.run(Some(1000), &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    cpu
+}
+
+#[test]
+fn reorder_pads_delay_slots() {
+    let source = "beq $t0, $t1, next\nnext: jr $ra";
+    assert_eq!(words(source, false), [0x1109_0000, 0x03e0_0008]);
+    assert_eq!(
+        words(source, true),
+        [0x1109_0001, 0x0000_0000, 0x03e0_0008, 0x0000_0000]
+    );
+    // Pseudo-ops that end in a branch get one too
+    assert_eq!(words("back: blt $t0, $t1, back", true).len(), 3);
+}
+
+#[test]
+fn noreorder_leaves_delay_slots_alone() {
+    let source = ".set noreorder\nbeq $t0, $t1, next\nnop\nnext: .set reorder\nj next";
+    assert_eq!(
+        words(source, true),
+        [0x1109_0001, 0x0000_0000, 0x0810_0002, 0x0000_0000]
+    );
+}
+
+#[test]
+fn pseudo_op_delay_slots() {
+    // div checks for zero with a branch over break, which needs a nop when branches are delayed
+    assert_eq!(
+        words("div $t0, $t1, $t2", false),
+        [0x1540_0001, 0x0000_000d, 0x012a_001a, 0x0000_4012]
+    );
+    assert_eq!(
+        words("div $t0, $t1, $t2", true),
+        [
+            0x1540_0002,
+            0x0000_0000,
+            0x0000_000d,
+            0x012a_001a,
+            0x0000_4012
+        ]
+    );
+}
+
+#[test]
+fn delay_slot_runs() {
+    let source = "
+        .set noreorder
+        beq $zero, $zero, skip
+        addiu $t0, $t0, 1
+        addiu $t0, $t0, 10
+skip:   jal f
+        addiu $t1, $zero, 4
+        b end
+        nop
+f:      jr $ra
+        addiu $t2, $zero, 8
+end:
+    ";
+    let cpu = run(source, true);
+    assert_eq!(&cpu.regs[8..11], [1, 4, 8]);
+    assert_eq!(cpu.regs[31], 0x0040_0014);
+    let cpu = run(source, false);
+    assert_eq!(&cpu.regs[8..11], [0, 4, 0]);
+    assert_eq!(cpu.regs[31], 0x0040_0010);
+}
+
+#[test]
+fn same_results_in_reorder_mode() {
+    let source = "
+        li $t0, 5
+loop:   addu $s0, $s0, $t0
+        addiu $t0, $t0, -1
+        bgtz $t0, loop
+        jal f
+        li $t1, 7
+        li $t2, 2
+        div $t3, $t1, $t2
+        b end
+f:      addiu $s1, $s1, 1
+        jr $ra
+end:
+    ";
+    for delayed in [false, true] {
+        let cpu = run(source, delayed);
+        assert_eq!(cpu.regs[16], 15);
+        assert_eq!(cpu.regs[17], 1);
+    }
+}
+
+#[test]
+fn exception_in_a_delay_slot() {
+    let source = "
+        .set noreorder
+        li $t0, 0x7fffffff
+        b end
+        add $t1, $t0, $t0
+end:    li $v0, 10
+        syscall
+        .set reorder
+        .ktext 0x80000180
+        mfc0 $s0, $14
+        mfc0 $s1, $13
+        li $v0, 10
+        syscall
+    ";
+    let mut cpu = Cpu::new(&program(source, true));
+    let stop = cpu.run(Some(1000), &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(stop, Ok(Stop::Exit(0)));
+    // EPC points at the branch, and Cause says so
+    assert_eq!(cpu.regs[16], 0x0040_0008);
+    assert_eq!(cpu.regs[17], 1 << 31 | 12 << 2);
+}
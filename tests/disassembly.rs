@@ -0,0 +1,82 @@
+//! Disassembly, checked by assembling its output again.
+
+mod common;
+
+use assembler_rust::{disassemble, Isa, Options};
+use common::{program, program_with, to_words};
+use std::collections::HashMap;
+
+/// Assembles `source`, disassembles the text and assembles that, returning the words of both
+/// along with the disassembly.
+fn round_trip(source: &str, isa: Isa) -> (Vec<u32>, Vec<u32>, String) {
+    let options = Options {
+        isa,
+        ..Options::default()
+    };
+    let first = program_with(source, &options);
+    let symbols: HashMap<String, u32> = first
+        .symbols
+        .iter()
+        .map(|s| (s.name.clone(), s.address))
+        .collect();
+    let text = disassemble(&first.text, first.text_start, &symbols, isa);
+    let second = program_with(&text, &options);
+    (to_words(&first.text), to_words(&second.text), text)
+}
+
+#[test]
+fn mixed_program() {
+    let source = "
+        .data
+        msg: .asciiz \"hi\"
+        .text
+        main: la $a0, msg
+        li $v0, 4
+        syscall
+        li $t0, 0x12345
+        loop: addi $t0, $t0, -1
+        sll $t1, $t0, 2
+        lw $t2, -8($sp)
+        sb $t2, 3($a0)
+        blt $t0, $t1, loop
+        bgez $t0, done
+        mult $t0, $t1
+        mflo $t3
+        jal func
+        j done
+        func: jr $ra
+        add.s $f0, $f2, $f4
+        c.lt.d $f2, $f4
+        bc1t func
+        mfc1 $t0, $f1
+        done: li $v0, 10
+        syscall
+        .word 0xffffffff
+    ";
+    let (first, second, text) = round_trip(source, Isa::default());
+    assert_eq!(first, second, "{}", text);
+    assert!(text.contains("loop:"), "{}", text);
+    assert!(text.contains("jal func"), "{}", text);
+    assert!(text.contains(".word 0xffffffff"), "{}", text);
+}
+
+#[test]
+fn targets_without_symbols_get_labels() {
+    let source = "
+        beq $t0, $zero, 0x0040000c
+        nop
+        j 0x00400000
+        jr $ra
+    ";
+    let program = program(source);
+    let text = disassemble(
+        &program.text,
+        program.text_start,
+        &HashMap::new(),
+        Isa::default(),
+    );
+    assert!(text.contains("L_00400000:"), "{}", text);
+    assert!(text.contains("beq $t0, $zero, L_0040000c"), "{}", text);
+    let (first, second, text) = round_trip(source, Isa::default());
+    assert_eq!(first, second, "{}", text);
+}
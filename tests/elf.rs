@@ -0,0 +1,228 @@
+//! ELF output, read back field by field.
+
+mod common;
+
+use assembler_rust::{ElfKind, Endian};
+use common::program;
+
+const SOURCE: &str = "
+    .extern buf, 16
+    .data
+    count: .word 3
+    .text
+    .globl main
+    main: la $t0, buf
+    jal func
+    lw $t1, count
+    func: jr $ra
+";
+
+/// Just enough of an ELF reader to check what the assembler wrote.
+struct Elf {
+    bytes: Vec<u8>,
+    big: bool,
+}
+
+/// A section header: name, type, address, offset, size, link and info.
+struct Section {
+    name: String,
+    kind: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+}
+
+impl Elf {
+    fn new(kind: ElfKind, endian: Endian) -> Elf {
+        Elf {
+            bytes: program(SOURCE).elf(kind, endian),
+            big: endian == Endian::Big,
+        }
+    }
+
+    fn half(&self, off: u32) -> u32 {
+        let b = [self.bytes[off as usize], self.bytes[off as usize + 1]];
+        (if self.big {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }) as u32
+    }
+
+    fn word(&self, off: u32) -> u32 {
+        let b: [u8; 4] = self.bytes[off as usize..off as usize + 4]
+            .try_into()
+            .unwrap();
+        if self.big {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+
+    fn string(&self, off: u32) -> String {
+        let rest = &self.bytes[off as usize..];
+        let end = rest.iter().position(|b| *b == 0).unwrap();
+        String::from_utf8(rest[..end].to_vec()).unwrap()
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let (shoff, shnum, shstrndx) = (self.word(32), self.half(48), self.half(50));
+        let header = |i: u32| shoff + 40 * i;
+        let names = self.word(header(shstrndx) + 16);
+        (0..shnum)
+            .map(|i| {
+                let h = header(i);
+                Section {
+                    name: self.string(names + self.word(h)),
+                    kind: self.word(h + 4),
+                    addr: self.word(h + 12),
+                    offset: self.word(h + 16),
+                    size: self.word(h + 20),
+                    link: self.word(h + 24),
+                    info: self.word(h + 28),
+                }
+            })
+            .collect()
+    }
+
+    fn section(&self, name: &str) -> Section {
+        self.sections()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no section {}", name))
+    }
+
+    /// Name, value, info and section index of each symbol.
+    fn symbols(&self) -> Vec<(String, u32, u8, u16)> {
+        let symtab = self.section(".symtab");
+        let strtab = self.sections()[symtab.link as usize].offset;
+        (0..symtab.size / 16)
+            .map(|i| {
+                let s = symtab.offset + 16 * i;
+                (
+                    self.string(strtab + self.word(s)),
+                    self.word(s + 4),
+                    self.bytes[s as usize + 12],
+                    self.half(s + 14) as u16,
+                )
+            })
+            .collect()
+    }
+
+    /// Offset, type and symbol index of each entry of a relocation section.
+    fn relocations(&self, name: &str) -> Vec<(u32, u32, u32)> {
+        let rel = self.section(name);
+        (0..rel.size / 8)
+            .map(|i| {
+                let info = self.word(rel.offset + 8 * i + 4);
+                (self.word(rel.offset + 8 * i), info & 0xff, info >> 8)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn header() {
+    for (kind, endian) in [
+        (ElfKind::Executable, Endian::Big),
+        (ElfKind::Object, Endian::Little),
+    ] {
+        let elf = Elf::new(kind, endian);
+        let exec = kind == ElfKind::Executable;
+        assert_eq!(elf.bytes[..4], *b"\x7fELF");
+        // ELFCLASS32, then ELFDATA2LSB or ELFDATA2MSB
+        assert_eq!(elf.bytes[4], 1);
+        assert_eq!(elf.bytes[5], if elf.big { 2 } else { 1 });
+        // ET_EXEC or ET_REL, for EM_MIPS
+        assert_eq!(elf.half(16), if exec { 2 } else { 1 });
+        assert_eq!(elf.half(18), 8);
+        assert_eq!(elf.word(24), if exec { 0x0040_0000 } else { 0 });
+        // Program headers for text, data and the extern area, only in executables
+        assert_eq!(elf.half(44), if exec { 3 } else { 0 });
+    }
+}
+
+#[test]
+fn sections() {
+    let exec = Elf::new(ElfKind::Executable, Endian::Big);
+    let text = exec.section(".text");
+    assert_eq!((text.kind, text.addr, text.size), (1, 0x0040_0000, 24));
+    assert_eq!(
+        exec.bytes[text.offset as usize..text.offset as usize + 4],
+        [0x3c, 0x01, 0x10, 0x00]
+    );
+    let bss = exec.section(".bss");
+    assert_eq!((bss.kind, bss.addr, bss.size), (8, 0x1000_0000, 16));
+
+    let obj = Elf::new(ElfKind::Object, Endian::Little);
+    let data = obj.section(".data");
+    assert_eq!((data.kind, data.addr, data.size), (1, 0, 4));
+    // A little-endian word
+    assert_eq!(obj.word(data.offset), 3);
+    let names: Vec<String> = obj.sections().into_iter().map(|s| s.name).collect();
+    assert_eq!(
+        names,
+        [
+            "",
+            ".text",
+            ".data",
+            ".bss",
+            ".ktext",
+            ".kdata",
+            ".symtab",
+            ".strtab",
+            ".rel.text",
+            ".shstrtab"
+        ]
+    );
+}
+
+#[test]
+fn symbols() {
+    let obj = Elf::new(ElfKind::Object, Endian::Big);
+    let symbols = obj.symbols();
+    // The null symbol and one per section come first
+    assert_eq!(
+        symbols[1..6].iter().map(|s| s.3).collect::<Vec<_>>(),
+        [1, 2, 3, 4, 5]
+    );
+    let named: Vec<(&str, u32, u8, u16)> = symbols[6..]
+        .iter()
+        .map(|(n, v, i, s)| (n.as_str(), *v, *i, *s))
+        .collect();
+    // The file, then locals, then globals, at offsets into their sections
+    assert_eq!(
+        named,
+        [
+            ("", 0, 4, 0xfff1),
+            ("func", 20, 0, 1),
+            ("count", 0, 0, 2),
+            ("main", 0, 0x10, 1),
+            ("buf", 0, 0x10, 3)
+        ]
+    );
+    // The first global, as .extern declares a global
+    assert_eq!(obj.section(".symtab").info, 9);
+}
+
+#[test]
+fn relocations() {
+    let obj = Elf::new(ElfKind::Object, Endian::Big);
+    // R_MIPS_HI16 and R_MIPS_LO16 against .bss for la, R_MIPS_26 against .text for jal,
+    // and HI16/LO16 against .data for lw
+    assert_eq!(
+        obj.relocations(".rel.text"),
+        [(0, 5, 3), (4, 6, 3), (8, 4, 1), (12, 5, 2), (16, 6, 2)]
+    );
+    let text = obj.section(".text");
+    let word = |n: u32| obj.word(text.offset + 4 * n);
+    // Fields hold offsets into the target section, and ori became addiu
+    assert_eq!(
+        [word(0), word(1), word(2)],
+        [0x3c01_0000, 0x2428_0000, 0x0c00_0005]
+    );
+    assert_eq!(obj.section(".rel.text").info, 1);
+}
@@ -0,0 +1,47 @@
+//! Encodings that sit next to similar ones: function codes, opcodes and implied registers.
+
+mod common;
+
+use common::words;
+
+#[test]
+fn set_on_less_than() {
+    let source = "
+        slt $t0, $t1, $t2
+        sltu $t0, $t1, $t2
+        slti $t0, $t1, 5
+        sltiu $t0, $t1, 5
+    ";
+    assert_eq!(
+        words(source),
+        [0x012a_402a, 0x012a_402b, 0x2928_0005, 0x2d28_0005]
+    );
+}
+
+#[test]
+fn jalr_links_through_ra() {
+    assert_eq!(words("jalr $t0\njr $t0"), [0x0100_f809, 0x0100_0008]);
+}
+
+#[test]
+fn unaligned_and_linked_opcodes() {
+    let source = "
+        lwl $t0, 4($t1)
+        lwr $t0, 4($t1)
+        swl $t0, 4($t1)
+        swr $t0, 4($t1)
+        ll $t0, 4($t1)
+        sc $t0, 4($t1)
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x8928_0004,
+            0x9928_0004,
+            0xa928_0004,
+            0xb928_0004,
+            0xc128_0004,
+            0xe128_0004
+        ]
+    );
+}
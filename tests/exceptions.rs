@@ -0,0 +1,118 @@
+//! Coprocessor 0, the kernel segments and exceptions taken by a handler.
+
+mod common;
+
+use assembler_rust::{Cpu, MemoryMap, Options, Stop};
+use common::{diagnostics_with, error, program, program_with, words};
+
+const HANDLER: &str = "
+        .ktext 0x80000180
+        mfc0 $k0, $13
+        srl $a0, $k0, 2
+        andi $a0, $a0, 31
+        li $v0, 1
+        syscall
+        mfc0 $k0, $14
+        addiu $k0, $k0, 4
+        mtc0 $k0, $14
+        eret
+";
+
+/// Runs `source` with the handler above and returns what it printed, the stop and the CPU.
+fn run(source: &str) -> (String, Result<Stop, String>, Cpu) {
+    let source = format!("{}{}", source, HANDLER);
+    let mut cpu = Cpu::new(&program(&source));
+    let mut out = Vec::new();
+    let stop = cpu.run(Some(1000), &mut std::io::empty(), &mut out);
+    (
+        String::from_utf8(out).unwrap(),
+        stop.map_err(|e| e.to_string()),
+        cpu,
+    )
+}
+
+#[test]
+fn encodings() {
+    assert_eq!(
+        words("mfc0 $k0, $13\nmtc0 $k0, $14\neret"),
+        [0x401a_6800, 0x409a_7000, 0x4200_0018]
+    );
+}
+
+#[test]
+fn kernel_segments() {
+    let program = program(".kdata\nmsg: .word 7\n.ktext\nlw $t0, msg\n.text\nli $t0, 1");
+    assert_eq!((program.ktext_start, program.ktext.len()), (0x8000_0000, 8));
+    assert_eq!(
+        (program.kdata_start, program.kdata),
+        (0x9000_0000, vec![0, 0, 0, 7])
+    );
+    assert_eq!(program.text.len(), 4);
+}
+
+#[test]
+fn segments_end_at_the_next_one() {
+    assert_eq!(
+        error(".ktext 0xfffffffc\nnop"),
+        "Address 0xfffffffc is past the end of the segment for .ktext, 0x90000000."
+    );
+    assert_eq!(
+        error(".kdata 0xfffffff0\n.word 1:8"),
+        "Address 0xfffffff0 is past the end of the segment for .kdata, 0xffff0000."
+    );
+    // Kernel text at the top of memory runs up to the end of the address space
+    let options = Options {
+        memory: MemoryMap {
+            ktext: 0xffff_fff8,
+            ..MemoryMap::default()
+        },
+        ..Options::default()
+    };
+    assert_eq!(program_with(".ktext\nnop", &options).ktext.len(), 4);
+    let diags = diagnostics_with(".ktext\nnop\nnop\nnop", &options);
+    assert_eq!(
+        diags
+            .iter()
+            .map(|d| (d.line, d.message.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            (3, "Segment runs past the end of the address space."),
+            (4, "Segment runs past the end of the address space.")
+        ]
+    );
+}
+
+#[test]
+fn handler_gets_the_cause() {
+    let (out, stop, cpu) = run("
+        li $t0, 0x7fffffff
+        add $t1, $t0, $t0
+        teq $t0, $t0
+        lw $t2, 1($zero)
+        sw $t2, 2($zero)
+        break
+        li $v0, 10
+        syscall
+    ");
+    assert_eq!(stop, Ok(Stop::Exit(0)));
+    assert_eq!(out, "1213459");
+    // The sum is never written
+    assert_eq!(cpu.regs[9], 0);
+    assert_eq!(cpu.cp0[8], 2);
+    assert_eq!(cpu.cp0[12] & 2, 0);
+}
+
+#[test]
+fn unknown_syscalls_go_to_the_handler() {
+    let (out, stop, cpu) = run("li $v0, 99\nsyscall");
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(out, "8");
+    assert_eq!(cpu.cp0[14], 0x0040_0008);
+}
+
+#[test]
+fn without_a_handler_the_program_stops() {
+    let mut cpu = Cpu::new(&program("li $t0, 0x7fffffff\nadd $t1, $t0, $t0"));
+    let stop = cpu.run(None, &mut std::io::empty(), &mut std::io::sink());
+    assert!(stop.is_err());
+}
@@ -0,0 +1,163 @@
+//! Operand and data expressions, `.` and `%hi`/`%lo`/`%gp_rel`.
+
+mod common;
+
+use assembler_rust::RelocKind;
+use common::{error, program, to_words, words};
+
+#[test]
+fn constant_expressions() {
+    let source = "
+        li $t0, (1 << 12) | 3
+        addi $t0, $t0, -(2 * 3)
+        sll $t0, $t0, 32 / 8
+        ori $t0, $t0, ~0 & 0xff
+        li $t0, 0x10000 + 1
+    ";
+    assert_eq!(
+        to_words(&program(source).text),
+        [
+            0x2408_1003,
+            0x2108_fffa,
+            0x0008_4100,
+            0x3508_00ff,
+            0x3c01_0001,
+            0x3428_0001
+        ]
+    );
+}
+
+#[test]
+fn label_offsets() {
+    let source = "
+        .data
+        arr: .word 1, 2, 3
+        .text
+        lw $t0, arr+8
+        la $t0, arr + 4
+        lw $t0, arr+100000($t1)
+    ";
+    assert_eq!(
+        to_words(&program(source).text),
+        [
+            0x3c01_1001,
+            0x8c28_0008,
+            0x3c01_1001,
+            0x3428_0004,
+            0x3c01_1003,
+            0x0029_0821,
+            0x8c28_86a0
+        ]
+    );
+}
+
+#[test]
+fn address_halves() {
+    let source = "
+        .data
+        .space 0x8020
+        x: .word 5
+        .extern v 4
+        .text
+        lui $t0, %hi(x)
+        addiu $t0, $t0, %lo(x)
+        addiu $t1, $t0, %lo(x)
+        addiu $t0, $gp, %gp_rel(v)
+    ";
+    let program = program(source);
+    // x is 0x10018020, so the low half is negative and the high half rounds up
+    assert_eq!(
+        to_words(&program.text),
+        [0x3c08_1002, 0x2508_8020, 0x2509_8020, 0x2788_8000]
+    );
+    let kinds: Vec<(RelocKind, &str, u32)> = program
+        .relocations
+        .iter()
+        .map(|r| (r.kind, r.symbol.as_str(), r.value))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (RelocKind::Hi16, "x", 0x1001_8020),
+            (RelocKind::Lo16, "x", 0x1001_8020),
+            (RelocKind::Lo16, "x", 0x1001_8020)
+        ]
+    );
+    // The halves also make the offsets of loads
+    let source = "
+        .data
+        .space 0x8020
+        x: .word 5
+        .extern v 4
+        .text
+        lw $t1, %lo(x)($t0)
+        lw $t0, %gp_rel(v)($gp)
+    ";
+    assert_eq!(words(source), [0x8d09_8020, 0x8f88_8000]);
+}
+
+#[test]
+fn dot_is_the_statement_address() {
+    let source = "
+        beq $t0, $t1, . + 8
+        j .
+        nop
+    ";
+    assert_eq!(
+        to_words(&program(source).text),
+        [0x1109_0001, 0x0810_0001, 0x0000_0000]
+    );
+}
+
+#[test]
+fn data_expressions() {
+    let source = "
+        .data
+        a: .word 1
+        b: .word b - a, a + 4, 3 * 4, .
+        .half (1 << 8) + 1, -1
+    ";
+    let program = program(source);
+    assert_eq!(
+        to_words(&program.data),
+        [1, 4, 0x1001_0004, 12, 0x1001_0010, 0x0101_ffff]
+    );
+    // Only values relative to a single label are relocated
+    let relocated: Vec<u32> = program.relocations.iter().map(|r| r.address).collect();
+    assert_eq!(relocated, [0x1001_0008]);
+}
+
+#[test]
+fn expression_errors() {
+    assert_eq!(error("li $t0, 1/0"), "Division by zero in li.");
+    assert_eq!(error(".word 1 % (2 - 2)"), "Division by zero.");
+    assert_eq!(
+        error("lw $t0, nowhere+4"),
+        "Label \"nowhere\" not found in lw."
+    );
+    assert_eq!(
+        error(".data\ny: .word 0\n.byte y"),
+        "Value y (268500992) does not fit in .byte."
+    );
+}
+
+#[test]
+fn values_must_fit_in_32_bits() {
+    assert_eq!(
+        error("li $t0, 1<<40"),
+        "Expression value does not fit in 32 bits in li."
+    );
+    assert_eq!(
+        error("addi $t1, $t0, 0x7fffffff*0x7fffffff"),
+        "Expression value does not fit in 32 bits in addi."
+    );
+    assert_eq!(
+        error(".data\n.word 0xffffffff+1"),
+        "Value 0xffffffff+1 (4294967296) does not fit in .word."
+    );
+    // Either the signed or the unsigned range will do
+    assert_eq!(
+        to_words(&program("li $t0, 0x7fffffff*2+1\nli $t1, -0x7fffffff-1").text),
+        [0x2408_ffff, 0x3c01_8000, 0x3429_0000]
+    );
+}
@@ -0,0 +1,96 @@
+//! Coprocessor 1 instructions, against the words MARS assembles them to.
+
+mod common;
+
+use common::{diagnostics, error, words};
+
+#[test]
+fn arithmetic() {
+    let source = "
+        add.s $f0, $f2, $f4
+        sub.d $f2, $f4, $f6
+        mul.s $f1, $f3, $f5
+        div.d $f8, $f10, $f12
+    ";
+    assert_eq!(
+        words(source),
+        [0x4604_1000, 0x4626_2081, 0x4605_1842, 0x462c_5203]
+    );
+}
+
+#[test]
+fn conversions() {
+    let source = "
+        cvt.s.d $f0, $f2
+        cvt.d.w $f2, $f4
+        cvt.w.s $f1, $f3
+    ";
+    assert_eq!(words(source), [0x4620_1020, 0x4680_20a1, 0x4600_1864]);
+}
+
+#[test]
+fn compare_and_branch() {
+    let source = "
+        c.eq.s $f0, $f1
+        c.lt.d 3, $f2, $f4
+        c.le.s $f6, $f7
+        bc1t end
+        bc1f 2, end
+        end: nop
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x4601_0032,
+            0x4624_133c,
+            0x4607_303e,
+            0x4501_0001,
+            0x4508_0000,
+            0x0000_0000
+        ]
+    );
+}
+
+#[test]
+fn loads_stores_and_moves() {
+    let source = "
+        lwc1 $f1, 8($sp)
+        sdc1 $f2, -16($sp)
+        ldc1 $f4, 0($a0)
+        swc1 $f5, 4($a0)
+        mfc1 $t0, $f1
+        mtc1 $t1, $f3
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0xc7a1_0008,
+            0xf7a2_fff0,
+            0xd484_0000,
+            0xe485_0004,
+            0x4408_0800,
+            0x4489_1800
+        ]
+    );
+}
+
+#[test]
+fn doubles_need_even_registers() {
+    assert_eq!(
+        error("add.d $f1, $f2, $f4"),
+        "add.d needs an even register for a double, not \"$f1\"."
+    );
+    assert_eq!(
+        error("cvt.s.d $f0, $f3"),
+        "cvt.s.d needs an even register for a double, not \"$f3\"."
+    );
+    assert_eq!(
+        error("sdc1 $f3, 0($sp)"),
+        "sdc1 needs an even register for a double, not \"$f3\"."
+    );
+    // The caret is under the odd register
+    let source = "mul.d $f2, $f4, $f7";
+    assert_eq!(&source[diagnostics(source)[0].cols.clone()], "$f7");
+    // Singles take any register
+    assert_eq!(words("add.s $f1, $f3, $f5"), [0x4605_1840]);
+}
@@ -0,0 +1,201 @@
+//! `.include` and conditional assembly with `.if`, `.ifdef` and `.ifndef`.
+
+mod common;
+
+use std::path::PathBuf;
+
+use assembler_rust::{assemble, Diagnostic, Options, Program};
+use common::{diagnostics, program_with, to_words};
+
+/// A directory of its own for `test`, holding `files`, given as name and text.
+fn dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("includes-{}-{}", test, std::process::id()));
+    for (name, text) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+fn assemble_file(path: PathBuf, options: Options) -> Result<Program, Vec<Diagnostic>> {
+    let source = std::fs::read_to_string(&path).unwrap();
+    let options = Options {
+        file: path.display().to_string(),
+        ..options
+    };
+    assemble(&source, &options)
+}
+
+fn errors(source: &str) -> Vec<String> {
+    diagnostics(source).into_iter().map(|d| d.message).collect()
+}
+
+#[test]
+fn includes_next_to_the_file_then_in_include_dirs() {
+    let dir = dir(
+        "search",
+        &[
+            (
+                "main.asm",
+                ".include \"defs.asm\"\nli $a0, SIZE\n.include \"util.asm\"",
+            ),
+            ("defs.asm", ".eqv SIZE 4"),
+            ("lib/util.asm", "helper: jr $ra"),
+        ],
+    );
+    let options = Options {
+        include_dirs: vec![dir.join("lib")],
+        ..Options::default()
+    };
+    let program = assemble_file(dir.join("main.asm"), options).unwrap();
+    assert_eq!(to_words(&program.text), [0x2404_0004, 0x03e0_0008]);
+    let files: Vec<PathBuf> = program.files.iter().map(PathBuf::from).collect();
+    assert_eq!(
+        files,
+        [
+            dir.join("main.asm"),
+            dir.join("defs.asm"),
+            dir.join("lib/util.asm")
+        ]
+    );
+    let included: Vec<(usize, usize, u32)> = program
+        .includes
+        .iter()
+        .map(|i| (i.file, i.parent, i.line))
+        .collect();
+    assert_eq!(included, [(1, 0, 1), (2, 0, 3)]);
+    // Labels belong to the file that includes them
+    assert_eq!((program.symbols[0].file, program.symbols[0].line), (2, 1));
+
+    let source = std::fs::read_to_string(dir.join("main.asm")).unwrap();
+    let listing = program.listing(&[&source]);
+    assert!(listing.contains(&format!(
+        "   3                        .include \"util.asm\"\nFile {}\n   1  0x00400004  03e00008  helper: jr $ra\n",
+        program.files[2]
+    )));
+}
+
+#[test]
+fn diagnostics_name_the_included_file() {
+    let dir = dir(
+        "diagnostics",
+        &[
+            ("main.asm", "nop\n.include \"bad.asm\"\nj nowhere"),
+            ("bad.asm", "\nadd $t0, $t1"),
+        ],
+    );
+    let diags = assemble_file(dir.join("main.asm"), Options::default()).unwrap_err();
+    let places: Vec<(PathBuf, u32)> = diags
+        .iter()
+        .map(|d| (PathBuf::from(&d.file), d.line))
+        .collect();
+    assert_eq!(
+        places,
+        [(dir.join("main.asm"), 3), (dir.join("bad.asm"), 2)]
+    );
+}
+
+#[test]
+fn include_errors() {
+    let dir = dir(
+        "errors",
+        &[
+            ("a.asm", ".include \"b.asm\""),
+            ("b.asm", ".include \"a.asm\""),
+            ("c.asm", ".include \"missing.asm\""),
+        ],
+    );
+    let message = |name: &str| {
+        assemble_file(dir.join(name), Options::default()).unwrap_err()[0]
+            .message
+            .clone()
+    };
+    let (a, b) = (dir.join("a.asm"), dir.join("b.asm"));
+    assert_eq!(
+        message("a.asm"),
+        format!(
+            "Cyclic .include: {} -> {} -> {}.",
+            a.display(),
+            b.display(),
+            a.display()
+        )
+    );
+    assert_eq!(
+        message("c.asm"),
+        format!("File \"missing.asm\" not found in {}.", dir.display())
+    );
+}
+
+#[test]
+fn conditions() {
+    let source = "
+        .eqv SIZE 8
+        .if SIZE - 8
+            li $a0, 1
+        .else
+            li $a0, 2
+            .ifdef DEBUG
+                li $a1, 3
+            .else
+                .ifndef FAST
+                    li $a1, 4
+                .endif
+            .endif
+        .endif
+        .ifdef SIZE
+            li $a2, SIZE
+        .endif
+    ";
+    let program_with = |defines: &[(&str, &str)]| {
+        let options = Options {
+            defines: defines
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            ..Options::default()
+        };
+        to_words(&program_with(source, &options).text)
+    };
+    assert_eq!(program_with(&[]), [0x2404_0002, 0x2405_0004, 0x2406_0008]);
+    assert_eq!(
+        program_with(&[("DEBUG", "1")]),
+        [0x2404_0002, 0x2405_0003, 0x2406_0008]
+    );
+    assert_eq!(program_with(&[("FAST", "1")]), [0x2404_0002, 0x2406_0008]);
+}
+
+#[test]
+fn defines_are_constants() {
+    let options = Options {
+        defines: vec![("N".to_string(), "3".to_string())],
+        ..Options::default()
+    };
+    assert_eq!(
+        to_words(&program_with("li $t0, N * 2", &options).text),
+        [0x2408_0006]
+    );
+    let diags = assemble(".eqv N 4", &options).unwrap_err();
+    assert_eq!(diags[0].message, "Constant N is already defined with -D.");
+}
+
+#[test]
+fn condition_errors() {
+    assert_eq!(
+        errors(".if 1\nnop\n.else\n.else\n.endif"),
+        ["The condition on line 1 already has an .else."]
+    );
+    assert_eq!(errors(".else"), [".else without .if."]);
+    assert_eq!(
+        errors(".if COUNT\n.endif"),
+        ["The condition \"COUNT\" of .if is not a constant."]
+    );
+    assert_eq!(errors(".endif"), [".endif without .if."]);
+    assert_eq!(
+        errors(".ifdef 3\n.endif\n.if 1\nnop"),
+        [
+            "Expected a constant name after .ifdef.",
+            "Missing .endif for this condition."
+        ]
+    );
+}
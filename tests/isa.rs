@@ -0,0 +1,207 @@
+//! Choosing the instruction set revision, checked against the encodings GNU as gives Release 6.
+
+mod common;
+
+use assembler_rust::{disassemble, Cpu, Isa, Options, Stop};
+use common::{diagnostics_with, program_with, to_words};
+use std::collections::HashMap;
+
+fn options(isa: Isa) -> Options {
+    Options {
+        isa,
+        ..Options::default()
+    }
+}
+
+/// Assembles `source` for `isa` and returns its text segment as words.
+fn words(source: &str, isa: Isa) -> Vec<u32> {
+    to_words(&program_with(source, &options(isa)).text)
+}
+
+/// The first error assembling `source` for `isa` gives.
+fn error(source: &str, isa: Isa) -> String {
+    diagnostics_with(source, &options(isa))[0].message.clone()
+}
+
+#[test]
+fn release_6_multiply_and_divide() {
+    let source = "
+        mul $s0, $t1, $t2
+        muh $s1, $t1, $t2
+        mulu $s2, $t1, $t2
+        muhu $s3, $t1, $t2
+        div $s4, $t1, $t2
+        mod $s5, $t1, $t2
+        divu $s6, $t2, $t1
+        modu $s7, $t2, $t1
+    ";
+    assert_eq!(
+        words(source, Isa::Mips32r6),
+        [
+            0x012a_8098,
+            0x012a_88d8,
+            0x012a_9099,
+            0x012a_98d9,
+            0x012a_a09a,
+            0x012a_a8da,
+            0x0149_b09b,
+            0x0149_b8db
+        ]
+    );
+}
+
+#[test]
+fn release_6_re_encodings() {
+    let source = "
+        clz $t3, $t1
+        clo $t4, $t2
+        ll $t6, 4($t5)
+        sc $t6, -4($t5)
+        jr $ra
+    ";
+    assert_eq!(
+        words(source, Isa::Mips32r6),
+        [
+            0x0120_5850,
+            0x0140_6051,
+            0x7dae_0236,
+            0x7dae_fe26,
+            0x03e0_0009
+        ]
+    );
+    // The same source the old way
+    assert_eq!(
+        words(source, Isa::Mips32r2),
+        [
+            0x712b_5820,
+            0x714c_6021,
+            0xc1ae_0004,
+            0xe1ae_fffc,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn compact_branches() {
+    let source = "
+        balc f
+        beqzc $t7, skip
+    skip:
+        bnezc $t6, f
+        bc skip
+    f:
+    ";
+    assert_eq!(
+        words(source, Isa::Mips32r6),
+        [0xe800_0003, 0xd9e0_0000, 0xf9c0_0001, 0xcbff_fffe]
+    );
+}
+
+#[test]
+fn release_6_fpu_compares() {
+    let source = "
+        cmp.lt.s $f0, $f2, $f4
+        cmp.ueq.d $f2, $f4, $f6
+        cmp.af.s $f1, $f3, $f5
+        cmp.ule.d $f8, $f10, $f12
+        bc1eqz $f0, end
+        bc1nez $f3, end
+    end:
+    ";
+    let expected = [
+        0x4684_1004,
+        0x46a6_2083,
+        0x4685_1840,
+        0x46ac_5207,
+        0x4520_0001,
+        0x45a3_0000,
+    ];
+    assert_eq!(words(source, Isa::Mips32r6), expected);
+    let program = program_with(source, &options(Isa::Mips32r6));
+    let text = disassemble(
+        &program.text,
+        program.text_start,
+        &HashMap::new(),
+        Isa::Mips32r6,
+    );
+    assert!(text.contains("cmp.ule.d $f8, $f10, $f12"), "{}", text);
+    assert_eq!(words(&text, Isa::Mips32r6), expected);
+    assert_eq!(
+        error("cmp.lt.d $f1, $f2, $f4", Isa::Mips32r6),
+        "cmp.lt.d needs an even register for a double, not \"$f1\"."
+    );
+}
+
+#[test]
+fn release_6_fpu_compares_run() {
+    // $s0 counts the branches taken: 1 < 2 holds, NaN == NaN does not
+    let source = "
+        li $t0, 1
+        mtc1 $t0, $f2
+        cvt.s.w $f2, $f2
+        add.s $f4, $f2, $f2
+        cmp.lt.s $f0, $f2, $f4
+        mfc1 $s1, $f0
+        bc1eqz $f0, skip1
+        addiu $s0, $s0, 1
+    skip1:
+        sub.s $f6, $f6, $f6
+        div.s $f6, $f6, $f6
+        cmp.eq.s $f8, $f6, $f6
+        bc1nez $f8, skip2
+        addiu $s0, $s0, 2
+    skip2:
+        cmp.ueq.s $f9, $f6, $f6
+        mfc1 $s2, $f9
+    ";
+    let mut cpu = Cpu::new(&program_with(source, &options(Isa::Mips32r6)));
+    let stop = cpu.run(Some(100), &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(cpu.regs[16..19], [3, u32::MAX, u32::MAX]);
+}
+
+#[test]
+fn removed_instructions() {
+    assert_eq!(
+        error("lwl $t0, 0($t1)", Isa::Mips32r6),
+        "lwl was removed in mips32r6."
+    );
+    assert_eq!(
+        error("div $t1, $t2", Isa::Mips32r6),
+        "div $t1,$t2 was removed in mips32r6, which has div $t1,$t2,$t3 instead."
+    );
+    assert_eq!(
+        error("mfhi $t0", Isa::Mips32r6),
+        "mfhi was removed in mips32r6."
+    );
+    // The FPU compares and branches name what replaced them
+    assert_eq!(
+        error("c.lt.d $f0, $f2", Isa::Mips32r6),
+        "c.lt.d $f0,$f2 was removed in mips32r6, which has cmp.lt.d $f0,$f2,$f4 instead."
+    );
+    assert_eq!(
+        error("bc1t end\nend:", Isa::Mips32r6),
+        "bc1t label was removed in mips32r6, which has bc1nez $f1,label instead."
+    );
+    assert_eq!(
+        error("bc1f 1, end\nend:", Isa::Mips32r6),
+        "bc1f label was removed in mips32r6, which has bc1eqz $f1,label instead."
+    );
+}
+
+#[test]
+fn newer_instructions() {
+    assert_eq!(
+        error("ext $t0, $t1, 1, 2", Isa::Mips2),
+        "ext needs mips32r2 or later, but the ISA is mips2."
+    );
+    assert_eq!(
+        error("ll $t0, 0($t1)", Isa::Mips1),
+        "ll needs mips2 or later, but the ISA is mips1."
+    );
+    assert_eq!(
+        error("bc label\nlabel:", Isa::Mips32r2),
+        "bc needs mips32r6 or later, but the ISA is mips32r2."
+    );
+}
@@ -0,0 +1,138 @@
+//! Several files assembled into one program with `assemble_files`.
+
+mod common;
+
+use assembler_rust::{assemble_files, Diagnostic, Options, Program};
+use common::to_words;
+
+fn link(files: &[(&str, &str)]) -> Program {
+    assemble_files(files, &Options::default()).unwrap_or_else(|diags| {
+        panic!("{:?}", diags.iter().map(|d| &d.message).collect::<Vec<_>>())
+    })
+}
+
+fn link_errors(files: &[(&str, &str)]) -> Vec<Diagnostic> {
+    match assemble_files(files, &Options::default()) {
+        Ok(_) => panic!("linked"),
+        Err(diags) => diags.into_iter().filter(|d| d.is_error()).collect(),
+    }
+}
+
+#[test]
+fn global_resolved_across_files() {
+    let main = "
+        .globl main
+        main: jal func
+        la $t0, value
+    ";
+    let lib = "
+        .globl func, value
+        func: jr $ra
+        .data
+        pad: .word 1
+        value: .word 2
+    ";
+    let program = link(&[("main.asm", main), ("lib.asm", lib)]);
+    // lib's text follows the three words of main's
+    assert_eq!(
+        to_words(&program.text),
+        [0x0c10_0003, 0x3c01_1001, 0x3428_0004, 0x03e0_0008]
+    );
+    assert_eq!(program.data, [0, 0, 0, 1, 0, 0, 0, 2]);
+    assert_eq!(program.files, ["main.asm", "lib.asm"]);
+    let globals: Vec<(&str, u32, usize)> = program
+        .symbols
+        .iter()
+        .filter(|s| s.global)
+        .map(|s| (s.name.as_str(), s.address, s.file))
+        .collect();
+    assert_eq!(
+        globals,
+        [
+            ("main", 0x0040_0000, 0),
+            ("func", 0x0040_000c, 1),
+            ("value", 0x1001_0004, 1)
+        ]
+    );
+}
+
+#[test]
+fn extern_without_size_names_a_global() {
+    let main = "
+        .extern func
+        jal func
+    ";
+    let lib = ".globl func\nfunc: jr $ra";
+    let program = link(&[("main.asm", main), ("lib.asm", lib)]);
+    assert_eq!(to_words(&program.text), [0x0c10_0001, 0x03e0_0008]);
+}
+
+#[test]
+fn local_labels_are_per_file() {
+    let first = "
+        loop: j loop
+        .data
+        x: .word 1
+        .text
+        la $t0, x
+    ";
+    let second = "
+        loop: j loop
+        .data
+        x: .word 2
+        .text
+        la $t0, x
+    ";
+    let program = link(&[("a.asm", first), ("b.asm", second)]);
+    assert_eq!(
+        to_words(&program.text),
+        [
+            0x0810_0000,
+            0x3c01_1001,
+            0x3428_0000,
+            0x0810_0003,
+            0x3c01_1001,
+            0x3428_0004
+        ]
+    );
+    let loops: Vec<(u32, usize)> = program
+        .symbols
+        .iter()
+        .filter(|s| s.name == "loop")
+        .map(|s| (s.address, s.file))
+        .collect();
+    assert_eq!(loops, [(0x0040_0000, 0), (0x0040_000c, 1)]);
+}
+
+#[test]
+fn duplicate_global() {
+    let errors = link_errors(&[
+        ("a.asm", ".globl f\nf: jr $ra"),
+        ("b.asm", ".globl f\nf: nop"),
+    ]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "Duplicate global symbol f, first defined in a.asm on line 2."
+    );
+    assert_eq!((errors[0].file.as_str(), errors[0].line), ("b.asm", 2));
+}
+
+#[test]
+fn undefined_extern() {
+    let errors = link_errors(&[("a.asm", ".extern g\njal g"), ("b.asm", "g: jr $ra")]);
+    assert_eq!(
+        errors[0].message,
+        "Undefined global symbol g, no file declares it .globl."
+    );
+    assert_eq!((errors[0].file.as_str(), errors[0].line), ("a.asm", 1));
+}
+
+#[test]
+fn global_without_definition() {
+    let errors = link_errors(&[("a.asm", ".globl g\nnop"), ("b.asm", "nop")]);
+    assert_eq!(
+        errors[0].message,
+        "Label \"g\" is declared global but not defined."
+    );
+}
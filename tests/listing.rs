@@ -0,0 +1,45 @@
+//! The listing written by `-l`, compared whole.
+
+mod common;
+
+use common::program;
+
+#[test]
+fn golden() {
+    let source = "\
+.data
+msg: .asciiz \"hi\"
+n: .word 3, msg
+.text
+main: lw $t0, n
+  beq $t0, $zero, main
+jr $ra
+";
+    let expected = "\
+Line  Address     Code      Source
+   1                        .data
+   2  0x10010000  686900    msg: .asciiz \"hi\"
+   3  0x10010003  00        n: .word 3, msg
+      0x10010004  00000003
+      0x10010008  10010000
+   4                        .text
+   5  0x00400000            main: lw $t0, n
+      0x00400000  3c011001      lui $1, 4097
+      0x00400004  8c280004      lw $t0, 4($1)
+   6  0x00400008  1100fffd    beq $t0, $zero, main
+   7  0x0040000c  03e00008  jr $ra
+
+Symbol table
+  Address     Name
+  0x00400000  main
+  0x10010000  msg
+  0x10010004  n
+
+Cross-reference
+  Name  Defined  Used
+  main  5        6
+  msg   2        3
+  n     3        5
+";
+    assert_eq!(program(source).listing(&[source]), expected);
+}
@@ -0,0 +1,127 @@
+//! `.macro` and `.end_macro`.
+
+mod common;
+
+use assembler_rust::{Cpu, Stop};
+use common::{diagnostics, error, program, words};
+
+const PRINT: &str = "
+.macro print_int(%r)
+    move $a0, %r
+    li $v0, 1
+    syscall
+.end_macro
+.macro print_int
+    print_int($t0)
+.end_macro
+";
+
+#[test]
+fn parameters_and_overloading() {
+    let source = format!("{}\nprint_int($t1)\nprint_int", PRINT);
+    assert_eq!(
+        words(&source),
+        [
+            0x0009_2021,
+            0x2402_0001,
+            0x0000_000c,
+            0x0008_2021,
+            0x2402_0001,
+            0x0000_000c
+        ]
+    );
+    // Without parentheses too
+    assert_eq!(words(&format!("{}\nprint_int $t1", PRINT))[0], 0x0009_2021);
+}
+
+#[test]
+fn each_expansion_has_its_own_labels() {
+    let source = "
+        .macro countdown %reg, %n
+            li %reg, %n
+        loop: addi %reg, %reg, -1
+            bnez %reg, loop
+        .end_macro
+        main: countdown $t1, 3
+        countdown $t2, 2
+        move $s0, $t1
+    ";
+    let program = program(source);
+    let names: Vec<(&str, u32)> = program
+        .symbols
+        .iter()
+        .map(|s| (s.name.as_str(), s.address))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("main", 0x0040_0000),
+            ("loop_M0", 0x0040_0004),
+            ("loop_M1", 0x0040_0010)
+        ]
+    );
+    let mut cpu = Cpu::new(&program);
+    let stop = cpu.run(Some(100), &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(cpu.regs[16], 0);
+}
+
+#[test]
+fn listing_shows_expansions() {
+    let source = format!("{}\nmain: print_int($t1)", PRINT);
+    let listing = program(&source).listing(&[&source]);
+    assert!(listing.contains(
+        "  11  0x00400000            main: print_int($t1)\n      0x00400000  00092021      addu $a0, $0, $t1\n"
+    ));
+}
+
+#[test]
+fn diagnostics_point_at_definition_and_call() {
+    let source = "
+.macro add_to(%x)
+    addi $t0, $t0, %x
+.end_macro
+.macro twice(%y)
+    add_to(%y)
+    add_to(%y)
+.end_macro
+    twice(nowhere)
+";
+    let diags = diagnostics(source);
+    assert_eq!(diags[0].message, "Label \"nowhere\" not found in addi.");
+    assert_eq!(diags[0].line, 3);
+    let notes: Vec<(u32, &str)> = diags[0]
+        .notes
+        .iter()
+        .map(|n| (n.line, n.message.as_str()))
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            (6, "In macro add_to, called here."),
+            (9, "In macro twice, called here.")
+        ]
+    );
+}
+
+#[test]
+fn macro_errors() {
+    assert_eq!(
+        error(&format!("{}\nprint_int($t0, $t1)", PRINT)),
+        "Macro print_int takes 0 or 1 arguments, not 2."
+    );
+    assert_eq!(
+        error(".macro m\n    m\n.end_macro\nm"),
+        "Macro m calls itself."
+    );
+    assert_eq!(error(".macro m(%a)\nnop"), "Macro m has no .end_macro.");
+    assert_eq!(error(".end_macro"), ".end_macro without .macro.");
+    assert_eq!(
+        error(".macro m(%a, %a)\n.end_macro"),
+        "Parameter %a of macro m appears twice."
+    );
+    assert_eq!(
+        error(".macro m(%a)\n.end_macro\n.macro m(%b)\n.end_macro"),
+        "Macro m with 1 parameters is already defined on line 1."
+    );
+}
@@ -0,0 +1,95 @@
+//! Memory maps, and the pseudo-op forms the compact ones allow.
+
+mod common;
+
+use assembler_rust::{Cpu, MemoryMap, Options, PRESETS};
+use common::{program_with, to_words};
+
+const LABELS: &str = "
+    .data
+    x: .word 5
+    y: .word 6
+    .text
+    la $t0, y
+    lw $t1, x
+    sw $t1, y($t0)
+    la $t3, y($t0)
+    j end
+    end: jr $ra
+";
+
+fn text(source: &str, memory: MemoryMap) -> Vec<u32> {
+    let options = Options {
+        memory,
+        ..Options::default()
+    };
+    to_words(&program_with(source, &options).text)
+}
+
+#[test]
+fn labels_through_at_by_default() {
+    assert!(!MemoryMap::mars_default().is_compact());
+    assert_eq!(
+        text(LABELS, MemoryMap::mars_default()),
+        [
+            0x3c01_1001,
+            0x3428_0004,
+            0x3c01_1001,
+            0x8c29_0000,
+            0x3c01_1001,
+            0x0028_0821,
+            0xac29_0004,
+            0x3c01_1001,
+            0x3421_0004,
+            0x0101_5820,
+            0x0810_000b,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn labels_from_zero_in_compact_maps() {
+    assert!(MemoryMap::compact_data_at_0().is_compact());
+    assert_eq!(
+        text(LABELS, MemoryMap::compact_data_at_0()),
+        [
+            0x2008_0004,
+            0x8c09_0000,
+            0xad09_0004,
+            0x210b_0004,
+            0x0800_0c05,
+            0x03e0_0008
+        ]
+    );
+    assert!(MemoryMap::compact_text_at_0().is_compact());
+    assert_eq!(
+        text(LABELS, MemoryMap::compact_text_at_0()),
+        [
+            0x2008_2004,
+            0x8c09_2000,
+            0xad09_2004,
+            0x210b_2004,
+            0x0800_0005,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn stack_pointer_of_each_preset() {
+    let expected = [0x7fff_effc, 0x2ffc, 0x3ffc];
+    for ((name, _), sp) in PRESETS.iter().zip(expected) {
+        let memory = MemoryMap::preset(name).unwrap();
+        assert_eq!(memory.sp, sp, "{}", name);
+        let options = Options {
+            memory,
+            ..Options::default()
+        };
+        let cpu = Cpu::new(&program_with("nop", &options));
+        assert_eq!(cpu.regs[29], sp, "{}", name);
+    }
+    // The stack grows down from just below the segment above it
+    let compact = MemoryMap::compact_data_at_0();
+    assert_eq!(compact.sp + 4, compact.text);
+}
@@ -0,0 +1,136 @@
+//! MIPS32 Release 2 instructions, checked against the encodings GNU as gives them.
+
+mod common;
+
+use assembler_rust::{Cpu, Stop};
+use common::{diagnostics, program, words};
+
+fn check(cases: &[(&str, u32)]) {
+    for (source, expected) in cases {
+        assert_eq!(
+            words(source),
+            [*expected],
+            "{} should be {:#010x}",
+            source,
+            expected
+        );
+    }
+}
+
+#[test]
+fn bit_fields() {
+    check(&[
+        ("ext $t0, $t1, 4, 8", 0x7d28_3900),
+        ("ins $t0, $t1, 4, 8", 0x7d28_5904),
+        ("ext $v0, $a0, 0, 32", 0x7c82_f800),
+        ("ins $v0, $a0, 31, 1", 0x7c82_ffc4),
+    ]);
+}
+
+#[test]
+fn byte_swaps_and_sign_extension() {
+    check(&[
+        ("wsbh $t0, $t1", 0x7c09_40a0),
+        ("seb $t0, $t1", 0x7c09_4420),
+        ("seh $t0, $t1", 0x7c09_4620),
+    ]);
+}
+
+#[test]
+fn rotates() {
+    check(&[
+        ("rotr $t0, $t1, 3", 0x0029_40c2),
+        ("rotrv $t0, $t1, $t2", 0x0149_4046),
+    ]);
+}
+
+#[test]
+fn count_leading_bits() {
+    check(&[("clz $t0, $t1", 0x7128_4020), ("clo $t0, $t1", 0x7128_4021)]);
+}
+
+#[test]
+fn multiply_accumulate() {
+    check(&[
+        ("madd $t0, $t1", 0x7109_0000),
+        ("maddu $t0, $t1", 0x7109_0001),
+        ("msub $t0, $t1", 0x7109_0004),
+        ("msubu $t0, $t1", 0x7109_0005),
+    ]);
+}
+
+#[test]
+fn conditional_moves() {
+    check(&[
+        ("movn $t0, $t1, $t2", 0x012a_400b),
+        ("movz $t0, $t1, $t2", 0x012a_400a),
+    ]);
+}
+
+#[test]
+fn traps() {
+    check(&[
+        ("tge $t0, $t1", 0x0109_0030),
+        ("tgeu $t0, $t1", 0x0109_0031),
+        ("tlt $t0, $t1", 0x0109_0032),
+        ("tltu $t0, $t1", 0x0109_0033),
+        ("teq $t0, $t1", 0x0109_0034),
+        ("tne $t0, $t1", 0x0109_0036),
+        ("tgei $t0, 5", 0x0508_0005),
+        ("tgeiu $t0, 5", 0x0509_0005),
+        ("tlti $t0, -1", 0x050a_ffff),
+        ("tltiu $t0, 5", 0x050b_0005),
+        ("teqi $t0, 5", 0x050c_0005),
+        ("tnei $t0, 5", 0x050e_0005),
+    ]);
+}
+
+#[test]
+fn sync() {
+    check(&[("sync", 0x0000_000f), ("sync 4", 0x0000_010f)]);
+}
+
+#[test]
+fn bit_field_must_fit() {
+    let diags = diagnostics("ext $t0, $t1, 30, 4");
+    assert!(diags[0].render("").contains("must fit in 32 bits"));
+}
+
+#[test]
+fn runs() {
+    let source = "
+        li $t1, 0x12345678
+        ext $s0, $t1, 8, 12
+        ins $t1, $zero, 4, 8
+        move $s1, $t1
+        wsbh $s2, $s1
+        li $t2, 0x80
+        seb $s3, $t2
+        rotr $s4, $t2, 4
+        clz $s5, $t2
+        li $t3, -1
+        clo $s6, $t3
+        mult $t2, $t2
+        madd $t3, $t2
+        movz $s7, $t2, $zero
+    ";
+    let mut cpu = Cpu::new(&program(source));
+    let stop = cpu.run(None, &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(cpu.regs[16], 0x456);
+    assert_eq!(cpu.regs[17], 0x1234_5008);
+    assert_eq!(cpu.regs[18], 0x3412_0850);
+    assert_eq!(cpu.regs[19], 0xffff_ff80);
+    assert_eq!(cpu.regs[20], 0x0000_0008);
+    assert_eq!(cpu.regs[21], 24);
+    assert_eq!(cpu.regs[22], 32);
+    assert_eq!((cpu.hi, cpu.lo), (0, 0x4000 - 0x80));
+    assert_eq!(cpu.regs[23], 0x80);
+}
+
+#[test]
+fn traps_stop_the_program() {
+    let mut cpu = Cpu::new(&program("li $t0, 3\nteqi $t0, 3"));
+    let stop = cpu.run(None, &mut std::io::empty(), &mut std::io::sink());
+    assert!(stop.is_err());
+}
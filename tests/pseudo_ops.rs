@@ -0,0 +1,134 @@
+//! Pseudo-op expansions, against the words MARS assembles them to.
+
+mod common;
+
+use assembler_rust::Options;
+use common::{program_with, words};
+
+#[test]
+fn load_immediate() {
+    let source = "
+        li $t0, 100
+        li $t0, -100
+        li $t0, 0x8000
+        li $t0, 0x12345
+        li $t0, -0x12345
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x2408_0064,
+            0x2408_ff9c,
+            0x3408_8000,
+            0x3c01_0001,
+            0x3428_2345,
+            0x3c01_fffe,
+            0x3428_dcbb
+        ]
+    );
+}
+
+#[test]
+fn load_address_and_moves() {
+    let source = "
+        la $t0, x
+        la $t1, 100($t2)
+        move $t0, $t1
+        not $t0, $t1
+        x: jr $ra
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x3c01_0040,
+            0x3428_0018,
+            0x3401_0064,
+            0x0141_4820,
+            0x0009_4021,
+            0x0120_4027,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn compare_and_branch() {
+    let source = "
+        blt $t0, $t1, x
+        bge $t0, -100, x
+        bgt $t0, 100000, x
+        ble $t0, $t1, x
+        bleu $t0, 7, x
+        x: jr $ra
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x0109_082a,
+            0x1420_000b,
+            0x2901_ff9c,
+            0x1020_0009,
+            0x3c01_0001,
+            0x3421_86a1,
+            0x0101_082a,
+            0x1020_0005,
+            0x0128_082a,
+            0x1020_0003,
+            0x2001_0007,
+            0x0028_082b,
+            0x1020_0000,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn multiply_and_divide() {
+    let source = "
+        mul $t0, $t1, 100
+        div $t0, $t1, 100000
+        div $t0, $t1, $t2
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x2001_0064,
+            0x7121_4002,
+            0x3c01_0001,
+            0x3421_86a0,
+            0x0121_001a,
+            0x0000_4012,
+            0x1540_0001,
+            0x0000_000d,
+            0x012a_001a,
+            0x0000_4012
+        ]
+    );
+}
+
+#[test]
+fn labels_after_pseudo_ops() {
+    // Pass 1 sizes each pseudo-op by the form its operands pick, and by its DBNOPs
+    let source = "
+        li $t0, 0x12345
+        blt $t0, 100000, x
+        div $t0, $t1, $t2
+        x: jr $ra
+    ";
+    let address = |options: &Options| {
+        let program = program_with(source, options);
+        program
+            .symbols
+            .iter()
+            .find(|s| s.name == "x")
+            .unwrap()
+            .address
+    };
+    assert_eq!(address(&Options::default()), 0x0040_0028);
+    let delayed = Options {
+        delayed_branches: true,
+        ..Options::default()
+    };
+    // A nop in the delay slots of blt and of the branch in div
+    assert_eq!(address(&delayed), 0x0040_0030);
+}
@@ -0,0 +1,82 @@
+//! Range checks on immediates, shift amounts, branch offsets and jump targets.
+
+mod common;
+
+use common::{error, words};
+
+#[test]
+fn edges_of_each_field() {
+    let source = "
+        addi $t0, $t0, -32768
+        slti $t0, $t0, 32767
+        ori $t0, $t0, 65535
+        lui $t0, 0xffff
+        sll $t0, $t0, 31
+        lw $t0, -32768($t1)
+        teqi $t0, -32768
+    ";
+    assert_eq!(
+        words(source),
+        [
+            0x2108_8000,
+            0x2908_7fff,
+            0x3508_ffff,
+            0x3c08_ffff,
+            0x0008_47c0,
+            0x8d28_8000,
+            0x050c_8000
+        ]
+    );
+    // %hi and %lo are halves, whatever the instruction
+    let source = "
+        ori $t0, $t0, %lo(x)
+        .data
+        .space 0x8000
+        x: .word 0
+    ";
+    assert_eq!(words(source), [0x3508_8000]);
+}
+
+#[test]
+fn immediates_out_of_range() {
+    assert_eq!(
+        error("sll $t0, $t0, 40"),
+        "Shift amount \"40\" not valid in sll, expected 0 to 31."
+    );
+    assert_eq!(
+        error("rotr $t0, $t0, -1"),
+        "Shift amount \"-1\" not valid in rotr, expected 0 to 31."
+    );
+    assert_eq!(
+        error("teqi $t0, x\n.data\nx: .word 0"),
+        "Immediate value \"x\" (268500992) not valid in teqi, expected -32768 to 32767."
+    );
+    assert_eq!(
+        error("lw $t0, %gp_rel(y)($gp)\n.data\n.space 0x10000\ny: .word 0"),
+        "Offset \"%gp_rel(y)\" (98304) not valid in lw, expected -32768 to 32767."
+    );
+}
+
+#[test]
+fn branches_and_jumps_out_of_range() {
+    assert_eq!(
+        error("bc1f 0x00500000"),
+        "Branch offset 262143 to 0x00500000 not valid in bc1f, expected -32768 to 32767 words."
+    );
+    assert_eq!(
+        error("bc1t 0x00300000"),
+        "Branch offset -262145 to 0x00300000 not valid in bc1t, expected -32768 to 32767 words."
+    );
+    assert_eq!(
+        error("bgez $t0, . + 6"),
+        "Branch target 0x00400006 not valid in bgez, it must be a multiple of 4."
+    );
+    assert_eq!(
+        error("j 0x10000000"),
+        "Jump target 0x10000000 not valid in j, expected a multiple of 4 from 0x00000000 to 0x0ffffffc."
+    );
+    assert_eq!(
+        error("jal 0x00400002"),
+        "Jump target 0x00400002 not valid in jal, expected a multiple of 4 from 0x00000000 to 0x0ffffffc."
+    );
+}
@@ -0,0 +1,75 @@
+//! Register names and the register classes operands take.
+
+mod common;
+
+use common::{error, words};
+
+#[test]
+fn numbers_and_aliases() {
+    let named = words("add $t0, $t1, $ra\nsw $fp, 4($sp)");
+    assert_eq!(words("add $8, $9, $31\nsw $30, 4($29)"), named);
+    assert_eq!(words("add $r8, $r9, $r31\nsw $s8, 4($r29)"), named);
+    assert_eq!(words("add $zero, $0, $r0"), [0x0000_0020]);
+}
+
+#[test]
+fn coprocessor_registers() {
+    let source = "
+        mfc0 $k0, $13
+        mtc1 $t0, $f4
+        mfc1 $r4, $f2
+        add.s $f0, $f2, $f31
+    ";
+    assert_eq!(
+        words(source),
+        [0x401a_6800, 0x4488_2000, 0x4404_1000, 0x461f_1000]
+    );
+    // The base of a coprocessor 1 load is a general purpose register
+    assert_eq!(words("lwc1 $f2, 8($r4)"), [0xc482_0008]);
+}
+
+#[test]
+fn wrong_classes() {
+    assert_eq!(
+        error("add $f0, $t1, $t2"),
+        "$f0 is a floating point register, but add takes a general purpose register here."
+    );
+    assert_eq!(
+        error("add.s $f0, $f1, $t2"),
+        "$t2 is a general purpose register, but add.s takes a floating point register here."
+    );
+    assert_eq!(
+        error("mfc0 $t0, $t1"),
+        "$t1 is a general purpose register, but mfc0 takes a coprocessor 0 register here."
+    );
+    assert_eq!(
+        error("lwc1 $t0, 0($t1)"),
+        "$t0 is a general purpose register, but lwc1 takes a floating point register here."
+    );
+    // Pseudo-ops too
+    assert_eq!(
+        error("li $f0, 5"),
+        "$f0 is a floating point register, but li takes a general purpose register here."
+    );
+    assert_eq!(
+        error("addi $t0, $r32, 1"),
+        "Register \"$r32\" not found in addi."
+    );
+}
+
+#[test]
+fn numbers_are_not_registers() {
+    assert_eq!(
+        error("sllv $t0, $t1, 100"),
+        "Register \"100\" out of range in sllv, expected 0 to 31."
+    );
+    assert_eq!(
+        error("jr 100"),
+        "Register \"100\" out of range in jr, expected 0 to 31."
+    );
+    assert_eq!(
+        error("mfhi 40"),
+        "Register \"40\" out of range in mfhi, expected 0 to 31."
+    );
+    assert_eq!(error("mfhi 3"), "mfhi needs a register here, not \"3\".");
+}
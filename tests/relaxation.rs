@@ -0,0 +1,94 @@
+//! Conditional branches too far from their targets, rewritten around a jump.
+
+mod common;
+
+use assembler_rust::Options;
+use common::{program_with, to_words};
+
+/// The words of `source` that are not padding.
+fn code(source: &str, options: &Options) -> Vec<u32> {
+    to_words(&program_with(source, options).text)
+        .into_iter()
+        .filter(|w| *w != 0)
+        .collect()
+}
+
+#[test]
+fn inverse_branch_over_a_jump() {
+    let source = "
+        beq $t0, $t1, far
+        bgez $t2, near
+        near: .space 0x20000
+        far: jr $ra
+    ";
+    let program = program_with(source, &Options::default());
+    assert_eq!(
+        to_words(&program.text)[..3],
+        [0x1509_0001, 0x0810_8003, 0x0541_0000]
+    );
+    assert!(program.listing(&[source]).contains(
+        "beq $t0, $t1, far\n      \
+         0x00400000  15090001      bne $t0, $t1, 0x00400008\n      \
+         0x00400004  08108003      j far\n"
+    ));
+}
+
+#[test]
+fn delay_slot_of_the_branch_goes_to_the_jump() {
+    let source = "
+        blez $t0, far
+        .set noreorder
+        bltz $t1, far
+        addi $t2, $t2, 1
+        .space 0x20000
+        far: jr $ra
+    ";
+    let options = Options {
+        delayed_branches: true,
+        ..Options::default()
+    };
+    // Each inverse branch lands on the delay slot of its jump, so the slot runs either way
+    assert_eq!(
+        code(source, &options),
+        [
+            0x1d00_0002,
+            0x0810_8008,
+            0x0521_0002,
+            0x0810_8008,
+            0x214a_0001,
+            0x03e0_0008
+        ]
+    );
+}
+
+#[test]
+fn jump_through_at_across_regions() {
+    assert_eq!(
+        code("bne $t0, $zero, 0x10408000", &Options::default()),
+        [0x1100_0003, 0x3c01_1041, 0x2421_8000, 0x0020_0008]
+    );
+}
+
+#[test]
+fn relaxing_one_branch_can_push_another_out_of_range() {
+    // bne reaches x at first, 32767 words ahead, until beq grows by a word
+    let source = "
+        bne $t0, $t1, x
+        beq $t2, $t3, far
+        .space 0x1fff8
+        x: jr $ra
+        .space 0x20000
+        far: jr $ra
+    ";
+    assert_eq!(
+        code(source, &Options::default()),
+        [
+            0x1109_0001,
+            0x0810_8002,
+            0x154b_0001,
+            0x0811_0003,
+            0x03e0_0008,
+            0x03e0_0008
+        ]
+    );
+}
@@ -0,0 +1,48 @@
+//! String literals in .ascii and .asciiz, and their escape sequences.
+
+mod common;
+
+use common::{error, program};
+
+fn data(source: &str) -> Vec<u8> {
+    program(&format!(".data\n{}", source)).data
+}
+
+#[test]
+fn simple_escapes() {
+    assert_eq!(data(r#".ascii "a\nb\tc\0d\\e\"f""#), b"a\nb\tc\0d\\e\"f");
+    assert_eq!(data(r#".ascii "\r\a\b\f\v\'""#), b"\r\x07\x08\x0c\x0b'");
+}
+
+#[test]
+fn octal_and_hex_escapes() {
+    assert_eq!(data(r#".ascii "\101\7\0778""#), b"A\x07\x3f8");
+    assert_eq!(data(r#".ascii "\x41\x7fg\x4""#), b"A\x7fg\x04");
+}
+
+#[test]
+fn asciiz_terminates_each_string() {
+    assert_eq!(data(r#".asciiz "hi", "a,b", """#), b"hi\0a,b\0\0");
+    assert_eq!(data(r#".ascii "hi", "a#b" # comment"#), b"hia#b");
+}
+
+#[test]
+fn invalid_strings() {
+    assert_eq!(
+        error(".data\n.ascii \"a\\q\""),
+        "Unknown escape sequence \"\\q\" in .ascii."
+    );
+    assert_eq!(
+        error(".data\n.asciiz \"\\400\""),
+        "Escape sequence \"\\400\" is out of range in .asciiz."
+    );
+    assert_eq!(
+        error(".data\n.asciiz \"abc"),
+        "Missing closing quote in .asciiz."
+    );
+    assert_eq!(error(".data\n.ascii"), "Expected a string in .ascii.");
+    assert_eq!(
+        error(".data\n.ascii \"a\" \"b\""),
+        "Expected ',' before \"\"b\"\" in .ascii."
+    );
+}
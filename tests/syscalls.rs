@@ -0,0 +1,224 @@
+//! Syscalls, run against input and output held in memory.
+
+mod common;
+
+use assembler_rust::{Cpu, Exception, Stop};
+use common::program;
+use std::io::Cursor;
+
+/// Runs `source` on `input`, returning how it stopped, what it printed, and the CPU.
+fn run(source: &str, input: &str) -> (Result<Stop, Exception>, String, Cpu) {
+    let mut cpu = Cpu::new(&program(source));
+    let mut output = Vec::new();
+    let stop = cpu.run(Some(1000), &mut Cursor::new(input), &mut output);
+    (stop, String::from_utf8(output).unwrap(), cpu)
+}
+
+#[test]
+fn prints() {
+    let source = r#"
+        .data
+        msg: .asciiz "x = "
+        .text
+        li $v0, 4
+        la $a0, msg
+        syscall
+        li $v0, 1
+        li $a0, -42
+        syscall
+        li $v0, 11
+        li $a0, '\n'
+        syscall
+        li $v0, 34
+        li $a0, 255
+        syscall
+    "#;
+    let (stop, output, _) = run(source, "");
+    assert_eq!(stop, Err(Exception::Syscall(34)));
+    assert_eq!(output, "x = -42\n");
+}
+
+#[test]
+fn reads_from_input() {
+    let source = "
+        .data
+        buf: .space 8
+        .text
+        li $v0, 5
+        syscall
+        move $s0, $v0
+        li $v0, 8
+        la $a0, buf
+        li $a1, 4
+        syscall
+        li $v0, 12
+        syscall
+        move $s1, $v0
+        li $v0, 17
+        li $a0, 3
+        syscall
+    ";
+    let (stop, _, cpu) = run(source, "  12\nhello\nz");
+    assert_eq!(stop, Ok(Stop::Exit(3)));
+    assert_eq!(cpu.regs[16], 12);
+    // Room for three characters and the NUL
+    assert_eq!(cpu.mem.load(0x1001_0000, 4), u32::from_be_bytes(*b"hel\0"));
+    assert_eq!(cpu.regs[17], b'z' as u32);
+}
+
+#[test]
+fn bad_input_is_an_exception() {
+    let (stop, _, _) = run("li $v0, 5\nsyscall", "twelve\n");
+    assert_eq!(stop, Err(Exception::BadSyscall(5)));
+    let (stop, _, _) = run("li $v0, 5\nsyscall", "");
+    assert_eq!(stop, Err(Exception::BadSyscall(5)));
+}
+
+#[test]
+fn read_and_write_descriptors() {
+    let source = "
+        .data
+        buf: .space 16
+        .text
+        li $v0, 14
+        li $a0, 0
+        la $a1, buf
+        li $a2, 16
+        syscall
+        move $s0, $v0
+        li $v0, 15
+        li $a0, 1
+        la $a1, buf
+        li $a2, 3
+        syscall
+        move $s1, $v0
+        li $v0, 14
+        li $a0, 0
+        li $a2, 16
+        syscall
+        move $s2, $v0
+        li $v0, 14
+        li $a0, 9
+        syscall
+        move $s3, $v0
+    ";
+    let (stop, output, cpu) = run(source, "abcdef");
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(output, "abc");
+    // Six bytes read, three written, then the end of input and a descriptor never opened
+    assert_eq!(cpu.regs[16..20], [6, 3, 0, u32::MAX]);
+}
+
+#[test]
+fn read_length_stops_at_the_top_of_memory() {
+    let source = "
+        li $v0, 14
+        li $a0, 0
+        li $a1, 0xfffffffc
+        li $a2, 0x7fffffff
+        syscall
+        move $s0, $v0
+        li $v0, 14
+        li $a2, -1
+        syscall
+        move $s1, $v0
+    ";
+    let (stop, _, cpu) = run(source, "abcdefgh");
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(cpu.regs[16], 4);
+    assert_eq!(cpu.mem.load(0xffff_fffc, 4), u32::from_be_bytes(*b"abcd"));
+    // A negative length fails
+    assert_eq!(cpu.regs[17], u32::MAX);
+}
+
+#[test]
+fn write_length_stops_at_the_top_of_memory() {
+    let source = "
+        .data
+        buf: .space 10000
+        .text
+        li $t0, 0x7778797a
+        li $a1, 0xfffffffc
+        sw $t0, 0($a1)
+        li $v0, 15
+        li $a0, 1
+        li $a2, 0x7fffffff
+        syscall
+        move $s0, $v0
+        li $v0, 15
+        la $a1, buf
+        li $a2, 10000
+        syscall
+        move $s1, $v0
+    ";
+    let (stop, output, cpu) = run(source, "");
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(cpu.regs[16..18], [4, 10000]);
+    assert_eq!(output.len(), 4 + 10000);
+    assert!(output.starts_with("wxyz\0"));
+}
+
+#[test]
+fn sbrk_and_exit() {
+    let source = "
+        li $v0, 9
+        li $a0, 5
+        syscall
+        move $s0, $v0
+        li $v0, 9
+        syscall
+        move $s1, $v0
+        li $v0, 10
+        syscall
+        li $s2, 1
+    ";
+    let (stop, _, cpu) = run(source, "");
+    assert_eq!(stop, Ok(Stop::Exit(0)));
+    // Whole words, from the start of the heap
+    assert_eq!(cpu.regs[16..19], [0x1004_0000, 0x1004_0008, 0]);
+}
+
+#[test]
+fn files() {
+    let path = std::env::temp_dir().join(format!("syscalls-{}.txt", std::process::id()));
+    let source = format!(
+        r#"
+        .data
+        buf: .space 8
+        name: .asciiz "{}"
+        text: .ascii "saved"
+        .text
+        li $v0, 13
+        la $a0, name
+        li $a1, 1
+        syscall
+        move $s0, $v0
+        li $v0, 15
+        move $a0, $s0
+        la $a1, text
+        li $a2, 5
+        syscall
+        li $v0, 16
+        syscall
+        li $v0, 13
+        li $a1, 0
+        la $a0, name
+        syscall
+        move $s1, $v0
+        li $v0, 14
+        move $a0, $s1
+        la $a1, buf
+        li $a2, 0x7fffffff
+        syscall
+        move $s2, $v0
+    "#,
+        path.display()
+    );
+    let (stop, _, cpu) = run(&source, "");
+    let saved = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(stop, Ok(Stop::DroppedOff));
+    assert_eq!(saved.unwrap(), b"saved");
+    assert_eq!(cpu.regs[16..19], [3, 3, 5]);
+    assert_eq!(cpu.mem.load(0x1001_0000, 4), u32::from_be_bytes(*b"save"));
+}